@@ -1,4 +1,4 @@
-#![allow(deprecated, dead_code)]
+#![allow(deprecated, dead_code, clippy::vec_init_then_push)]
 use criterion::{criterion_group, criterion_main, Criterion, Fun};
 use rand::distributions::{Standard};
 use rand::{thread_rng, Rng};
@@ -6,7 +6,9 @@ use rand::{thread_rng, Rng};
 
 use pzip::ptraversal::{single_neighbours_grouped_no_ring};
 use pzip::position::Position as Coordinate;
+#[cfg(feature = "nightly")]
 use pzip::traversal::{neighbours as neighboursfn, Traversal};
+#[cfg(feature = "nightly")]
 use pzip::gen::GeneratorIteratorAdapter;
 
 fn random_number_generator_f32(min: f32, max: f32, size: usize) -> Vec<f32> {
@@ -25,19 +27,23 @@ fn prepare_data_f32(shape: &Coordinate) -> Vec<f32> {
 }
 
 fn prepare_functions() -> Vec<Fun<(pzip::position::Position, Vec<pzip::position::Position>)>> {
-    let neighbours_grouped_no_ring = Fun::new("PA Grouped NO ring", |b, (shape, neighbours)| {
-        let data = prepare_data_f32(&shape);
-        b.iter(|| { let _: Vec<Vec<f32>> = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(&shape, &neighbours, &data)).collect(); ()});
+    let neighbours_grouped_no_ring = Fun::new("PA Grouped NO ring", |b, (shape, neighbours): &(Coordinate, Vec<Coordinate>)| {
+        let data = prepare_data_f32(shape);
+        b.iter(|| { let _: Vec<Vec<f32>> = single_neighbours_grouped_no_ring(shape, neighbours, &data).collect(); });
     });
-    let former_implementation = Fun::new("Normal NO ring", |b, (shape, neighbours)| {
+    #[allow(unused_mut)]
+    let mut funcs = vec![neighbours_grouped_no_ring];
+
+    #[cfg(feature = "nightly")]
+    funcs.push(Fun::new("Normal NO ring", |b, (shape, neighbours)| {
         let data = prepare_data_f32(&shape);
         b.iter(|| {
             let tr = Traversal::new(shape.x as usize, shape.y as usize, shape.z as usize);
             let _: Vec<Vec<f32>> = GeneratorIteratorAdapter(neighboursfn(tr, &data, &neighbours)).collect(); ()
         });
-    });
+    }));
 
-    vec![neighbours_grouped_no_ring, former_implementation]
+    funcs
 }
 
 //neighbourhood & size
@@ -25,6 +25,12 @@ pub struct PreviousError {
     parts: u32,  // absolute parts [default: 100]
 }
 
+impl Default for PreviousError {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PreviousError {
     pub fn new() -> Self {
         PreviousError {
@@ -85,6 +91,12 @@ pub struct DeltaToPowerOf2 {
     parts: u32,
 }
 
+impl Default for DeltaToPowerOf2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DeltaToPowerOf2 {
     pub fn new() -> Self {
         DeltaToPowerOf2 {
@@ -2,7 +2,6 @@
 use pzip::position::Position;
 use pzip::testing::{FileToBeCompressed, Source};
 use pzip::ptraversal::single_neighbours_grouped_no_ring;
-use pzip::gen::GeneratorIteratorAdapter;
 
 fn main() {
     let input = String::from("/home/ucyo/rust/pzip/data/emac.ml.tm1.f32.little.5x90x160x320_0.raw");
@@ -17,10 +16,10 @@ fn main() {
         { Position { z: 0, y: 1, x: 1 } },
     ];
 
-    let mut source: Source<f32> = Source::new(&input);
+    let mut source: Source<f32> = Source::new(&input).unwrap();
     let _nbytes = source.load().unwrap();
 
-    let values = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(&shape, &information, &source.data ));
+    let values = single_neighbours_grouped_no_ring(&shape, &information, &source.data);
     for environ in values {
         println!("{:?}", environ)
     }
@@ -0,0 +1,199 @@
+/// Several named arrays in a single file
+///
+/// Every variable is stored as a complete pzip container, such that it keeps
+/// its own type, shape and pipeline. The directory at the end of the file
+/// names the variables and locates their containers, which allows writing
+/// the variables one after another. All numbers are little endian.
+///
+/// | Field     | Size                       | Content                                                  |
+/// |-----------|----------------------------|----------------------------------------------------------|
+/// | magic     | 4                          | `PZMV`                                                   |
+/// | version   | 1                          | `VERSION`                                                |
+/// | payload   | n containers               | pzip containers of the variables                         |
+/// | directory | 4 + n x (2 + name + 8 + 8) | number of variables, (name length, name, offset, length) |
+/// | start     | 8                          | offset of the directory                                  |
+///
+/// Offsets are relative to the start of the file.
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+
+use super::codec::Value;
+use super::container::{self, Chunk, Header};
+use super::error::{Error, Result};
+use super::{compress, decompress, Options, Shape};
+
+pub const MAGIC: &[u8; 4] = b"PZMV";
+pub const VERSION: u8 = 1;
+
+/// Variable in the directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    /// Location of the container of the variable
+    pub chunk: Chunk,
+}
+
+/// Whether `input` is a file with several variables
+pub fn is_archive(input: &str) -> bool {
+    let mut magic = [0u8; 4];
+    fs::File::open(input).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && &magic == MAGIC
+}
+
+/// Writes variables one after another, the directory on `finish`.
+pub struct Writer<W: Write> {
+    w: W,
+    offset: u64,
+    directory: Vec<Entry>,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut w: W) -> Result<Self> {
+        w.write_all(MAGIC)?;
+        w.write_u8(VERSION)?;
+        Ok(Writer { w, offset: MAGIC.len() as u64 + 1, directory: Vec::new() })
+    }
+
+    /// Adds the pzip container of the variable `name`.
+    pub fn add(&mut self, name: &str, container: &[u8]) -> Result<()> {
+        if name.is_empty() || name.len() > u16::MAX as usize {
+            return Err(Error::InvalidInput(format!("Invalid variable name {:?}", name)));
+        }
+        if self.directory.iter().any(|e| e.name == name) {
+            return Err(Error::InvalidInput(format!("Variable {} exists already", name)));
+        }
+        self.w.write_all(container)?;
+        let chunk = Chunk { offset: self.offset, length: container.len() as u64 };
+        self.offset += chunk.length;
+        self.directory.push(Entry { name: name.to_string(), chunk });
+        Ok(())
+    }
+
+    /// Compresses `data` (row-major with `shape`) as the variable `name`.
+    pub fn add_values<T: Value>(&mut self, name: &str, data: &[T], shape: Shape, options: &Options) -> Result<()> {
+        let container = compress(data, shape, options)?;
+        self.add(name, &container)
+    }
+
+    /// Writes the directory and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.w.write_u32::<LittleEndian>(self.directory.len() as u32)?;
+        for entry in &self.directory {
+            self.w.write_u16::<LittleEndian>(entry.name.len() as u16)?;
+            self.w.write_all(entry.name.as_bytes())?;
+            self.w.write_u64::<LittleEndian>(entry.chunk.offset)?;
+            self.w.write_u64::<LittleEndian>(entry.chunk.length)?;
+        }
+        self.w.write_u64::<LittleEndian>(self.offset)?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+/// Reads variables by name.
+pub struct Archive<R> {
+    r: R,
+    pub directory: Vec<Entry>,
+}
+
+impl Archive<BufReader<fs::File>> {
+    pub fn open(input: &str) -> Result<Self> {
+        Archive::from_reader(BufReader::new(fs::File::open(input)?))
+    }
+}
+
+impl<R: Read + Seek> Archive<R> {
+    pub fn from_reader(mut r: R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(container::invalid("Not a pzip file with several variables"));
+        }
+        let version = r.read_u8()?;
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let end = r.seek(SeekFrom::End(-8))?;
+        let start = r.read_u64::<LittleEndian>()?;
+        if start > end {
+            return Err(container::invalid("Directory exceeds the end of the file"));
+        }
+        r.seek(SeekFrom::Start(start))?;
+        let n = r.read_u32::<LittleEndian>()?;
+        let mut directory = Vec::new();
+        for _ in 0..n {
+            let mut name = vec![0u8; r.read_u16::<LittleEndian>()? as usize];
+            r.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| container::invalid("Variable name is not UTF-8"))?;
+            let offset = r.read_u64::<LittleEndian>()?;
+            let length = r.read_u64::<LittleEndian>()?;
+            if offset.checked_add(length).is_none_or(|e| e > start) {
+                return Err(container::invalid(&format!("Variable {} exceeds the payload", name)));
+            }
+            directory.push(Entry { name, chunk: Chunk { offset, length } });
+        }
+        Ok(Archive { r, directory })
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.directory.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    fn entry(&self, name: &str) -> Result<Chunk> {
+        match self.directory.iter().find(|e| e.name == name) {
+            Some(entry) => Ok(entry.chunk),
+            None => Err(Error::InvalidInput(format!("No variable {}", name))),
+        }
+    }
+
+    /// The pzip container of the variable `name`
+    pub fn container(&mut self, name: &str) -> Result<Vec<u8>> {
+        let chunk = self.entry(name)?;
+        container::read_chunk(&mut self.r, 0, &chunk)
+    }
+
+    /// Header of the variable `name`, without reading its chunks
+    pub fn header(&mut self, name: &str) -> Result<Header> {
+        let chunk = self.entry(name)?;
+        self.r.seek(SeekFrom::Start(chunk.offset))?;
+        Header::read(&mut (&mut self.r).take(chunk.length))
+    }
+
+    /// Decompresses the variable `name`.
+    pub fn read<T: Value>(&mut self, name: &str) -> Result<(Vec<T>, Shape)> {
+        decompress(&self.container(name)?)
+    }
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::*;
+    use super::super::config::{FileType, Predictor};
+    use std::io::Cursor;
+
+    #[test]
+    fn variables_roundtrip() {
+        let qv: Vec<f32> = (0..2 * 3 * 4).map(|i| 0.001 * (i as f32).sqrt()).collect();
+        let tm1: Vec<f64> = (0..5 * 6).map(|i| 250.0 + f64::from(i) * 0.25).collect();
+        let lorenz = Options { predictor: Predictor::Lorenz, ..Options::default() };
+
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer.add_values("qv", &qv, Shape { z: 2, y: 3, x: 4 }, &lorenz).unwrap();
+        writer.add_values("tm1", &tm1, Shape { z: 1, y: 5, x: 6 }, &Options::default()).unwrap();
+        assert!(writer.add_values("qv", &qv, Shape { z: 2, y: 3, x: 4 }, &lorenz).is_err());
+        let bytes = writer.finish().unwrap();
+
+        let mut archive = Archive::from_reader(Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(archive.names(), vec!["qv", "tm1"]);
+        assert_eq!(archive.header("tm1").unwrap().filetype, FileType::F64);
+        assert_eq!(archive.header("qv").unwrap().cells.len(), 7);
+        assert_eq!(archive.read::<f32>("qv").unwrap(), (qv, Shape { z: 2, y: 3, x: 4 }));
+        assert_eq!(archive.read::<f64>("tm1").unwrap().0, tm1);
+        assert!(archive.read::<f32>("tm1").is_err());
+        assert!(archive.read::<f32>("u").is_err());
+
+        assert!(Archive::from_reader(Cursor::new(bytes[..bytes.len() - 3].to_vec())).is_err());
+        let empty = Writer::new(Vec::new()).unwrap().finish().unwrap();
+        assert!(Archive::from_reader(Cursor::new(empty)).unwrap().directory.is_empty());
+    }
+}
@@ -0,0 +1,352 @@
+/// Compression of whole directories
+///
+/// Type, byte order and shape of every file are taken from a sidecar
+/// (`FILE.toml` or `FILE.json`, written like a configuration file) or from
+/// the name of the file, e.g. `icon.ml.qv.f32.little.4x90x351x901_0.raw`.
+/// NumPy files are described by their header. A sidecar without byte order (or with `endian = "auto"`) has it detected
+/// from the data.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::bench::aligned;
+use super::codec::{self, Value};
+use super::config::{parse_shape, Config, FileType, Settings};
+use super::endian::Endian;
+use super::error::{Error, Result};
+use super::npy;
+use super::position::Position;
+use super::Setup;
+
+/// What is known about a file before reading it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Description {
+    pub filetype: FileType,
+    /// Byte order, detected from the data if `None`
+    pub endian: Option<Endian>,
+    pub shape: Position,
+}
+
+/// Parses a name like `icon.ml.qv.f32.little.4x90x351x901_0.raw`.
+///
+/// The last three dimensions are z, y and x. A suffix `_k` marks the file as
+/// the k-th step of the leading dimension, which is then not part of the
+/// file. Remaining leading dimensions are folded into z.
+pub fn parse_name(name: &str) -> Option<Description> {
+    let mut filetype = None;
+    let mut endian = None;
+    let mut shape = None;
+    for part in name.split('.') {
+        match part {
+            "f32" => filetype = Some(FileType::F32),
+            "f64" => filetype = Some(FileType::F64),
+            "little" => endian = Some(Endian::Little),
+            "big" => endian = Some(Endian::Big),
+            "mixed" => endian = Some(Endian::Mixed),
+            _ => shape = dimensions(part).or(shape),
+        }
+    }
+    Some(Description { filetype: filetype?, endian: Some(endian?), shape: shape? })
+}
+
+fn dimensions(part: &str) -> Option<Position> {
+    let (dims, step) = match part.find('_') {
+        Some(pos) => (&part[..pos], Some(&part[pos + 1..])),
+        None => (part, None),
+    };
+    let mut dims = dims.split('x').map(|d| d.parse::<i32>().ok().filter(|&d| d > 0)).collect::<Option<Vec<i32>>>()?;
+    if let Some(step) = step {
+        step.parse::<u32>().ok()?;
+        if dims.len() < 2 {
+            return None;
+        }
+        dims.remove(0);
+    }
+    if dims.len() < 2 {
+        return None;
+    }
+    while dims.len() < 3 {
+        dims.insert(0, 1);
+    }
+    let split = dims.len() - 2;
+    let z = dims[..split].iter().try_fold(1i32, |acc, &d| acc.checked_mul(d))?;
+    Some(Position { x: dims[split + 1], y: dims[split], z })
+}
+
+fn sidecar(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    ["toml", "json"]
+        .iter()
+        .map(|ext| path.with_file_name(format!("{}.{}", name, ext)))
+        .find(|p| p.is_file())
+}
+
+fn unknown(what: &str) -> Error {
+    Error::InvalidInput(format!("{} unknown, no sidecar and no matching name", what))
+}
+
+/// Description of a file, values of the sidecar take precedence over the name.
+pub fn describe(path: &Path) -> Result<Description> {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    if npy::is_npy(name) {
+        let header = npy::header(&path.to_string_lossy())?;
+        let shape = header.position()?;
+        return Ok(Description { filetype: header.filetype, endian: Some(header.endian), shape });
+    }
+    let named = parse_name(name);
+    let settings = match sidecar(path) {
+        Some(p) => Settings::load(&p.to_string_lossy())?,
+        None => Settings::default(),
+    };
+    let filetype = match settings.filetype.as_deref() {
+        Some("f32") => FileType::F32,
+        Some("f64") => FileType::F64,
+        Some(v) => return Err(Error::InvalidInput(format!("Invalid type '{}'", v))),
+        None => named.ok_or_else(|| unknown("Type"))?.filetype,
+    };
+    let endian = match settings.endian.as_deref() {
+        Some("little") => Some(Endian::Little),
+        Some("big") => Some(Endian::Big),
+        Some("mixed") => Some(Endian::Mixed),
+        Some("auto") => None,
+        Some(v) => return Err(Error::InvalidInput(format!("Invalid endian '{}'", v))),
+        None => named.and_then(|d| d.endian),
+    };
+    let shape = match settings.shape {
+        Some(shape) => parse_shape(&shape)?,
+        None => named.ok_or_else(|| unknown("Shape"))?.shape,
+    };
+    Ok(Description { filetype, endian, shape })
+}
+
+/// Result of a single file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Compressed { raw: u64, compressed: u64, seconds: f64 },
+    /// Type or shape of the file are unknown
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Path relative to the input directory
+    pub file: PathBuf,
+    pub description: Option<Description>,
+    pub outcome: Outcome,
+}
+
+/// All files below `dir` in sorted order, without sidecars and pzip files.
+fn files(dir: &Path, skip: &Path, result: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?.map(|e| e.map(|e| e.path())).collect::<io::Result<_>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            if path.canonicalize()? != skip {
+                files(&path, skip, result)?;
+            }
+            continue;
+        }
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let sidecar = [".toml", ".json"].iter().any(|ext| name.ends_with(ext));
+        if !(sidecar || name.starts_with('.') || name.ends_with(".pzip")) {
+            result.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Compresses every file below `input` into the same location below `output`,
+/// each like `pzip -c` with the settings of `config`.
+pub fn compress_dir(input: &Path, output: &Path, config: &Config) -> Result<Vec<Entry>> {
+    fs::create_dir_all(output)?;
+    let mut paths = Vec::new();
+    files(input, &output.canonicalize()?, &mut paths)?;
+
+    let mut entries = Vec::new();
+    for path in paths {
+        let file = path.strip_prefix(input).unwrap_or(&path).to_path_buf();
+        let description = match describe(&path) {
+            Ok(d) => d,
+            Err(e) => {
+                entries.push(Entry { file, description: None, outcome: Outcome::Skipped(e.to_string()) });
+                continue;
+            }
+        };
+        let target = output.join(format!("{}.pzip", file.to_string_lossy()));
+        let start = Instant::now();
+        let result = match description.filetype {
+            FileType::F32 => compress_file::<f32>(&path, &target, &description, config),
+            FileType::F64 => compress_file::<f64>(&path, &target, &description, config),
+        };
+        let outcome = match result.and_then(|raw| Ok((raw, fs::metadata(&target)?.len()))) {
+            Ok((raw, compressed)) => Outcome::Compressed { raw, compressed, seconds: start.elapsed().as_secs_f64() },
+            Err(e) => Outcome::Failed(e.to_string()),
+        };
+        entries.push(Entry { file, description: Some(description), outcome });
+    }
+    Ok(entries)
+}
+
+/// Compresses a single file and returns the number of raw bytes.
+fn compress_file<T: Value>(input: &Path, output: &Path, description: &Description, config: &Config) -> Result<u64> {
+    let shape = &description.shape;
+    let expected = codec::size(shape)? as u64 * T::BYTES as u64;
+    let (name, target) = (input.to_string_lossy(), output.to_string_lossy());
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if npy::is_npy(&name) {
+        Setup::npy(&name, config.predictor.get::<T>())?.compress_file(config, &name, &target)?;
+        return Ok(expected);
+    }
+    let size = fs::metadata(input)?.len();
+    if size != expected && !config.partial {
+        return Err(Error::SizeMismatch(format!("{} bytes do not match the shape {:?}", size, shape)));
+    }
+    let mut setup = Setup::mmap(&name, *shape, config.predictor.get::<T>())?;
+    setup.set_endian(description.endian);
+    setup.compress_file(config, &name, &target)?;
+    Ok(size)
+}
+
+const COLUMNS: [&str; 8] = ["file", "type", "shape", "raw", "compressed", "ratio", "MB/s", "status"];
+
+fn fields(entry: &Entry) -> Vec<String> {
+    let (filetype, shape) = match entry.description {
+        Some(d) => (format!("{:?}", d.filetype), format!("{}x{}x{}", d.shape.z, d.shape.y, d.shape.x)),
+        None => ("-".to_string(), "-".to_string()),
+    };
+    let (raw, compressed, ratio, speed, status) = match &entry.outcome {
+        Outcome::Compressed { raw, compressed, seconds } => (
+            raw.to_string(),
+            compressed.to_string(),
+            format!("{:.3}", *raw as f64 / (*compressed).max(1) as f64),
+            format!("{:.1}", *raw as f64 / 1e6 / seconds.max(1e-9)),
+            "ok".to_string(),
+        ),
+        Outcome::Skipped(e) => ("-".into(), "-".into(), "-".into(), "-".into(), format!("skipped: {}", e)),
+        Outcome::Failed(e) => ("-".into(), "-".into(), "-".into(), "-".into(), format!("failed: {}", e)),
+    };
+    vec![entry.file.to_string_lossy().into_owned(), filetype, shape, raw, compressed, ratio, speed, status]
+}
+
+/// Aligned table of the entries followed by the totals
+pub fn report(entries: &[Entry]) -> String {
+    let mut result = aligned(&COLUMNS, entries.iter().map(fields).collect());
+    let (mut raw, mut compressed, mut ok) = (0, 0, 0);
+    for entry in entries {
+        if let Outcome::Compressed { raw: r, compressed: c, .. } = entry.outcome {
+            raw += r;
+            compressed += c;
+            ok += 1;
+        }
+    }
+    let failed = entries.iter().filter(|e| matches!(e.outcome, Outcome::Failed(_))).count();
+    result += &format!(
+        "\n{} compressed, {} skipped, {} failed, {} -> {} bytes (ratio {:.3})\n",
+        ok,
+        entries.len() - ok - failed,
+        failed,
+        raw,
+        compressed,
+        raw as f64 / compressed.max(1) as f64
+    );
+    result
+}
+
+/// Entries as comma separated values with a header line
+pub fn csv(entries: &[Entry]) -> String {
+    let mut result = COLUMNS.join(",") + "\n";
+    for entry in entries {
+        let row: Vec<String> = fields(entry).into_iter().map(|f| f.replace(',', ";")).collect();
+        result += &(row.join(",") + "\n");
+    }
+    result
+}
+
+#[allow(unused_imports, dead_code)]
+mod tests {
+    use super::*;
+    use super::super::config::parse_args;
+
+    fn write_values(path: &Path, n: usize) {
+        let data: Vec<u8> = (0..n).flat_map(|i| (i as f32 * 0.1).sin().to_le_bytes().to_vec()).collect();
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn names_of_the_data_sets() {
+        let d = parse_name("icon.ml.qv.f32.little.4x90x351x901_0.raw").unwrap();
+        assert_eq!(d.filetype, FileType::F32);
+        assert_eq!(d.endian, Some(Endian::Little));
+        assert_eq!(d.shape, Position { x: 901, y: 351, z: 90 });
+        let d = parse_name("emac.ml.tm1.f64.big.5x90x160x320.raw").unwrap();
+        assert_eq!((d.filetype, d.endian), (FileType::F64, Some(Endian::Big)));
+        assert_eq!(d.shape, Position { x: 320, y: 160, z: 450 });
+        let d = parse_name("surface.f32.little.351x901.raw").unwrap();
+        assert_eq!(d.shape, Position { x: 901, y: 351, z: 1 });
+
+        assert_eq!(parse_name("icon.ml.qv.little.4x90x351x901_0.raw"), None);
+        assert_eq!(parse_name("icon.f32.little.raw"), None);
+        assert_eq!(parse_name("icon.f32.little.901_0.raw"), None);
+        assert_eq!(parse_name("icon.f32.little.0x90x10.raw"), None);
+    }
+
+    #[test]
+    fn sidecar_takes_precedence() {
+        let dir = &std::env::temp_dir().join(format!("pzip_batch_sidecar_{}", std::process::id()));
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join("field.f32.little.2x3x4.raw");
+        fs::write(dir.join("field.f32.little.2x3x4.raw.toml"), "shape = '1x6x4'\n").unwrap();
+        let d = describe(&path).unwrap();
+        assert_eq!((d.filetype, d.shape), (FileType::F32, Position { x: 4, y: 6, z: 1 }));
+        assert!(describe(&dir.join("unknown.raw")).is_err());
+        fs::write(dir.join("unknown.raw.json"), r#"{"type": "f64", "shape": "2x2x2"}"#).unwrap();
+        let d = describe(&dir.join("unknown.raw")).unwrap();
+        assert_eq!((d.filetype, d.endian), (FileType::F64, None));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compress_directory() {
+        let dir = &std::env::temp_dir().join(format!("pzip_batch_input_{}", std::process::id()));
+        let out = dir.join("out");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        write_values(&dir.join("a.f32.little.2x2x3x4_1.raw"), 24);
+        write_values(&dir.join("sub/b.f32.little.3x5.raw"), 15);
+        write_values(&dir.join("c.f32.little.3x5.raw"), 14);
+        write_values(&dir.join("notes.raw"), 1);
+        let big: Vec<f32> = (0..15).map(|i| 280.0 + i as f32).collect();
+        fs::write(dir.join("d.f32.big.3x5.raw"), super::super::endian::bytes(&big, Endian::Big)).unwrap();
+
+        let args: Vec<String> = vec!["pzip", "batch", "-p", "lorenz", "-a", "source=test", "in", "out"].into_iter().map(String::from).collect();
+        let config = parse_args(&args).unwrap();
+        let entries = compress_dir(dir, &out, &config).unwrap();
+        let names: Vec<String> = entries.iter().map(|e| e.file.to_string_lossy().into_owned()).collect();
+        assert_eq!(names, vec!["a.f32.little.2x2x3x4_1.raw", "c.f32.little.3x5.raw", "d.f32.big.3x5.raw", "notes.raw", "sub/b.f32.little.3x5.raw"]);
+        assert!(matches!(entries[0].outcome, Outcome::Compressed { raw: 96, .. }));
+        assert!(matches!(entries[1].outcome, Outcome::Failed(_)));
+        assert!(matches!(entries[2].outcome, Outcome::Compressed { raw: 60, .. }));
+        assert!(matches!(entries[3].outcome, Outcome::Skipped(_)));
+        assert!(matches!(entries[4].outcome, Outcome::Compressed { raw: 60, .. }));
+
+        let (values, shape) = super::super::decompress_file::<f32>(&out.join("sub/b.f32.little.3x5.raw.pzip").to_string_lossy()).unwrap();
+        assert_eq!((values.len(), shape), (15, Position { x: 5, y: 3, z: 1 }));
+        let (values, _) = super::super::decompress_file::<f32>(&out.join("d.f32.big.3x5.raw.pzip").to_string_lossy()).unwrap();
+        assert_eq!(values, big);
+        let (header, _) = super::super::container::read(&out.join("d.f32.big.3x5.raw.pzip").to_string_lossy()).unwrap();
+        assert_eq!(header.attributes["filename"], "d.f32.big.3x5.raw");
+        assert_eq!(header.attributes["source"], "test");
+        let text = report(&entries);
+        assert!(text.contains("3 compressed, 1 skipped, 1 failed, 216 -> "));
+        assert_eq!(csv(&entries).lines().count(), 6);
+
+        // a second run does not pick up its own output
+        assert_eq!(compress_dir(dir, &out, &config).unwrap().len(), 5);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+/// Sweeps over pipeline combinations
+///
+/// Every combination of Inter x Intra x Byte x Compact x predictor x ring is
+/// used to encode the same data. The size of the result (optionally after a
+/// backend coder) and the throughput of the encoding are reported.
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io::Write;
+use std::time::Instant;
+
+use super::codec::{self, Value};
+use super::error::Result;
+use super::position::Position;
+use super::predictors::Ignorant;
+use super::transform::{Byte, Compact, Inter, Intra};
+use super::Pipeline;
+
+/// Backend coder applied to the encoded chunks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coder {
+    None,
+    Deflate,
+}
+
+impl Coder {
+    pub fn code(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Coder::None => Ok(data.to_vec()),
+            Coder::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+}
+
+/// Result of a single combination
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub predictor: String,
+    pub pipeline: Pipeline,
+    pub coder: Coder,
+    /// Size after encoding (and the backend coder) in bytes
+    pub bytes: usize,
+    pub bits_per_value: f64,
+    /// Encoded megabytes (10^6) of raw data per second
+    pub throughput: f64,
+}
+
+/// All pipelines of the sweep, `Compact::NoLZC` only for 4-byte values.
+pub fn pipelines(value_bytes: usize) -> Vec<Pipeline> {
+    let compacts: &[Compact] = if value_bytes == 4 {
+        &[Compact::Untouched, Compact::NoLZC]
+    } else {
+        &[Compact::Untouched]
+    };
+    let mut result = Vec::new();
+    for &inter in &[Inter::Untouched, Inter::Ordered] {
+        for &intra in &[Intra::Untouched, Intra::Gray] {
+            for &byte in &[Byte::Untouched, Byte::MonoGray] {
+                for &compact in compacts {
+                    for &ring in &[false, true] {
+                        result.push(Pipeline { inter, intra, byte, compact, ring, ..Pipeline::default() });
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Encodes `data` with every pipeline and predictor.
+pub fn sweep<T: Value>(
+    data: &[T],
+    shape: &Position,
+    predictors: &[(String, Ignorant<T>)],
+    coder: Coder,
+) -> Result<Vec<Run>> {
+    let raw = (data.len() * T::BYTES) as f64;
+    let mut runs = Vec::new();
+    for (name, predictor) in predictors {
+        for pipeline in pipelines(T::BYTES) {
+            let mut predictor = predictor.clone();
+            let start = Instant::now();
+            let chunks = codec::encode_chunks(data, shape, &mut predictor, &pipeline)?;
+            let coded = coder.code(&chunks.concat())?;
+            let seconds = start.elapsed().as_secs_f64();
+            runs.push(Run {
+                predictor: name.clone(),
+                pipeline,
+                coder,
+                bytes: coded.len(),
+                bits_per_value: coded.len() as f64 * 8.0 / data.len().max(1) as f64,
+                throughput: raw / 1e6 / seconds.max(1e-9),
+            });
+        }
+    }
+    Ok(runs)
+}
+
+const COLUMNS: [&str; 10] = [
+    "predictor", "inter", "intra", "byte", "compact", "ring", "coder", "bytes", "bits/value", "MB/s",
+];
+
+fn fields(run: &Run) -> Vec<String> {
+    let p = &run.pipeline;
+    vec![
+        run.predictor.clone(),
+        format!("{:?}", p.inter),
+        format!("{:?}", p.intra),
+        format!("{:?}", p.byte),
+        format!("{:?}", p.compact),
+        p.ring.to_string(),
+        format!("{:?}", run.coder),
+        run.bytes.to_string(),
+        format!("{:.3}", run.bits_per_value),
+        format!("{:.1}", run.throughput),
+    ]
+}
+
+/// Aligned table of the runs
+pub fn table(runs: &[Run]) -> String {
+    aligned(&COLUMNS, runs.iter().map(fields).collect())
+}
+
+/// Right aligned columns with a header line
+pub(crate) fn aligned(columns: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<usize> = columns.iter().map(|c| c.len()).collect();
+    for row in rows.iter() {
+        for (w, field) in widths.iter_mut().zip(row.iter()) {
+            *w = (*w).max(field.len());
+        }
+    }
+    let line = |row: Vec<String>| {
+        let cells: Vec<String> = row.iter().zip(widths.iter()).map(|(f, w)| format!("{:>1$}", f, w)).collect();
+        cells.join("  ") + "\n"
+    };
+    let mut result = line(columns.iter().map(|c| c.to_string()).collect());
+    for row in rows {
+        result += &line(row);
+    }
+    result
+}
+
+/// Runs as comma separated values with a header line
+pub fn csv(runs: &[Run]) -> String {
+    let mut result = COLUMNS.join(",") + "\n";
+    for run in runs {
+        result += &(fields(run).join(",") + "\n");
+    }
+    result
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::*;
+    use super::super::predictors::predictors;
+
+    #[test]
+    fn number_of_pipelines() {
+        assert_eq!(pipelines(4).len(), 32);
+        assert_eq!(pipelines(8).len(), 16);
+    }
+
+    #[test]
+    fn sweep_all_combinations() {
+        let shape = Position { x: 16, y: 8, z: 2 };
+        let data: Vec<f32> = (0..256).map(|i| (i as f32 * 0.05).sin()).collect();
+        let predictors = vec![
+            ("lv".to_string(), predictors::get_last_value_f32()),
+            ("lorenz".to_string(), predictors::get_lorenz_f32()),
+        ];
+        let runs = sweep(&data, &shape, &predictors, Coder::Deflate).unwrap();
+        assert_eq!(runs.len(), 64);
+        assert!(runs.iter().all(|r| r.bytes > 0 && r.bits_per_value < 64.0));
+
+        let csv = csv(&runs);
+        assert_eq!(csv.lines().count(), 65);
+        assert!(csv.starts_with("predictor,inter,intra,byte,compact,ring,coder,bytes,bits/value,MB/s\n"));
+        assert!(csv.lines().nth(1).unwrap().starts_with("lv,Untouched,Untouched,Untouched,Untouched,false,Deflate,"));
+        assert_eq!(table(&runs).lines().count(), 65);
+    }
+
+    #[test]
+    fn untouched_coder() {
+        let shape = Position { x: 4, y: 1, z: 1 };
+        let predictors = vec![("lv".to_string(), predictors::get_last_value_f64())];
+        let runs = sweep(&[1.0f64, 2.0, 3.0, 4.0], &shape, &predictors, Coder::None).unwrap();
+        assert!(runs.iter().all(|r| r.bytes == 32 && r.bits_per_value == 64.0));
+    }
+}
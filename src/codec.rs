@@ -0,0 +1,485 @@
+/// Encoding and decoding of the payload
+///
+/// Every value is predicted from its (already known) neighbours. The
+/// prediction and the true value are mapped to integers (Inter, Intra) and
+/// the residual is the XOR of both. The residuals are written as little
+/// endian words, optionally compacted (Compact) and mapped byte-wise (Byte).
+/// The decoder repeats the same prediction on the already decoded values.
+use byteorder::{ByteOrder, LittleEndian};
+use twox_hash::XxHash64;
+use std::hash::Hasher;
+use std::iter::Sum;
+use std::ops::{AddAssign, Mul};
+
+use super::config::FileType;
+use super::container::invalid;
+use super::error::{Error, Result};
+use super::position::Position;
+use super::predictors::{Ignorant, PredictorTrait};
+use super::ptraversal::NeighbourIndices;
+use super::tiling::{self, Tile};
+use super::transform::{ByteMapping, CompactMapping, InterMapping, IntraMapping};
+use super::transform::{Compact, Inter, Intra};
+use super::Pipeline;
+
+/// Floating point types which can be encoded.
+pub trait Value: Copy + Default + Mul<Output = Self> + AddAssign + Sum {
+    const FILETYPE: FileType;
+    const BYTES: usize;
+    fn to_word(self, h: &Inter, k: &Intra) -> u64;
+    fn from_word(word: u64, h: &Inter, k: &Intra) -> Self;
+    fn to_raw(self) -> u64;
+    fn from_raw(raw: u64) -> Self;
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+impl Value for f32 {
+    const FILETYPE: FileType = FileType::F32;
+    const BYTES: usize = 4;
+    fn to_word(self, h: &Inter, k: &Intra) -> u64 {
+        u64::from(k.to_new_u32(h.to_u32(self)))
+    }
+    fn from_word(word: u64, h: &Inter, k: &Intra) -> Self {
+        h.from_u32(k.from_new_u32(word as u32))
+    }
+    fn to_raw(self) -> u64 {
+        u64::from(self.to_bits())
+    }
+    fn from_raw(raw: u64) -> Self {
+        f32::from_bits(raw as u32)
+    }
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
+
+impl Value for f64 {
+    const FILETYPE: FileType = FileType::F64;
+    const BYTES: usize = 8;
+    fn to_word(self, h: &Inter, k: &Intra) -> u64 {
+        k.to_new_u64(h.to_u64(self))
+    }
+    fn from_word(word: u64, h: &Inter, k: &Intra) -> Self {
+        h.from_u64(k.from_new_u64(word))
+    }
+    fn to_raw(self) -> u64 {
+        self.to_bits()
+    }
+    fn from_raw(raw: u64) -> Self {
+        f64::from_bits(raw)
+    }
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+/// Checks that every cell points to an earlier value of the data.
+pub fn validate_cells(cells: &[Position]) -> Result<()> {
+    for c in cells {
+        if c.x < 0 || c.y < 0 || c.z < 0 || (c.x == 0 && c.y == 0 && c.z == 0) {
+            return Err(Error::InvalidInput(format!(
+                "Neighbour {:?} does not precede the predicted value",
+                c
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// xxHash64 of the values as raw little endian bytes, independent of the
+/// byte order of the input.
+pub fn checksum<T: Value>(data: &[T]) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+    let mut buf = vec![0u8; 1024 * T::BYTES];
+    for block in data.chunks(1024) {
+        for (value, bytes) in block.iter().zip(buf.chunks_mut(T::BYTES)) {
+            LittleEndian::write_uint(bytes, value.to_raw(), T::BYTES);
+        }
+        hasher.write(&buf[..block.len() * T::BYTES]);
+    }
+    hasher.finish()
+}
+
+/// Number of values of the grid with `shape`.
+pub fn size(shape: &Position) -> Result<usize> {
+    if shape.x < 0 || shape.y < 0 || shape.z < 0 {
+        return Err(Error::BadShape(format!("Negative extent in {:?}", shape)));
+    }
+    (shape.x as usize)
+        .checked_mul(shape.y as usize)
+        .and_then(|n| n.checked_mul(shape.z as usize))
+        .ok_or_else(|| Error::BadShape(format!("Too many values in {:?}", shape)))
+}
+
+/// Grid of an array with dimensions `dims` (slowest varying first). The last
+/// three dimensions are z, y and x, further leading dimensions are folded
+/// into z.
+pub fn fold(dims: &[usize]) -> Result<Position> {
+    if dims.contains(&0) {
+        return Err(Error::BadShape(format!("Empty array of shape {:?} can not be compressed", dims)));
+    }
+    let too_large = || Error::BadShape(format!("Shape {:?} is too large", dims));
+    let mut dims = dims.to_vec();
+    while dims.len() < 3 {
+        dims.insert(0, 1);
+    }
+    let split = dims.len() - 2;
+    let z = dims[..split].iter().try_fold(1usize, |acc, &d| acc.checked_mul(d)).ok_or_else(too_large)?;
+    let extent = |d: usize| if d > i32::MAX as usize { Err(too_large()) } else { Ok(d as i32) };
+    Ok(Position { x: extent(dims[split + 1])?, y: extent(dims[split])?, z: extent(z)? })
+}
+
+/// Checks that `len` values fill the grid of `shape`.
+pub fn check_size(len: usize, shape: &Position) -> Result<()> {
+    if size(shape)? != len {
+        return Err(Error::SizeMismatch(format!("{} values do not match the shape {:?}", len, shape)));
+    }
+    Ok(())
+}
+
+/// Splits `len` values into complete records of `shape`, stacked along z,
+/// and a trailing partial record. Returns the grid of the complete records
+/// and the number of trailing values.
+pub fn records(len: usize, shape: &Position) -> Result<(Position, usize)> {
+    let size = size(shape)?;
+    if size == 0 || len < size {
+        return Err(Error::SizeMismatch(format!(
+            "{} values do not fill a single record of the shape {:?}",
+            len, shape
+        )));
+    }
+    let z = (len / size)
+        .checked_mul(shape.z as usize)
+        .filter(|&z| z <= i32::MAX as usize)
+        .ok_or_else(|| Error::BadShape(format!("Too many records of the shape {:?}", shape)))?;
+    Ok((Position { z: z as i32, ..*shape }, len % size))
+}
+
+/// Shape and pipeline of the values of a trailing partial record, which are
+/// encoded in row-major order as a single row.
+fn trailing(n: usize, pipeline: &Pipeline) -> Result<(Position, Pipeline)> {
+    if n > i32::MAX as usize {
+        return Err(Error::BadShape(format!("Too many trailing values ({})", n)));
+    }
+    let pipeline = Pipeline { order: Default::default(), tile: None, ..*pipeline };
+    Ok((Position { x: n as i32, y: 1, z: 1 }, pipeline))
+}
+
+/// Checks that payloads encoded with `pipeline` can be decoded again. NoLZC
+/// compaction is only used to measure sizes in `bench`.
+pub fn check_decodable(pipeline: &Pipeline) -> Result<()> {
+    if pipeline.compact != Compact::Untouched {
+        return Err(Error::InvalidInput("Compacted payloads can not be decoded, use no compaction".to_string()));
+    }
+    Ok(())
+}
+
+/// Encodes `data` (stored in row-major order with `shape`) into the payload.
+pub fn encode<T: Value>(
+    data: &[T],
+    shape: &Position,
+    predictor: &mut Ignorant<T>,
+    pipeline: &Pipeline,
+) -> Result<Vec<u8>> {
+    validate_cells(&predictor.cells)?;
+    check_size(data.len(), shape)?;
+    let scanned_shape = pipeline.order.scanned_shape(shape)?;
+    let scanned = pipeline.order.gather(data, shape);
+    let (h, k) = (&pipeline.inter, &pipeline.intra);
+
+    let predictions = predictor.consume(&scanned, &scanned_shape, pipeline.ring);
+    let residuals: Vec<u64> = predictions
+        .iter()
+        .zip(scanned.iter())
+        .map(|(p, v)| p.to_word(h, k) ^ v.to_word(h, k))
+        .collect();
+    let residuals = match pipeline.compact {
+        Compact::Untouched => residuals,
+        Compact::NoLZC if T::BYTES == 4 => {
+            let words = residuals.iter().map(|&r| r as u32).collect();
+            pipeline.compact.compact_u32(words).iter().map(|&r| u64::from(r)).collect()
+        }
+        Compact::NoLZC => {
+            return Err(Error::InvalidInput("NoLZC compaction is only available for f32".to_string()))
+        }
+    };
+
+    let mut payload = vec![0u8; residuals.len() * T::BYTES];
+    for (chunk, r) in payload.chunks_mut(T::BYTES).zip(residuals.iter()) {
+        LittleEndian::write_uint(chunk, *r, T::BYTES);
+    }
+    Ok(payload.iter().map(|b| pipeline.byte.to_u8(*b)).collect())
+}
+
+/// Decodes the payload into data stored in row-major order with `shape`.
+pub fn decode<T: Value>(
+    payload: &[u8],
+    shape: &Position,
+    predictor: &mut Ignorant<T>,
+    pipeline: &Pipeline,
+) -> Result<Vec<T>> {
+    validate_cells(&predictor.cells)?;
+    check_decodable(pipeline)?;
+    let size = size(shape)?;
+    if Some(payload.len()) != size.checked_mul(T::BYTES) {
+        return Err(invalid(&format!(
+            "Payload has {} bytes, expected {} values of {} bytes",
+            payload.len(),
+            size,
+            T::BYTES
+        )));
+    }
+    let bytes: Vec<u8> = payload.iter().map(|b| pipeline.byte.from_u8(*b)).collect();
+    let scanned_shape = pipeline.order.scanned_shape(shape)?;
+    let (h, k) = (&pipeline.inter, &pipeline.intra);
+
+    let cells = predictor.cells.clone();
+    let mut neighbours: Vec<NeighbourIndices> = cells
+        .iter()
+        .map(|c| NeighbourIndices::new(&scanned_shape, c, size, pipeline.ring))
+        .collect();
+    let mut scanned: Vec<T> = Vec::with_capacity(size);
+    let mut space: Vec<T> = Vec::with_capacity(cells.len());
+    for chunk in bytes.chunks(T::BYTES) {
+        space.clear();
+        for iter in neighbours.iter_mut() {
+            let index = iter.next().unwrap_or(None);
+            space.push(index.map(|i| scanned[i]).unwrap_or_default());
+        }
+        let prediction = predictor.predict(&space);
+        let residual = LittleEndian::read_uint(chunk, T::BYTES);
+        let value = T::from_word(residual ^ prediction.to_word(h, k), h, k);
+        predictor.update(value);
+        scanned.push(value);
+    }
+    Ok(pipeline.order.scatter(&scanned, shape))
+}
+
+/// Tiles of the data, a single tile covering everything if the pipeline is
+/// not tiled.
+pub fn layout(shape: &Position, pipeline: &Pipeline) -> Vec<Tile> {
+    let tile = pipeline.tile.unwrap_or(Position { x: 0, y: 0, z: 0 });
+    tiling::tiles(shape, &tile)
+}
+
+/// Encodes every tile of `data` independently into its own chunk.
+pub fn encode_chunks<T: Value>(
+    data: &[T],
+    shape: &Position,
+    predictor: &mut Ignorant<T>,
+    pipeline: &Pipeline,
+) -> Result<Vec<Vec<u8>>> {
+    check_size(data.len(), shape)?;
+    layout(shape, pipeline)
+        .iter()
+        .map(|tile| {
+            let values = tiling::extract(data, shape, tile);
+            encode(&values, &tile.shape, predictor, pipeline)
+        })
+        .collect()
+}
+
+/// Decodes the chunks of all tiles into data stored in row-major order with `shape`.
+pub fn decode_chunks<T: Value>(
+    chunks: &[Vec<u8>],
+    shape: &Position,
+    predictor: &mut Ignorant<T>,
+    pipeline: &Pipeline,
+) -> Result<Vec<T>> {
+    let tiles = layout(shape, pipeline);
+    if tiles.len() != chunks.len() {
+        return Err(invalid(&format!(
+            "Found {} chunks, expected {} tiles",
+            chunks.len(),
+            tiles.len()
+        )));
+    }
+    let size = size(shape)?;
+    let bytes: usize = chunks.iter().map(Vec::len).sum();
+    if pipeline.compact == Compact::Untouched && Some(bytes) != size.checked_mul(T::BYTES) {
+        return Err(invalid(&format!("Chunks have {} bytes, expected {} values", bytes, size)));
+    }
+    let mut data = vec![T::default(); size];
+    for (tile, chunk) in tiles.iter().zip(chunks.iter()) {
+        let values = decode(chunk, &tile.shape, predictor, pipeline)?;
+        tiling::insert(&mut data, shape, tile, &values);
+    }
+    Ok(data)
+}
+
+/// Encodes the complete records of `shape` in `data` like `encode_chunks`
+/// and a trailing partial record into an additional last chunk. Returns the
+/// grid of the complete records, the number of trailing values and the chunks.
+pub fn encode_records<T: Value>(
+    data: &[T],
+    shape: &Position,
+    predictor: &mut Ignorant<T>,
+    pipeline: &Pipeline,
+) -> Result<(Position, usize, Vec<Vec<u8>>)> {
+    let (grid, n) = records(data.len(), shape)?;
+    let split = data.len() - n;
+    let mut chunks = encode_chunks(&data[..split], &grid, predictor, pipeline)?;
+    if n > 0 {
+        let (shape, pipeline) = trailing(n, pipeline)?;
+        chunks.push(encode(&data[split..], &shape, predictor, &pipeline)?);
+    }
+    Ok((grid, n, chunks))
+}
+
+/// Decodes the chunks written by `encode_records`.
+pub fn decode_records<T: Value>(
+    chunks: &[Vec<u8>],
+    grid: &Position,
+    n: usize,
+    predictor: &mut Ignorant<T>,
+    pipeline: &Pipeline,
+) -> Result<Vec<T>> {
+    if n == 0 {
+        return decode_chunks(chunks, grid, predictor, pipeline);
+    }
+    let (last, chunks) = chunks.split_last().ok_or_else(|| invalid("Missing chunk of the trailing values"))?;
+    let mut data = decode_chunks(chunks, grid, predictor, pipeline)?;
+    let (shape, pipeline) = trailing(n, pipeline)?;
+    data.extend(decode(last, &shape, predictor, &pipeline)?);
+    Ok(data)
+}
+
+#[allow(unused_imports, dead_code)]
+mod tests {
+    use super::*;
+    use super::super::order::{Axis, Order};
+    use super::super::predictors::predictors;
+    use super::super::transform::Byte;
+
+    fn field(shape: &Position) -> Vec<f32> {
+        let mut data = Vec::new();
+        for z in 0..shape.z {
+            for y in 0..shape.y {
+                for x in 0..shape.x {
+                    data.push((x as f32 * 0.3).sin() + (y as f32 * 0.2).cos() * z as f32);
+                }
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn roundtrip_all_orders() {
+        let shape = Position { x: 7, y: 5, z: 3 };
+        let data = field(&shape);
+        let orders = vec![
+            Order::default(),
+            Order::Linear { axes: [Axis::X, Axis::Z, Axis::Y], reversed: [true, false, true] },
+            Order::Serpentine,
+            Order::Morton,
+            Order::Hilbert,
+        ];
+        for order in orders {
+            for &ring in [false, true].iter() {
+                let pipeline = Pipeline {
+                    inter: Inter::Ordered,
+                    intra: Intra::Gray,
+                    byte: Byte::MonoGray,
+                    compact: Compact::Untouched,
+                    ring,
+                    order,
+                    tile: None,
+                };
+                let payload = encode(&data, &shape, &mut predictors::get_lorenz_f32(), &pipeline).unwrap();
+                let result = decode(&payload, &shape, &mut predictors::get_lorenz_f32(), &pipeline).unwrap();
+                assert_eq!(result, data);
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip_f64() {
+        let shape = Position { x: 4, y: 3, z: 2 };
+        let data: Vec<f64> = (0..24).map(|v| (v as f64).sqrt()).collect();
+        let pipeline = Pipeline::default();
+        let payload = encode(&data, &shape, &mut predictors::get_last_value_f64(), &pipeline).unwrap();
+        assert_eq!(payload.len(), 24 * 8);
+        let result = decode(&payload, &shape, &mut predictors::get_last_value_f64(), &pipeline).unwrap();
+        assert_eq!(result, data);
+    }
+
+    #[test]
+    fn tiles_are_independent() {
+        let shape = Position { x: 9, y: 6, z: 4 };
+        let data = field(&shape);
+        let pipeline = Pipeline {
+            tile: Some(Position { x: 4, y: 4, z: 2 }),
+            order: Order::Hilbert,
+            ..Pipeline::default()
+        };
+        let chunks = encode_chunks(&data, &shape, &mut predictors::get_lorenz_f32(), &pipeline).unwrap();
+        assert_eq!(chunks.len(), 3 * 2 * 2);
+
+        // every chunk can be decoded on its own
+        let tiles = layout(&shape, &pipeline);
+        let last = decode(&chunks[11], &tiles[11].shape, &mut predictors::get_lorenz_f32(), &pipeline).unwrap();
+        assert_eq!(last, tiling::extract(&data, &shape, &tiles[11]));
+
+        let result = decode_chunks(&chunks, &shape, &mut predictors::get_lorenz_f32(), &pipeline).unwrap();
+        assert_eq!(result, data);
+        assert!(decode_chunks(&chunks[1..], &shape, &mut predictors::get_lorenz_f32(), &pipeline).is_err());
+    }
+
+    #[test]
+    fn scan_order_changes_residuals() {
+        // smooth along y, noisy along x
+        let shape = Position { x: 8, y: 8, z: 1 };
+        let data: Vec<f32> = (0..64).map(|i| if i % 2 == 0 { 1.0 } else { 1000.0 }).collect();
+        let zeros = |order: Order| {
+            let pipeline = Pipeline { order, ..Pipeline::default() };
+            let payload = encode(&data, &shape, &mut predictors::get_last_value_f32(), &pipeline).unwrap();
+            payload.iter().filter(|&&b| b == 0).count()
+        };
+        let transposed = Order::Linear { axes: [Axis::Z, Axis::X, Axis::Y], reversed: [false; 3] };
+        assert!(zeros(transposed) > zeros(Order::default()));
+    }
+
+    #[test]
+    fn reject_self_reference() {
+        let shape = Position { x: 2, y: 1, z: 1 };
+        let mut predictor = Ignorant { coeff: vec![1.0f32], cells: vec![Position { x: 0, y: 0, z: 0 }] };
+        assert!(encode(&[1.0, 2.0], &shape, &mut predictor, &Pipeline::default()).is_err());
+    }
+
+    #[test]
+    fn reject_wrong_shapes() {
+        let data = field(&Position { x: 4, y: 3, z: 2 });
+        let mut predictor = predictors::get_lorenz_f32();
+        let pipeline = Pipeline { tile: Some(Position { x: 2, y: 2, z: 2 }), ..Pipeline::default() };
+        for shape in &[Position { x: 4, y: 3, z: 3 }, Position { x: 5, y: 3, z: 2 }, Position { x: 4, y: 2, z: 2 }] {
+            let result = encode_chunks(&data, shape, &mut predictor, &pipeline);
+            assert!(matches!(result, Err(Error::SizeMismatch(_))), "{:?}", shape);
+        }
+        let negative = Position { x: -4, y: 3, z: -2 };
+        assert!(matches!(encode_chunks(&data, &negative, &mut predictor, &pipeline), Err(Error::BadShape(_))));
+        let huge = Position { x: i32::MAX, y: i32::MAX, z: i32::MAX };
+        assert!(decode::<f32>(&[0u8; 8], &huge, &mut predictor, &Pipeline::default()).is_err());
+    }
+
+    #[test]
+    fn trailing_partial_record() {
+        let shape = Position { x: 4, y: 3, z: 2 };
+        assert_eq!(records(24, &shape).unwrap(), (shape, 0));
+        assert_eq!(records(59, &shape).unwrap(), (Position { x: 4, y: 3, z: 4 }, 11));
+        assert!(records(23, &shape).is_err());
+
+        let data = field(&Position { x: 59, y: 1, z: 1 });
+        let pipeline = Pipeline { order: Order::Morton, tile: Some(Position { x: 2, y: 2, z: 2 }), ..Pipeline::default() };
+        let (grid, n, chunks) = encode_records(&data, &shape, &mut predictors::get_lorenz_f32(), &pipeline).unwrap();
+        assert_eq!((grid.z, n, chunks.len()), (4, 11, 2 * 2 * 2 + 1));
+        let result = decode_records(&chunks, &grid, n, &mut predictors::get_lorenz_f32(), &pipeline).unwrap();
+        assert_eq!(result, data);
+    }
+}
@@ -1,28 +1,79 @@
-use super::Position;
-use std::collections::HashMap;
+use super::bench::Coder;
+use super::codec::Value;
+use super::error::{Error, Result};
+use super::npy;
+use super::order::{Axis, Order};
+use super::predictors::{predictors, Ignorant};
+use super::tiling::Tile;
+use super::transform::{Byte, Compact, Inter, Intra};
+use super::{Pipeline, Position};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// INPUT or OUTPUT reading from stdin or writing to stdout
+pub const STDIO: &str = "-";
 
 #[derive(Debug, PartialEq)]
 pub enum CodingMode {
     Encode,
     Decode,
+    Bench,
+    Verify,
+    Batch,
+    NetCdf,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FileType {
     F32,
     F64,
 }
 
+pub use super::endian::Endian;
+
 #[derive(Debug, PartialEq)]
 pub enum MapType {
     Raw,
     Ordered,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Predictor {
     LastValue,
     Lorenz,
+    Custom { cells: Vec<Position>, coeff: Vec<f64> },
+}
+
+impl Predictor {
+    pub fn get_f32(&self) -> Ignorant<f32> {
+        match self {
+            Predictor::LastValue => predictors::get_last_value_f32(),
+            Predictor::Lorenz => predictors::get_lorenz_f32(),
+            Predictor::Custom { cells, coeff } => Ignorant {
+                coeff: coeff.iter().map(|&c| c as f32).collect(),
+                cells: cells.clone(),
+            },
+        }
+    }
+    pub fn get_f64(&self) -> Ignorant<f64> {
+        match self {
+            Predictor::LastValue => predictors::get_last_value_f64(),
+            Predictor::Lorenz => predictors::get_lorenz_f64(),
+            Predictor::Custom { cells, coeff } => Ignorant {
+                coeff: coeff.clone(),
+                cells: cells.clone(),
+            },
+        }
+    }
+    /// Predictor for either type of values
+    pub fn get<T: Value>(&self) -> Ignorant<T> {
+        let predictor = self.get_f64();
+        Ignorant {
+            coeff: predictor.coeff.iter().map(|&c| T::from_f64(c)).collect(),
+            cells: predictor.cells,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -46,145 +97,582 @@ pub enum CompactType {
 #[derive(Debug)]
 pub struct Config<'a> {
     pub input: &'a String,
-    pub output: &'a String,
+    /// Not used by `CodingMode::Bench` and `CodingMode::Verify`
+    pub output: Option<&'a String>,
     pub coding: CodingMode,
     pub filetype: FileType,
+    /// Byte order of the input, detected from the data if `None`
+    pub endian: Option<Endian>,
     pub shape: Position,
     pub predictor: Predictor,
     pub mapping: MapType,
     pub bytemapping: ByteMappingType,
     pub intramapping: IntramappingType,
     pub compact: CompactType,
+    pub ring: bool,
+    /// Order in which the values are scanned
+    pub order: Order,
+    /// Shape of independently encoded tiles
+    pub tile: Option<Position>,
+    /// Box decoded by `CodingMode::Decode`, everything if `None`
+    pub region: Option<Tile>,
+    /// Accept several records of the shape and a trailing partial record
+    pub partial: bool,
+    /// Backend coder of `CodingMode::Bench`
+    pub coder: Coder,
+    /// Report of `CodingMode::Bench` as CSV instead of a table
+    pub csv: bool,
+    /// Attributes stored with the compressed data
+    pub attributes: BTreeMap<String, String>,
 }
 
-pub fn parse_args<'a>(args: &'a Vec<String>) -> Config {
-    let mut cli = HashMap::new();
-    cli.insert("coding", 1);
-    cli.insert("filetype", 2);
-    cli.insert("input", 3);
-    cli.insert("output", 4);
-    cli.insert("z", 6);
-    cli.insert("y", 7);
-    cli.insert("x", 8);
-    cli.insert("predictor", 10);
-    cli.insert("mapping", 12);
-    cli.insert("bytemapping", 14);
-    cli.insert("intramapping", 16);
-    cli.insert("compact", 17);
-
-    let coding = if args[cli["coding"]] == "-c" {
-        CodingMode::Encode
-    } else if args[cli["coding"]] == "-d" {
-        CodingMode::Decode
-    } else {
-        panic!("Wrong coding mode")
-    };
+impl<'a> Config<'a> {
+    /// Transformations selected by the configuration
+    pub fn pipeline(&self) -> Pipeline {
+        Pipeline {
+            inter: match self.mapping {
+                MapType::Raw => Inter::Untouched,
+                MapType::Ordered => Inter::Ordered,
+            },
+            intra: match self.intramapping {
+                IntramappingType::Untouched => Intra::Untouched,
+                IntramappingType::ClassicGrayCodes => Intra::Gray,
+            },
+            byte: match self.bytemapping {
+                ByteMappingType::Untouched => Byte::Untouched,
+                ByteMappingType::MonotonicGrayCodes => Byte::MonoGray,
+            },
+            compact: match self.compact {
+                CompactType::Untouched => Compact::Untouched,
+                CompactType::NoLZC => Compact::NoLZC,
+            },
+            ring: self.ring,
+            order: self.order,
+            tile: self.tile,
+        }
+    }
+}
 
-    let filetype = if args[cli["filetype"]] == "-f32" {
-        FileType::F32
-    } else if args[cli["filetype"]] == "-f64" {
-        FileType::F64
-    } else {
-        panic!("Wrong filetype")
-    };
+pub const USAGE: &str = "\
+Usage: pzip (-c | -d) [OPTIONS] INPUT OUTPUT
+       pzip info FILE...
+       pzip bench [OPTIONS] INPUT
+       pzip verify [OPTIONS] INPUT
+       pzip batch [OPTIONS] DIR OUTDIR
+       pzip netcdf [OPTIONS] FILE OUTDIR
 
-    let (input, output) = (&args[cli["input"]], &args[cli["output"]]);
+Modes:
+    -c, --compress            compress INPUT into OUTPUT
+    -d, --decompress          decompress INPUT into OUTPUT
+    info                      describe compressed files without decompressing them
+    bench                     compress INPUT with every combination of predictor,
+                              mappings, compaction and ring and report the sizes
+    verify                    compress and decompress INPUT in memory and compare
+                              the values bit by bit
+    batch                     compress every file below DIR into OUTDIR like -c,
+                              type and shape are taken from a sidecar (FILE.toml
+                              or FILE.json) or the name, e.g.
+                              icon.ml.qv.f32.little.4x90x351x901_0.raw
+    netcdf                    compress every float and double variable of the
+                              NetCDF classic FILE into OUTDIR/VARIABLE.pzip, or
+                              into the single file OUTDIR if it ends with .pzip
 
-    assert_eq!(args[5], "-s");
+Type, byte order and shape of NumPy input (.npy, .npz with a single array)
+are read from its header. Decompression into OUTPUT ending with .npy or .npz
+writes a NumPy file with the dimensions of the original array.
+Decompressing a file with several variables writes OUTPUT/VARIABLE.raw.
+INPUT and OUTPUT of -c and -d may be -, which reads from stdin or writes
+to stdout, e.g. `cat data.raw | pzip -c -s 90x351x901 - - > data.pzip`.
+Pipes are processed value by value, unless --order, --tile, --partial or
+another byte order than little need all values in memory.
 
-    let shape = Position {
-        z: args[cli["z"]].parse::<i32>().unwrap_or(1),
-        x: args[cli["x"]].parse::<i32>().unwrap_or(1),
-        y: args[cli["y"]].parse::<i32>().unwrap_or(1),
-    };
+Options:
+    -t, --type f32|f64        type of the values, -d reads it from INPUT
+                              [default: f32]
+    -e, --endian little|big|mixed|auto
+                              byte order of INPUT, auto detects it from the
+                              exponents; -d restores the order of the original
+                              input [default: little]
+    -s, --shape ZxYxX         shape of the data, e.g. 90x351x901 (required for -c)
+    -p, --predictor lv|lorenz predictor [default: lv]
+    -m, --inter raw|ordered   mapping of floats to integers [default: raw]
+    --intra untouched|gray    mapping of the integers [default: untouched]
+    --byte untouched|mono     mapping of the bytes [default: untouched]
+    --compact untouched|nolzc compaction of the residuals [default: untouched],
+                              nolzc can not be decoded and is refused by -c
+    --ring                    wrap neighbours in x-direction around
+    --order ORDER             scan order, serpentine, morton, hilbert or the axes
+                              from the outermost to the innermost loop, - scans
+                              an axis backwards, e.g. y,-x,z [default: z,y,x]
+    --tile ZxYxX              encode tiles of the shape independently, 0 leaves
+                              an axis untiled, e.g. 0x64x64
+    --region ZxYxX:ZxYxX      decode only the box at the offset with the shape,
+                              e.g. 10x0x0:1x351x901 (-d from a file)
+    --partial                 INPUT holds records of the shape (stacked along z)
+                              and possibly a trailing partial record
+    -a, --attribute KEY=VALUE store an attribute with the data, e.g. units=K;
+                              may be repeated, the name of INPUT is stored as
+                              filename
+    --config FILE             read the settings from a TOML or JSON (*.json) file
+    --preset NAME             use the settings of a preset (see below)
+    --coder none|deflate      backend coder used by bench [default: none]
+    --csv                     report of bench as CSV
+    -h, --help                print this help
 
-    assert_eq!(args[9], "-p");
+Options on the command line take precedence over --config, which takes
+precedence over --preset.
 
-    let predictor = if args[cli["predictor"]] == "lv" {
-        Predictor::LastValue
-    } else if args[cli["predictor"]] == "lorenz" {
-        Predictor::Lorenz
-    } else {
-        panic!("Wrong predictor, {}", args[cli["predictor"]])
-    };
+Presets:
+    climate-f32-fast          last value, ordered, gray and mono mapping (f32)
+    climate-f32               lorenz, ordered, gray and mono mapping (f32)
+    climate-f64               lorenz, ordered, gray and mono mapping (f64)
+";
 
-    let mapping = if args[cli["mapping"]] == "raw" {
-        MapType::Raw
-    } else if args[cli["mapping"]] == "ordered" {
-        MapType::Ordered
-    } else {
-        panic!("Wrong mapping type, {}", args[cli["mapping"]])
-    };
+/// Named settings, written in the same format as configuration files
+pub const PRESETS: &[(&str, &str)] = &[
+    (
+        "climate-f32-fast",
+        "type = 'f32'\npredictor = 'lv'\ninter = 'ordered'\nintra = 'gray'\nbyte = 'mono'\n",
+    ),
+    (
+        "climate-f32",
+        "type = 'f32'\npredictor = 'lorenz'\ninter = 'ordered'\nintra = 'gray'\nbyte = 'mono'\n",
+    ),
+    (
+        "climate-f64",
+        "type = 'f64'\npredictor = 'lorenz'\ninter = 'ordered'\nintra = 'gray'\nbyte = 'mono'\n",
+    ),
+];
 
-    let bytemapping = if args[cli["bytemapping"]] == "mono" {
-        ByteMappingType::MonotonicGrayCodes
-    } else if args[cli["bytemapping"]] == "untouched" {
-        ByteMappingType::Untouched
-    } else {
-        panic!("Wrong bytemapping type {}", args[cli["bytemapping"]])
-    };
+/// Settings of a pipeline as given by the command line, a configuration
+/// file or a preset. Unset values are taken from the next source.
+///
+/// ```toml
+/// type = "f32"
+/// endian = "little"
+/// shape = "90x351x901"
+/// inter = "ordered"
+/// intra = "gray"
+/// byte = "mono"
+/// compact = "untouched"
+/// ring = false
+/// order = "z,y,x"
+/// tile = "0x64x64"
+/// # decompress only a box, given as offset:shape
+/// region = "10x0x0:1x351x901"
+/// partial = false
+/// # either a named predictor or a list of cells
+/// predictor = "lorenz"
+/// # [[cells]]
+/// # z = 0
+/// # y = 0
+/// # x = 1
+/// # coeff = 1.0
+///
+/// # stored with the compressed data
+/// [attributes]
+/// units = "kg kg-1"
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(rename = "type")]
+    pub filetype: Option<String>,
+    pub endian: Option<String>,
+    pub shape: Option<String>,
+    pub predictor: Option<String>,
+    pub cells: Option<Vec<Cell>>,
+    pub inter: Option<String>,
+    pub intra: Option<String>,
+    pub byte: Option<String>,
+    pub compact: Option<String>,
+    pub ring: Option<bool>,
+    pub order: Option<String>,
+    pub tile: Option<String>,
+    pub region: Option<String>,
+    pub partial: Option<bool>,
+    pub attributes: Option<BTreeMap<String, String>>,
+}
 
-    let intramapping = if args[cli["intramapping"]] == "gray" {
-        IntramappingType::ClassicGrayCodes
-    } else if args[cli["intramapping"]] == "untouched" {
-        IntramappingType::Untouched
-    } else {
-        panic!("Wrong intramapping type {}", args[cli["intramapping"]])
-    };
+/// Neighbour of a custom predictor
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Cell {
+    pub z: i32,
+    pub y: i32,
+    pub x: i32,
+    pub coeff: f64,
+}
+
+impl Settings {
+    pub fn from_toml(text: &str) -> Result<Settings> {
+        toml::from_str(text).map_err(|e| Error::InvalidInput(format!("Invalid configuration: {}", e)))
+    }
+
+    pub fn from_json(text: &str) -> Result<Settings> {
+        serde_json::from_str(text).map_err(|e| Error::InvalidInput(format!("Invalid configuration: {}", e)))
+    }
+
+    /// Reads a configuration file, JSON if it ends with `.json` and TOML otherwise.
+    pub fn load(path: &str) -> Result<Settings> {
+        let text = fs::read_to_string(path).map_err(|e| Error::InvalidInput(format!("Can not read {}: {}", path, e)))?;
+        if path.ends_with(".json") {
+            Settings::from_json(&text)
+        } else {
+            Settings::from_toml(&text)
+        }
+    }
+
+    pub fn preset(name: &str) -> Result<Settings> {
+        match PRESETS.iter().find(|(n, _)| *n == name) {
+            Some((_, text)) => Settings::from_toml(text),
+            None => {
+                let names: Vec<&str> = PRESETS.iter().map(|(n, _)| *n).collect();
+                Err(Error::InvalidInput(format!("Unknown preset '{}', expected one of: {}", name, names.join(", "))))
+            }
+        }
+    }
+
+    /// Values of `self`, missing ones taken from `other`.
+    pub fn or(self, other: Settings) -> Settings {
+        let (predictor, cells) = if self.predictor.is_some() || self.cells.is_some() {
+            (self.predictor, self.cells)
+        } else {
+            (other.predictor, other.cells)
+        };
+        Settings {
+            filetype: self.filetype.or(other.filetype),
+            endian: self.endian.or(other.endian),
+            shape: self.shape.or(other.shape),
+            predictor,
+            cells,
+            inter: self.inter.or(other.inter),
+            intra: self.intra.or(other.intra),
+            byte: self.byte.or(other.byte),
+            compact: self.compact.or(other.compact),
+            ring: self.ring.or(other.ring),
+            order: self.order.or(other.order),
+            tile: self.tile.or(other.tile),
+            region: self.region.or(other.region),
+            partial: self.partial.or(other.partial),
+            attributes: match (self.attributes, other.attributes) {
+                (Some(mut a), Some(b)) => {
+                    for (key, value) in b {
+                        a.entry(key).or_insert(value);
+                    }
+                    Some(a)
+                }
+                (a, b) => a.or(b),
+            },
+        }
+    }
+}
 
-    let compact = if args[cli["compact"]] == "-c" {
-        CompactType::NoLZC
-    } else if args[cli["compact"]] == "-u" {
-        CompactType::NoLZC
-    } else {
-        panic!("Wrong coding mode")
+/// Checks if the help has been requested.
+pub fn help_requested(args: &[String]) -> bool {
+    args.iter().skip(1).any(|a| a == "-h" || a == "--help")
+}
+
+/// Parses a shape given as `ZxYxX`.
+pub fn parse_shape(value: &str) -> Result<Position> {
+    parse_extents("shape", value, 1)
+}
+
+/// Parses the shape of tiles given as `ZxYxX`, extents of 0 leave an axis untiled.
+pub fn parse_tile(value: &str) -> Result<Option<Position>> {
+    let tile = parse_extents("tile", value, 0)?;
+    if tile == (Position { x: 0, y: 0, z: 0 }) {
+        return Ok(None);
+    }
+    Ok(Some(tile))
+}
+
+/// Parses a box given as `ZxYxX:ZxYxX`, the offset of its first value and its shape.
+pub fn parse_region(value: &str) -> Result<Tile> {
+    match value.find(':') {
+        Some(pos) => Ok(Tile {
+            offset: parse_extents("offset", &value[..pos], 0)?,
+            shape: parse_shape(&value[pos + 1..])?,
+        }),
+        None => Err(Error::BadShape(format!("Region must be given as ZxYxX:ZxYxX, got '{}'", value))),
+    }
+}
+
+fn parse_extents(what: &str, value: &str, min: i32) -> Result<Position> {
+    let dims: Vec<&str> = value.split('x').collect();
+    if dims.len() != 3 {
+        return Err(Error::BadShape(format!("{}{} must be given as ZxYxX, got '{}'", what[..1].to_uppercase(), &what[1..], value)));
+    }
+    let mut n = [0i32; 3];
+    for (d, dim) in n.iter_mut().zip(dims.iter()) {
+        *d = match dim.parse::<i32>() {
+            Ok(v) if v >= min => v,
+            _ => return Err(Error::BadShape(format!("Invalid dimension '{}' in {} '{}'", dim, what, value))),
+        };
+    }
+    Ok(Position { z: n[0], y: n[1], x: n[2] })
+}
+
+/// Parses a scan order, a curve or the axes of linear loops given as `z,-x,y`.
+pub fn parse_order(value: &str) -> Result<Order> {
+    match value {
+        "serpentine" => return Ok(Order::Serpentine),
+        "morton" => return Ok(Order::Morton),
+        "hilbert" => return Ok(Order::Hilbert),
+        _ => {}
+    }
+    let invalid = || Error::InvalidInput(format!("Invalid order '{}', expected serpentine, morton, hilbert or axes like z,y,x", value));
+    let names: Vec<&str> = value.split(',').collect();
+    if names.len() != 3 {
+        return Err(invalid());
+    }
+    let mut axes = [Axis::Z; 3];
+    let mut reversed = [false; 3];
+    for (i, name) in names.iter().enumerate() {
+        let (r, name) = match name.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, *name),
+        };
+        axes[i] = match name {
+            "x" => Axis::X,
+            "y" => Axis::Y,
+            "z" => Axis::Z,
+            _ => return Err(invalid()),
+        };
+        reversed[i] = r;
+    }
+    if axes[0] == axes[1] || axes[0] == axes[2] || axes[1] == axes[2] {
+        return Err(invalid());
+    }
+    Ok(Order::Linear { axes, reversed })
+}
+
+pub(crate) fn choice<T>(flag: &str, value: &str, choices: Vec<(&str, T)>) -> Result<T> {
+    let names: Vec<&str> = choices.iter().map(|(name, _)| *name).collect();
+    match choices.into_iter().find(|(name, _)| *name == value) {
+        Some((_, v)) => Ok(v),
+        None => Err(Error::InvalidInput(format!("Invalid value '{}' for {}, expected one of: {}", value, flag, names.join(", ")))),
+    }
+}
+
+pub fn parse_args(args: &[String]) -> Result<Config<'_>> {
+    let mut coding = None;
+    let mut cli = Settings::default();
+    let mut config = None;
+    let mut preset = None;
+    let mut coder = Coder::None;
+    let mut csv = false;
+    let mut files = Vec::new();
+
+    let mut i = 1;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        let (flag, inline) = match arg.find('=') {
+            Some(pos) if arg.starts_with("--") => (&arg[..pos], Some(&arg[pos + 1..])),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || match inline {
+            Some(v) => Ok(v.to_string()),
+            None if i < args.len() => {
+                i += 1;
+                Ok(args[i - 1].clone())
+            }
+            None => Err(Error::InvalidInput(format!("Missing value for {}", flag))),
+        };
+        match flag {
+            "-c" | "--compress" => coding = Some(CodingMode::Encode),
+            "-d" | "--decompress" => coding = Some(CodingMode::Decode),
+            "-t" | "--type" => cli.filetype = Some(value()?),
+            "-e" | "--endian" => cli.endian = Some(value()?),
+            "-s" | "--shape" => cli.shape = Some(value()?),
+            "-p" | "--predictor" => cli.predictor = Some(value()?),
+            "-m" | "--inter" => cli.inter = Some(value()?),
+            "--intra" => cli.intra = Some(value()?),
+            "--byte" => cli.byte = Some(value()?),
+            "--compact" => cli.compact = Some(value()?),
+            "--ring" => cli.ring = Some(true),
+            "--order" => cli.order = Some(value()?),
+            "--tile" => cli.tile = Some(value()?),
+            "--region" => cli.region = Some(value()?),
+            "--partial" => cli.partial = Some(true),
+            "-a" | "--attribute" => {
+                let v = value()?;
+                let (key, value) = match v.find('=') {
+                    Some(pos) if pos > 0 => (&v[..pos], &v[pos + 1..]),
+                    _ => return Err(Error::InvalidInput(format!("Expected KEY=VALUE for {}, got '{}'", flag, v))),
+                };
+                cli.attributes.get_or_insert_with(BTreeMap::new).insert(key.to_string(), value.to_string());
+            }
+            "--config" => config = Some(value()?),
+            "--preset" => preset = Some(value()?),
+            "--coder" => coder = choice(flag, &value()?, vec![("none", Coder::None), ("deflate", Coder::Deflate)])?,
+            "--csv" => csv = true,
+            "bench" if coding.is_none() && files.is_empty() => coding = Some(CodingMode::Bench),
+            "verify" if coding.is_none() && files.is_empty() => coding = Some(CodingMode::Verify),
+            "batch" if coding.is_none() && files.is_empty() => coding = Some(CodingMode::Batch),
+            "netcdf" if coding.is_none() && files.is_empty() => coding = Some(CodingMode::NetCdf),
+            _ if flag.starts_with('-') && flag.len() > 1 => return Err(Error::InvalidInput(format!("Unknown option {}", flag))),
+            _ => files.push(arg),
+        }
+    }
+
+    let mut settings = cli;
+    if let Some(path) = config {
+        settings = settings.or(Settings::load(&path)?);
+    }
+    if let Some(name) = preset {
+        settings = settings.or(Settings::preset(&name)?);
+    }
+
+    let coding = coding.ok_or_else(|| Error::InvalidInput("Missing mode, use -c to compress or -d to decompress".to_string()))?;
+    match coding {
+        CodingMode::Bench | CodingMode::Verify if files.len() != 1 => {
+            return Err(Error::InvalidInput(format!("Expected INPUT, got {} file(s)", files.len())));
+        }
+        CodingMode::Batch if files.len() != 2 => {
+            return Err(Error::InvalidInput(format!("Expected DIR and OUTDIR, got {} file(s)", files.len())));
+        }
+        CodingMode::NetCdf if files.len() != 2 => {
+            return Err(Error::InvalidInput(format!("Expected FILE and OUTDIR, got {} file(s)", files.len())));
+        }
+        CodingMode::Encode | CodingMode::Decode if files.len() != 2 => {
+            return Err(Error::InvalidInput(format!("Expected INPUT and OUTPUT, got {} file(s)", files.len())));
+        }
+        _ => {}
+    }
+    let shape = match (settings.shape, &coding) {
+        (Some(shape), _) => parse_shape(&shape)?,
+        (None, CodingMode::Decode) | (None, CodingMode::Batch) | (None, CodingMode::NetCdf) => Position { x: 0, y: 0, z: 0 },
+        (None, _) if npy::is_npy(files[0]) => Position { x: 0, y: 0, z: 0 },
+        (None, _) => return Err(Error::InvalidInput("Missing --shape for compression".to_string())),
+    };
+    let filetype = match settings.filetype {
+        Some(v) => choice("--type", &v, vec![("f32", FileType::F32), ("f64", FileType::F64)])?,
+        None => FileType::F32,
+    };
+    let endian = match settings.endian {
+        Some(v) => {
+            let choices = vec![
+                ("little", Some(Endian::Little)),
+                ("big", Some(Endian::Big)),
+                ("mixed", Some(Endian::Mixed)),
+                ("auto", None),
+            ];
+            choice("--endian", &v, choices)?
+        }
+        None => Some(Endian::Little),
+    };
+    let predictor = match (settings.predictor, settings.cells) {
+        (Some(_), Some(_)) => return Err(Error::InvalidInput("Use either a predictor or cells, not both".to_string())),
+        (Some(v), None) => {
+            let choices = vec![("lv", Predictor::LastValue), ("lorenz", Predictor::Lorenz)];
+            choice("--predictor", &v, choices)?
+        }
+        (None, Some(cells)) => Predictor::Custom {
+            cells: cells.iter().map(|c| Position { x: c.x, y: c.y, z: c.z }).collect(),
+            coeff: cells.iter().map(|c| c.coeff).collect(),
+        },
+        (None, None) => Predictor::LastValue,
+    };
+    let mapping = match settings.inter {
+        Some(v) => choice("--inter", &v, vec![("raw", MapType::Raw), ("ordered", MapType::Ordered)])?,
+        None => MapType::Raw,
+    };
+    let intramapping = match settings.intra {
+        Some(v) => {
+            let choices = vec![
+                ("untouched", IntramappingType::Untouched),
+                ("gray", IntramappingType::ClassicGrayCodes),
+            ];
+            choice("--intra", &v, choices)?
+        }
+        None => IntramappingType::Untouched,
+    };
+    let bytemapping = match settings.byte {
+        Some(v) => {
+            let choices = vec![
+                ("untouched", ByteMappingType::Untouched),
+                ("mono", ByteMappingType::MonotonicGrayCodes),
+            ];
+            choice("--byte", &v, choices)?
+        }
+        None => ByteMappingType::Untouched,
+    };
+    let order = match settings.order {
+        Some(v) => parse_order(&v)?,
+        None => Order::default(),
+    };
+    let tile = match settings.tile {
+        Some(v) => parse_tile(&v)?,
+        None => None,
+    };
+    let region = match (settings.region, &coding) {
+        (Some(v), CodingMode::Decode) => Some(parse_region(&v)?),
+        (Some(_), _) => return Err(Error::InvalidInput("--region is only used by -d".to_string())),
+        (None, _) => None,
+    };
+    let compact = match settings.compact {
+        Some(v) => {
+            let choices = vec![("untouched", CompactType::Untouched), ("nolzc", CompactType::NoLZC)];
+            choice("--compact", &v, choices)?
+        }
+        None => CompactType::Untouched,
     };
 
-    Config {
-        input,
-        output,
+    Ok(Config {
+        input: files[0],
+        output: files.get(1).cloned(),
         coding,
         filetype,
+        endian,
         shape,
         predictor,
         mapping,
         bytemapping,
         intramapping,
         compact,
-    }
+        ring: settings.ring.unwrap_or(false),
+        order,
+        tile,
+        region,
+        partial: settings.partial.unwrap_or(false),
+        coder,
+        csv,
+        attributes: settings.attributes.unwrap_or_default(),
+    })
 }
 
-#[allow(unused_imports)]
+#[allow(unused_imports, dead_code)]
 mod tests {
     use super::*;
 
+    fn to_args(t: &[&str]) -> Vec<String> {
+        t.iter().map(|a| String::from(*a)).collect()
+    }
+
     #[test]
     fn test_parsing_to_config() {
-        let t = vec![
+        let args = to_args(&[
             "pzip",
             "-c",
-            "-f32",
+            "--type",
+            "f32",
             "inputfile.bin",
+            "--shape",
+            "321x32x12",
             "outputfile.bin",
-            "-s",
-            "321",
-            "32",
-            "12",
             "-p",
             "lv",
-            "-m",
-            "raw",
-            "-bm",
+            "--inter=raw",
+            "--byte",
             "mono",
-            "-im",
+            "--intra",
             "gray",
-            "-c",
-        ];
-        let mut args: Vec<String> = Vec::new();
-        for a in t {
-            args.push(String::from(a));
-        }
-        let configuration = parse_args(&args);
+            "--compact",
+            "nolzc",
+        ]);
+        let configuration = parse_args(&args).unwrap();
         assert_eq!(configuration.coding, CodingMode::Encode);
         assert_eq!(configuration.filetype, FileType::F32);
         assert_eq!(
@@ -196,8 +684,8 @@ mod tests {
             }
         );
         assert_eq!(configuration.predictor, Predictor::LastValue);
-        assert_eq!(*configuration.input, args[3]);
-        assert_eq!(*configuration.output, args[4]);
+        assert_eq!(*configuration.input, args[4]);
+        assert_eq!(configuration.output, Some(&args[7]));
         assert_eq!(configuration.mapping, MapType::Raw);
         assert_eq!(
             configuration.bytemapping,
@@ -208,5 +696,220 @@ mod tests {
             IntramappingType::ClassicGrayCodes
         );
         assert_eq!(configuration.compact, CompactType::NoLZC);
+        assert!(!configuration.ring);
+
+        let pipeline = configuration.pipeline();
+        assert_eq!(pipeline.inter, Inter::Untouched);
+        assert_eq!(pipeline.intra, Intra::Gray);
+        assert_eq!(pipeline.byte, Byte::MonoGray);
+        assert_eq!(pipeline.compact, Compact::NoLZC);
+        assert_eq!(configuration.predictor.get_f32().cells.len(), 1);
+    }
+
+    #[test]
+    fn defaults() {
+        let args = to_args(&["pzip", "-d", "in.pzip", "out.raw"]);
+        let configuration = parse_args(&args).unwrap();
+        assert_eq!(configuration.coding, CodingMode::Decode);
+        assert_eq!(configuration.filetype, FileType::F32);
+        assert_eq!(configuration.pipeline(), Pipeline::default());
+        assert!(!configuration.partial);
+        assert_eq!(configuration.endian, Some(Endian::Little));
+
+        let args = to_args(&["pzip", "--compress", "--ring", "-s", "1x2x3", "in", "out"]);
+        assert!(parse_args(&args).unwrap().ring);
+        let args = to_args(&["pzip", "-c", "--partial", "-s", "1x2x3", "in", "out"]);
+        assert!(parse_args(&args).unwrap().partial);
+    }
+
+    #[test]
+    fn errors_instead_of_panics() {
+        let fails = vec![
+            vec!["pzip"],
+            vec!["pzip", "-c", "in", "out"],
+            vec!["pzip", "-c", "-s", "3x4", "in", "out"],
+            vec!["pzip", "-c", "-s", "0x4x5", "in", "out"],
+            vec!["pzip", "-c", "-s", "2x4x5", "in"],
+            vec!["pzip", "-c", "-s", "2x4x5", "in", "out", "-p"],
+            vec!["pzip", "-c", "-s", "2x4x5", "in", "out", "-p", "nope"],
+            vec!["pzip", "-c", "-s", "2x4x5", "in", "out", "--unknown"],
+            vec!["pzip", "-c", "-s", "2x4x5", "in", "out", "--order", "z,y"],
+            vec!["pzip", "-c", "-s", "2x4x5", "in", "out", "--order", "z,x,z"],
+            vec!["pzip", "-c", "-s", "2x4x5", "in", "out", "--order", "z,w,x"],
+            vec!["pzip", "-c", "-s", "2x4x5", "in", "out", "--tile", "2x-1x2"],
+        ];
+        for args in fails {
+            assert!(parse_args(&to_args(&args)).is_err(), "{:?}", args);
+        }
+        let message = parse_args(&to_args(&["pzip", "-d", "a", "b", "--inter", "x"])).unwrap_err();
+        assert_eq!(message.to_string(), "Invalid value 'x' for --inter, expected one of: raw, ordered");
+        let shape = parse_args(&to_args(&["pzip", "-c", "-s", "3x4", "in", "out"])).unwrap_err();
+        assert!(matches!(shape, Error::BadShape(_)));
+    }
+
+    #[test]
+    fn presets_and_precedence() {
+        for (name, _) in PRESETS {
+            assert!(Settings::preset(name).is_ok(), "{}", name);
+        }
+        assert!(Settings::preset("nope").is_err());
+
+        let args = to_args(&["pzip", "-c", "--preset", "climate-f32-fast", "-s", "4x5x6", "in", "out", "-p", "lorenz"]);
+        let configuration = parse_args(&args).unwrap();
+        assert_eq!(configuration.predictor, Predictor::Lorenz);
+        assert_eq!(configuration.mapping, MapType::Ordered);
+        assert_eq!(configuration.bytemapping, ByteMappingType::MonotonicGrayCodes);
+    }
+
+    #[test]
+    fn configuration_files() {
+        let text = "
+            type = 'f64'
+            shape = '90x351x901'
+            inter = 'ordered'
+            ring = true
+
+            [[cells]]
+            z = 0
+            y = 0
+            x = 1
+            coeff = 2.0
+
+            [[cells]]
+            z = 0
+            y = 0
+            x = 2
+            coeff = -1.0
+        ";
+        let settings = Settings::from_toml(text).unwrap();
+        let json = r#"{"type": "f64", "shape": "90x351x901", "inter": "ordered", "ring": true,
+            "cells": [{"z": 0, "y": 0, "x": 1, "coeff": 2.0}, {"z": 0, "y": 0, "x": 2, "coeff": -1.0}]}"#;
+        assert_eq!(Settings::from_json(json).unwrap(), settings);
+        assert!(Settings::from_toml("colour = 'red'").is_err());
+
+        let path = &std::env::temp_dir().join(format!("pzip_config_{}.toml", std::process::id())).to_string_lossy().into_owned();
+        std::fs::write(path, text).unwrap();
+        let args = to_args(&["pzip", "-c", "--config", path, "--preset", "climate-f32", "in", "out"]);
+        let configuration = parse_args(&args);
+        std::fs::remove_file(path).unwrap();
+        let configuration = configuration.unwrap();
+        assert_eq!(configuration.filetype, FileType::F64);
+        assert_eq!(configuration.shape, Position { x: 901, y: 351, z: 90 });
+        assert_eq!(configuration.bytemapping, ByteMappingType::MonotonicGrayCodes);
+        assert!(configuration.ring);
+        let predictor = configuration.predictor.get_f64();
+        assert_eq!(predictor.coeff, vec![2.0, -1.0]);
+        assert_eq!(predictor.cells[1], Position { x: 2, y: 0, z: 0 });
+    }
+
+    #[test]
+    fn bench_mode() {
+        let args = to_args(&["pzip", "bench", "-s", "1x2x3", "--coder", "deflate", "--csv", "in"]);
+        let configuration = parse_args(&args).unwrap();
+        assert_eq!(configuration.coding, CodingMode::Bench);
+        assert_eq!(configuration.output, None);
+        assert_eq!(configuration.coder, Coder::Deflate);
+        assert!(configuration.csv);
+        assert!(parse_args(&to_args(&["pzip", "bench", "-s", "1x2x3", "in", "out"])).is_err());
+        assert!(parse_args(&to_args(&["pzip", "bench", "in"])).is_err());
+    }
+
+    #[test]
+    fn verify_mode() {
+        let args = to_args(&["pzip", "verify", "--preset", "climate-f32", "-s", "1x2x3", "in"]);
+        let configuration = parse_args(&args).unwrap();
+        assert_eq!(configuration.coding, CodingMode::Verify);
+        assert_eq!(configuration.predictor, Predictor::Lorenz);
+        assert_eq!(configuration.output, None);
+        assert!(parse_args(&to_args(&["pzip", "verify", "-s", "1x2x3", "in", "out"])).is_err());
+    }
+
+    #[test]
+    fn batch_mode() {
+        let args = to_args(&["pzip", "batch", "--preset", "climate-f32", "data", "out"]);
+        let configuration = parse_args(&args).unwrap();
+        assert_eq!(configuration.coding, CodingMode::Batch);
+        assert_eq!(configuration.input, "data");
+        assert_eq!(configuration.output.unwrap(), "out");
+        assert!(parse_args(&to_args(&["pzip", "batch", "data"])).is_err());
+    }
+
+    #[test]
+    fn netcdf_mode() {
+        let args = to_args(&["pzip", "netcdf", "-p", "lorenz", "emac.nc", "out"]);
+        let configuration = parse_args(&args).unwrap();
+        assert_eq!(configuration.coding, CodingMode::NetCdf);
+        assert_eq!(configuration.input, "emac.nc");
+        assert_eq!(configuration.output.unwrap(), "out");
+        assert!(parse_args(&to_args(&["pzip", "netcdf", "emac.nc"])).is_err());
+    }
+
+    #[test]
+    fn byte_order() {
+        let args = to_args(&["pzip", "-c", "-e", "big", "-s", "1x2x3", "in", "out"]);
+        assert_eq!(parse_args(&args).unwrap().endian, Some(Endian::Big));
+        let args = to_args(&["pzip", "verify", "--endian=auto", "-s", "1x2x3", "in"]);
+        assert_eq!(parse_args(&args).unwrap().endian, None);
+        let settings = Settings::from_toml("endian = 'mixed'").unwrap();
+        assert_eq!(settings.endian.as_deref(), Some("mixed"));
+        assert!(parse_args(&to_args(&["pzip", "-c", "-e", "middle", "-s", "1x2x3", "in", "out"])).is_err());
+    }
+
+    #[test]
+    fn help() {
+        assert!(help_requested(&to_args(&["pzip", "-c", "--help"])));
+        assert!(!help_requested(&to_args(&["pzip", "-c"])));
+    }
+
+    #[test]
+    fn attributes() {
+        let args = to_args(&["pzip", "-c", "-s", "1x2x3", "-a", "units=K", "--attribute=note=a=b", "in", "out"]);
+        let config = parse_args(&args).unwrap();
+        assert_eq!(config.attributes.len(), 2);
+        assert_eq!((config.attributes["units"].as_str(), config.attributes["note"].as_str()), ("K", "a=b"));
+        assert!(parse_args(&to_args(&["pzip", "-c", "-s", "1x2x3", "-a", "=K", "in", "out"])).is_err());
+
+        let file = Settings::from_toml("[attributes]\nunits = 'kg'\nsource = 'icon'\n").unwrap();
+        let cli = Settings { attributes: Some(config.attributes.clone()), ..Settings::default() };
+        let merged = cli.or(file).attributes.unwrap();
+        assert_eq!((merged["units"].as_str(), merged["source"].as_str()), ("K", "icon"));
+    }
+
+    #[test]
+    fn scan_order() {
+        let args = to_args(&["pzip", "-c", "-s", "1x2x3", "--order", "y,-x,z", "in", "out"]);
+        let expected = Order::Linear { axes: [Axis::Y, Axis::X, Axis::Z], reversed: [false, true, false] };
+        assert_eq!(parse_args(&args).unwrap().pipeline().order, expected);
+        let args = to_args(&["pzip", "-c", "-s", "1x2x3", "--order=hilbert", "in", "out"]);
+        assert_eq!(parse_args(&args).unwrap().pipeline().order, Order::Hilbert);
+
+        let file = Settings::from_toml("order = 'morton'\n").unwrap();
+        assert_eq!(file.order.as_deref(), Some("morton"));
+        let cli = Settings { order: Some("serpentine".to_string()), ..Settings::default() };
+        assert_eq!(cli.or(file).order.as_deref(), Some("serpentine"));
+    }
+
+    #[test]
+    fn tiles() {
+        let args = to_args(&["pzip", "-c", "-s", "4x20x30", "--tile", "0x8x16", "in", "out"]);
+        assert_eq!(parse_args(&args).unwrap().pipeline().tile, Some(Position { x: 16, y: 8, z: 0 }));
+        let args = to_args(&["pzip", "-c", "-s", "4x20x30", "--tile=0x0x0", "in", "out"]);
+        assert_eq!(parse_args(&args).unwrap().pipeline().tile, None);
+        let file = Settings::from_toml("tile = '1x2x3'\n").unwrap();
+        assert_eq!(file.tile.as_deref(), Some("1x2x3"));
+        assert!(matches!(parse_tile("8x8"), Err(Error::BadShape(_))));
+    }
+
+    #[test]
+    fn regions() {
+        let args = to_args(&["pzip", "-d", "--region", "2x0x3:1x5x7", "in", "out"]);
+        let region = Tile { offset: Position { x: 3, y: 0, z: 2 }, shape: Position { x: 7, y: 5, z: 1 } };
+        assert_eq!(parse_args(&args).unwrap().region, Some(region));
+        assert_eq!(parse_args(&to_args(&["pzip", "-d", "in", "out"])).unwrap().region, None);
+        for region in &["1x5x7", "0x0x0:0x1x1", "-1x0x0:1x1x1"] {
+            assert!(matches!(parse_region(region), Err(Error::BadShape(_))), "{}", region);
+        }
+        let args = to_args(&["pzip", "-c", "-s", "1x5x7", "--region", "0x0x0:1x1x1", "in", "out"]);
+        assert!(parse_args(&args).is_err());
     }
 }
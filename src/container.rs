@@ -0,0 +1,610 @@
+/// Container format of pzip files
+///
+/// A pzip file starts with a header describing everything needed to decode
+/// the data, followed by an index of the chunks and the encoded chunks
+/// themselves. All numbers are little endian.
+///
+/// | Field      | Size                    | Content                                             |
+/// |------------|-------------------------|-----------------------------------------------------|
+/// | magic      | 4                       | `PZIP`                                              |
+/// | version    | 1                       | `VERSION`                                           |
+/// | filetype   | 1                       | 0: f32, 1: f64                                      |
+/// | shape      | 3 x 4                   | z, y, x                                             |
+/// | mappings   | 4                       | Inter, Intra, Byte, Compact                         |
+/// | ring       | 1                       | 0: no ring, 1: ring                                 |
+/// | order      | 1 (+4 for Linear)       | tag, (axes and reversed flags)                      |
+/// | tile       | 3 x 4                   | z, y, x (0: not tiled along the axis)               |
+/// | cells      | 1 + n x (3 x 4 + 8)     | number of cells, (x, y, z, coefficient)             |
+/// | trailing   | 8                       | values after the grid (since version 3)             |
+/// | endian     | 1                       | input 0: little, 1: big, 2: mixed (v4)              |
+/// | attributes | 4 + n x (2 + k + 4 + v) | number, (key length, key, value length, value) (v5) |
+/// | index      | 4 + m x (8 + 8)         | number of chunks, (offset, length)                  |
+/// | payload    | sum of chunk lengths    | encoded chunks, offsets relative to start           |
+/// | crc        | 4 x m                   | CRC32 of every encoded chunk (v6)                   |
+/// | checksum   | 1 + 8                   | 0: none, 1: xxHash64 of the values; the hash (v6)   |
+///
+/// Values of a trailing partial record follow the grid of `shape` in the
+/// data and are stored in an additional last chunk. The byte order of the
+/// original input is recorded, such that it can be restored on decompression.
+/// Attributes are UTF-8 key/value pairs describing the data, e.g. units.
+/// The checksums follow the payload, such that streams can compute them
+/// while writing. Both are verified on decode.
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use super::codec::{self, Value};
+use super::config::FileType;
+use super::endian::Endian;
+use super::error::{Error, Result};
+use super::order::{Axis, Order};
+use super::position::Position;
+use super::predictors::Ignorant;
+use super::tiling;
+use super::transform::{Byte, Compact, Inter, Intra};
+use super::Pipeline;
+
+pub const MAGIC: &[u8; 4] = b"PZIP";
+pub const VERSION: u8 = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub filetype: FileType,
+    pub shape: Position,
+    pub cells: Vec<Position>,
+    pub coeff: Vec<f64>,
+    pub pipeline: Pipeline,
+    /// Number of values of a trailing partial record after the grid
+    pub trailing: u64,
+    /// Byte order of the original input
+    pub endian: Endian,
+    /// Metadata of the data, e.g. units, long_name or the original filename
+    pub attributes: BTreeMap<String, String>,
+    /// Version of the file the header was read from, `VERSION` for new headers
+    pub version: u8,
+    /// xxHash64 of the original values (see `codec::checksum`), stored after the payload
+    pub checksum: Option<u64>,
+}
+
+impl Header {
+    /// Header for data of `shape` encoded with `predictor` and `pipeline`.
+    pub fn new<T: Value>(shape: &Position, predictor: &Ignorant<T>, pipeline: &Pipeline) -> Header {
+        Header {
+            filetype: T::FILETYPE,
+            shape: *shape,
+            cells: predictor.cells.clone(),
+            coeff: predictor.coeff.iter().map(|c| c.to_f64()).collect(),
+            pipeline: *pipeline,
+            trailing: 0,
+            endian: Endian::Little,
+            attributes: BTreeMap::new(),
+            version: VERSION,
+            checksum: None,
+        }
+    }
+
+    /// Predictor recorded in the header, checking the type of the data.
+    pub fn predictor<T: Value>(&self) -> Result<Ignorant<T>> {
+        if self.filetype != T::FILETYPE {
+            return Err(Error::InvalidInput(format!("File contains {:?} data", self.filetype)));
+        }
+        let coeff = self.coeff.iter().map(|c| T::from_f64(*c)).collect();
+        Ok(Ignorant { coeff, cells: self.cells.clone() })
+    }
+
+    /// Checks that the header can be written, `write` fails before writing anything.
+    pub fn check(&self) -> Result<()> {
+        if self.cells.len() > u8::MAX as usize {
+            return Err(Error::InvalidInput(format!("Predictor has {} cells, at most {} can be stored", self.cells.len(), u8::MAX)));
+        }
+        if self.attributes.len() > u32::MAX as usize {
+            return Err(Error::InvalidInput(format!("{} attributes can not be stored", self.attributes.len())));
+        }
+        for (key, value) in &self.attributes {
+            if key.len() > u16::MAX as usize || value.len() > u32::MAX as usize {
+                return Err(Error::InvalidInput(format!("Attribute {} is too long", key)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        self.check()?;
+        w.write_all(MAGIC)?;
+        w.write_u8(VERSION)?;
+        w.write_u8(match self.filetype {
+            FileType::F32 => 0,
+            FileType::F64 => 1,
+        })?;
+        for n in &[self.shape.z, self.shape.y, self.shape.x] {
+            w.write_u32::<LittleEndian>(*n as u32)?;
+        }
+        let p = &self.pipeline;
+        w.write_u8(match p.inter {
+            Inter::Untouched => 0,
+            Inter::Ordered => 1,
+        })?;
+        w.write_u8(match p.intra {
+            Intra::Untouched => 0,
+            Intra::Gray => 1,
+        })?;
+        w.write_u8(match p.byte {
+            Byte::Untouched => 0,
+            Byte::MonoGray => 1,
+        })?;
+        w.write_u8(match p.compact {
+            Compact::Untouched => 0,
+            Compact::NoLZC => 1,
+        })?;
+        w.write_u8(p.ring as u8)?;
+        write_order(w, &p.order)?;
+        let tile = p.tile.unwrap_or(Position { x: 0, y: 0, z: 0 });
+        for n in &[tile.z, tile.y, tile.x] {
+            w.write_u32::<LittleEndian>(*n as u32)?;
+        }
+        w.write_u8(self.cells.len() as u8)?;
+        for (cell, coeff) in self.cells.iter().zip(self.coeff.iter()) {
+            w.write_i32::<LittleEndian>(cell.x)?;
+            w.write_i32::<LittleEndian>(cell.y)?;
+            w.write_i32::<LittleEndian>(cell.z)?;
+            w.write_f64::<LittleEndian>(*coeff)?;
+        }
+        w.write_u64::<LittleEndian>(self.trailing)?;
+        w.write_u8(match self.endian {
+            Endian::Little => 0,
+            Endian::Big => 1,
+            Endian::Mixed => 2,
+        })?;
+        w.write_u32::<LittleEndian>(self.attributes.len() as u32)?;
+        for (key, value) in &self.attributes {
+            w.write_u16::<LittleEndian>(key.len() as u16)?;
+            w.write_all(key.as_bytes())?;
+            w.write_u32::<LittleEndian>(value.len() as u32)?;
+            w.write_all(value.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> Result<Header> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("Not a pzip file"));
+        }
+        let version = r.read_u8()?;
+        if !(2..=VERSION).contains(&version) {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let filetype = match r.read_u8()? {
+            0 => FileType::F32,
+            1 => FileType::F64,
+            v => return Err(invalid(&format!("Unknown filetype {}", v))),
+        };
+        let z = r.read_u32::<LittleEndian>()? as i32;
+        let y = r.read_u32::<LittleEndian>()? as i32;
+        let x = r.read_u32::<LittleEndian>()? as i32;
+        let shape = Position { x, y, z };
+        let size = codec::size(&shape).map_err(|e| invalid(&e.to_string()))?;
+        let inter = match r.read_u8()? {
+            0 => Inter::Untouched,
+            1 => Inter::Ordered,
+            v => return Err(invalid(&format!("Unknown inter mapping {}", v))),
+        };
+        let intra = match r.read_u8()? {
+            0 => Intra::Untouched,
+            1 => Intra::Gray,
+            v => return Err(invalid(&format!("Unknown intra mapping {}", v))),
+        };
+        let byte = match r.read_u8()? {
+            0 => Byte::Untouched,
+            1 => Byte::MonoGray,
+            v => return Err(invalid(&format!("Unknown byte mapping {}", v))),
+        };
+        let compact = match r.read_u8()? {
+            0 => Compact::Untouched,
+            1 => Compact::NoLZC,
+            v => return Err(invalid(&format!("Unknown compaction {}", v))),
+        };
+        let ring = r.read_u8()? != 0;
+        let order = read_order(r)?;
+        let tz = r.read_u32::<LittleEndian>()? as i32;
+        let ty = r.read_u32::<LittleEndian>()? as i32;
+        let tx = r.read_u32::<LittleEndian>()? as i32;
+        let tile = if tx == 0 && ty == 0 && tz == 0 {
+            None
+        } else if tx < 0 || ty < 0 || tz < 0 {
+            return Err(invalid("Negative tile extent"));
+        } else {
+            Some(Position { x: tx, y: ty, z: tz })
+        };
+        if let Some(tile) = &tile {
+            if tiling::count(&shape, tile).filter(|&n| n <= u32::MAX as usize).is_none() {
+                return Err(invalid(&format!("Too many tiles of {:?} in the shape {:?}", tile, shape)));
+            }
+        }
+        let n = r.read_u8()?;
+        let mut cells = Vec::new();
+        let mut coeff = Vec::new();
+        for _ in 0..n {
+            let x = r.read_i32::<LittleEndian>()?;
+            let y = r.read_i32::<LittleEndian>()?;
+            let z = r.read_i32::<LittleEndian>()?;
+            cells.push(Position { x, y, z });
+            coeff.push(r.read_f64::<LittleEndian>()?);
+        }
+        let trailing = if version < 3 { 0 } else { r.read_u64::<LittleEndian>()? };
+        // a trailing partial record is shorter than the records of the grid
+        if trailing > 0 && (trailing >= size as u64 || trailing > i32::MAX as u64) {
+            return Err(invalid(&format!("{} trailing values after the shape {:?}", trailing, shape)));
+        }
+        let endian = match if version < 4 { 0 } else { r.read_u8()? } {
+            0 => Endian::Little,
+            1 => Endian::Big,
+            2 => Endian::Mixed,
+            v => return Err(invalid(&format!("Unknown byte order {}", v))),
+        };
+        let mut attributes = BTreeMap::new();
+        for _ in 0..if version < 5 { 0 } else { r.read_u32::<LittleEndian>()? } {
+            let n = r.read_u16::<LittleEndian>()? as u64;
+            let key = read_text(r, n)?;
+            let n = r.read_u32::<LittleEndian>()? as u64;
+            attributes.insert(key, read_text(r, n)?);
+        }
+        let pipeline = Pipeline { inter, intra, byte, compact, ring, order, tile };
+        Ok(Header { filetype, shape, cells, coeff, pipeline, trailing, endian, attributes, version, checksum: None })
+    }
+}
+
+/// Location of an encoded chunk relative to the start of the payload
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chunk {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Index of consecutive chunks with the given lengths.
+pub fn index(chunks: &[Vec<u8>]) -> Vec<Chunk> {
+    let mut offset = 0;
+    chunks
+        .iter()
+        .map(|c| {
+            let chunk = Chunk { offset, length: c.len() as u64 };
+            offset += chunk.length;
+            chunk
+        })
+        .collect()
+}
+
+pub fn write_index<W: Write>(w: &mut W, index: &[Chunk]) -> Result<()> {
+    w.write_u32::<LittleEndian>(index.len() as u32)?;
+    for chunk in index {
+        w.write_u64::<LittleEndian>(chunk.offset)?;
+        w.write_u64::<LittleEndian>(chunk.length)?;
+    }
+    Ok(())
+}
+
+pub fn read_index<R: Read>(r: &mut R) -> Result<Vec<Chunk>> {
+    let n = r.read_u32::<LittleEndian>()?;
+    let mut index = Vec::new();
+    for _ in 0..n {
+        let offset = r.read_u64::<LittleEndian>()?;
+        let length = r.read_u64::<LittleEndian>()?;
+        index.push(Chunk { offset, length });
+    }
+    Ok(index)
+}
+
+/// End of the last chunk relative to the start of the payload
+pub fn end(index: &[Chunk]) -> u64 {
+    index.iter().map(|c| c.offset.saturating_add(c.length)).max().unwrap_or(0)
+}
+
+/// CRC32 of an encoded chunk
+pub fn crc(chunk: &[u8]) -> u32 {
+    crc32fast::hash(chunk)
+}
+
+/// Writes the CRC32 of every chunk and the checksum of the values.
+pub fn write_checksums<W: Write>(w: &mut W, crcs: &[u32], checksum: Option<u64>) -> Result<()> {
+    for crc in crcs {
+        w.write_u32::<LittleEndian>(*crc)?;
+    }
+    w.write_u8(checksum.is_some() as u8)?;
+    w.write_u64::<LittleEndian>(checksum.unwrap_or(0))?;
+    Ok(())
+}
+
+/// Reads the CRC32 of `n` chunks and the checksum of the values.
+pub fn read_checksums<R: Read>(r: &mut R, n: usize) -> Result<(Vec<u32>, Option<u64>)> {
+    let mut crcs = Vec::new();
+    for _ in 0..n {
+        crcs.push(r.read_u32::<LittleEndian>()?);
+    }
+    let present = r.read_u8()?;
+    let checksum = r.read_u64::<LittleEndian>()?;
+    match present {
+        0 => Ok((crcs, None)),
+        1 => Ok((crcs, Some(checksum))),
+        v => Err(invalid(&format!("Unknown checksum flag {}", v))),
+    }
+}
+
+/// Compares chunk `i` with its CRC32.
+pub fn verify_chunk(i: usize, chunk: &[u8], crc: u32) -> Result<()> {
+    if self::crc(chunk) != crc {
+        return Err(invalid(&format!("Checksum of chunk {} does not match, the file is corrupt", i)));
+    }
+    Ok(())
+}
+
+fn axis_code(axis: Axis) -> u8 {
+    match axis {
+        Axis::X => 0,
+        Axis::Y => 1,
+        Axis::Z => 2,
+    }
+}
+
+fn write_order<W: Write>(w: &mut W, order: &Order) -> Result<()> {
+    match order {
+        Order::Linear { axes, reversed } => {
+            w.write_u8(0)?;
+            for axis in axes.iter() {
+                w.write_u8(axis_code(*axis))?;
+            }
+            let flags = reversed.iter().enumerate().fold(0u8, |acc, (i, &r)| acc | ((r as u8) << i));
+            w.write_u8(flags)?;
+        }
+        Order::Serpentine => w.write_u8(1)?,
+        Order::Morton => w.write_u8(2)?,
+        Order::Hilbert => w.write_u8(3)?,
+    }
+    Ok(())
+}
+
+fn read_order<R: Read>(r: &mut R) -> Result<Order> {
+    match r.read_u8()? {
+        0 => {
+            let mut axes = [Axis::Z; 3];
+            for axis in axes.iter_mut() {
+                *axis = match r.read_u8()? {
+                    0 => Axis::X,
+                    1 => Axis::Y,
+                    2 => Axis::Z,
+                    v => return Err(invalid(&format!("Unknown axis {}", v))),
+                };
+            }
+            let flags = r.read_u8()?;
+            let reversed = [flags & 1 != 0, flags & 2 != 0, flags & 4 != 0];
+            Ok(Order::Linear { axes, reversed })
+        }
+        1 => Ok(Order::Serpentine),
+        2 => Ok(Order::Morton),
+        3 => Ok(Order::Hilbert),
+        v => Err(invalid(&format!("Unknown scan order {}", v))),
+    }
+}
+
+fn read_text<R: Read>(r: &mut R, n: u64) -> Result<String> {
+    let mut buf = Vec::new();
+    r.take(n).read_to_end(&mut buf)?;
+    if buf.len() as u64 != n {
+        return Err(invalid("Attribute exceeds the end of the file"));
+    }
+    String::from_utf8(buf).map_err(|_| invalid("Attribute is not UTF-8"))
+}
+
+pub(crate) fn invalid(msg: &str) -> Error {
+    Error::Corrupt(msg.to_string())
+}
+
+/// Reads a single chunk from `r`, the payload starting at `start`.
+pub fn read_chunk<R: Read + Seek>(r: &mut R, start: u64, chunk: &Chunk) -> Result<Vec<u8>> {
+    r.seek(SeekFrom::Start(start + chunk.offset))?;
+    let mut buf = Vec::new();
+    r.take(chunk.length).read_to_end(&mut buf)?;
+    if buf.len() as u64 != chunk.length {
+        return Err(invalid("Chunk exceeds the end of the file"));
+    }
+    Ok(buf)
+}
+
+/// Writes header, index and chunks into a pzip file. Pipelines which can
+/// not be decoded and invalid headers are refused before the file is created.
+pub fn write(output: &str, header: &Header, chunks: &[Vec<u8>]) -> Result<()> {
+    codec::check_decodable(&header.pipeline)?;
+    header.check()?;
+    let mut file = BufWriter::new(fs::File::create(output)?);
+    write_to(&mut file, header, chunks)?;
+    Ok(file.flush()?)
+}
+
+/// Writes header, index and chunks to `w`.
+pub fn write_to<W: Write>(w: &mut W, header: &Header, chunks: &[Vec<u8>]) -> Result<()> {
+    codec::check_decodable(&header.pipeline)?;
+    header.write(w)?;
+    write_index(w, &index(chunks))?;
+    for chunk in chunks {
+        w.write_all(chunk)?;
+    }
+    let crcs: Vec<u32> = chunks.iter().map(|c| crc(c)).collect();
+    write_checksums(w, &crcs, header.checksum)
+}
+
+/// Reads header and all chunks of a pzip file.
+pub fn read(input: &str) -> Result<(Header, Vec<Vec<u8>>)> {
+    read_from(&mut BufReader::new(fs::File::open(input)?))
+}
+
+/// Reads header and all chunks from `r`.
+pub fn read_from<R: Read>(r: &mut R) -> Result<(Header, Vec<Vec<u8>>)> {
+    let mut header = Header::read(r)?;
+    let index = read_index(r)?;
+    let mut payload = Vec::new();
+    r.read_to_end(&mut payload)?;
+    let mut chunks = Vec::with_capacity(index.len());
+    for chunk in &index {
+        let start = chunk.offset as usize;
+        let end = start.saturating_add(chunk.length as usize);
+        if end > payload.len() {
+            return Err(invalid("Chunk exceeds the end of the file"));
+        }
+        chunks.push(payload[start..end].to_vec());
+    }
+    if header.version >= 6 {
+        let mut trailer = &payload[end(&index) as usize..];
+        let (crcs, checksum) = read_checksums(&mut trailer, index.len())?;
+        for (i, (chunk, crc)) in chunks.iter().zip(crcs).enumerate() {
+            verify_chunk(i, chunk, crc)?;
+        }
+        header.checksum = checksum;
+    }
+    Ok((header, chunks))
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_roundtrip() {
+        let order = Order::Linear {
+            axes: [Axis::Y, Axis::Z, Axis::X],
+            reversed: [true, false, true],
+        };
+        let header = Header {
+            filetype: FileType::F64,
+            shape: Position { x: 901, y: 351, z: 90 },
+            cells: vec![Position { x: 1, y: 0, z: 0 }, Position { x: 0, y: 1, z: 1 }],
+            coeff: vec![1.0, -0.5],
+            pipeline: Pipeline {
+                inter: Inter::Ordered,
+                intra: Intra::Gray,
+                byte: Byte::MonoGray,
+                compact: Compact::Untouched,
+                ring: true,
+                order,
+                tile: Some(Position { x: 64, y: 64, z: 16 }),
+            },
+            trailing: 17,
+            endian: Endian::Big,
+            attributes: vec![("units", "kg kg-1"), ("long_name", "specific humidity")]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            version: VERSION,
+            checksum: None,
+        };
+        let mut buf = Vec::new();
+        header.write(&mut buf).unwrap();
+        assert_eq!(&buf[..4], MAGIC);
+        assert_eq!(Header::read(&mut buf.as_slice()).unwrap(), header);
+        let end = buf.len() - 1;
+        buf[end] = 0xFF;
+        assert!(Header::read(&mut buf.as_slice()).is_err());
+
+        // version 4 has no attributes, version 3 no byte order, version 2 no trailing values
+        buf[4] = 4;
+        buf.truncate(buf.len() - (4 + 2 + 5 + 4 + 7 + 2 + 9 + 4 + 17));
+        let header = Header { attributes: BTreeMap::new(), version: 4, ..header };
+        assert_eq!(Header::read(&mut buf.as_slice()).unwrap(), header);
+        buf[4] = 3;
+        buf.truncate(buf.len() - 1);
+        let little = Header { endian: Endian::Little, version: 3, ..header.clone() };
+        assert_eq!(Header::read(&mut buf.as_slice()).unwrap(), little);
+        buf[4] = 2;
+        buf.truncate(buf.len() - 8);
+        assert_eq!(Header::read(&mut buf.as_slice()).unwrap(), Header { trailing: 0, version: 2, ..little });
+
+        let cells = vec![Position { x: 1, y: 0, z: 0 }; 256];
+        let many = Header { coeff: vec![0.0; 256], cells, ..header.clone() };
+        assert!(matches!(many.write(&mut Vec::new()), Err(Error::InvalidInput(_))));
+
+        // more trailing values than a record or more tiles than an index can hold
+        let small = Header { shape: Position { x: 4, y: 2, z: 1 }, trailing: 8, ..header.clone() };
+        let mut buf = Vec::new();
+        small.write(&mut buf).unwrap();
+        assert!(matches!(Header::read(&mut buf.as_slice()), Err(Error::Corrupt(_))));
+        let pipeline = Pipeline { tile: Some(Position { x: 1, y: 1, z: 1 }), ..header.pipeline };
+        let tiny = Header { shape: Position { x: 1 << 16, y: 1 << 16, z: 2 }, trailing: 0, pipeline, ..header.clone() };
+        let mut buf = Vec::new();
+        tiny.write(&mut buf).unwrap();
+        assert!(matches!(Header::read(&mut buf.as_slice()), Err(Error::Corrupt(_))));
+
+        // nothing is written for an invalid attribute, whichever position it has
+        let mut long = header;
+        long.attributes.insert("a".to_string(), "ok".to_string());
+        long.attributes.insert("b".repeat(70000), "too long".to_string());
+        let mut buf = Vec::new();
+        assert!(matches!(long.write(&mut buf), Err(Error::InvalidInput(_))));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn index_roundtrip() {
+        let chunks = vec![vec![1u8; 12], vec![], vec![2u8; 5]];
+        let result = index(&chunks);
+        assert_eq!(result[2], Chunk { offset: 12, length: 5 });
+
+        let mut buf = Vec::new();
+        write_index(&mut buf, &result).unwrap();
+        assert_eq!(buf.len(), 4 + 3 * 16);
+        assert_eq!(read_index(&mut buf.as_slice()).unwrap(), result);
+    }
+
+    #[test]
+    fn detect_corruption() {
+        let shape = Position { x: 4, y: 2, z: 1 };
+        let predictor = super::super::predictors::predictors::get_last_value_f32();
+        let mut header = Header::new(&shape, &predictor, &Pipeline::default());
+        header.checksum = Some(42);
+        let chunks = vec![vec![1u8; 12], vec![2u8; 5]];
+        let mut buf = Vec::new();
+        write_to(&mut buf, &header, &chunks).unwrap();
+        assert_eq!(read_from(&mut buf.as_slice()).unwrap(), (header.clone(), chunks.clone()));
+
+        let payload = buf.len() - 17 - 2 * 4 - 9;
+        let mut damaged = buf.clone();
+        damaged[payload + 13] ^= 0x01;
+        let result = read_from(&mut damaged.as_slice());
+        assert!(matches!(result, Err(Error::Corrupt(ref msg)) if msg.contains("chunk 1")));
+        assert!(read_from(&mut &buf[..buf.len() - 1]).is_err());
+
+        // version 5 has no checksums
+        buf[4] = 5;
+        buf.truncate(buf.len() - 2 * 4 - 9);
+        let (old, _) = read_from(&mut buf.as_slice()).unwrap();
+        assert_eq!((old.version, old.checksum), (5, None));
+    }
+
+    #[test]
+    fn seek_single_chunk() {
+        let chunks = vec![vec![1u8; 3], vec![2u8; 4], vec![3u8; 2]];
+        let index = index(&chunks);
+        let mut buf = vec![0u8; 10];
+        for chunk in chunks.iter() {
+            buf.extend_from_slice(chunk);
+        }
+        let mut cursor = std::io::Cursor::new(buf);
+        assert_eq!(read_chunk(&mut cursor, 10, &index[1]).unwrap(), vec![2u8; 4]);
+        assert!(read_chunk(&mut cursor, 12, &index[2]).is_err());
+    }
+
+    #[test]
+    fn reject_foreign_files() {
+        let data = b"RIFF0000".to_vec();
+        assert!(matches!(Header::read(&mut data.as_slice()), Err(Error::Corrupt(_))));
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION + 1);
+        assert!(matches!(Header::read(&mut data.as_slice()), Err(Error::UnsupportedVersion(v)) if v == VERSION + 1));
+
+        let mut data = Vec::new();
+        let shape = Position { x: 4, y: -1, z: 2 };
+        Header::new(&shape, &super::super::predictors::predictors::get_last_value_f32(), &Pipeline::default())
+            .write(&mut data)
+            .unwrap();
+        assert!(matches!(Header::read(&mut data.as_slice()), Err(Error::Corrupt(_))));
+    }
+}
@@ -0,0 +1,133 @@
+/// Byte order of raw input files
+///
+/// Besides little and big endian, legacy output sometimes stores the two
+/// halves of every value in swapped order (mixed endian). The byte order of
+/// a file can be detected from the distribution of the exponents: correctly
+/// read floating point data has few distinct exponents, while misread bytes
+/// spread over the whole exponent range.
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use super::codec::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endian {
+    Little,
+    Big,
+    /// Little endian halves (16 bit for f32, 32 bit for f64) in swapped order
+    Mixed,
+}
+
+/// Byte order of the machine, values stored with it can be used in place
+#[cfg(target_endian = "little")]
+pub const NATIVE: Endian = Endian::Little;
+#[cfg(target_endian = "big")]
+pub const NATIVE: Endian = Endian::Big;
+
+const ORDERS: [Endian; 3] = [Endian::Little, Endian::Big, Endian::Mixed];
+
+/// Number of values used by `detect`
+const SAMPLE: usize = 1 << 16;
+
+fn swap_halves(word: u64, bytes: usize) -> u64 {
+    let half = bytes * 4;
+    let mask = if bytes == 8 { u64::MAX } else { (1u64 << (bytes * 8)) - 1 };
+    ((word >> half) | (word << half)) & mask
+}
+
+fn read<T: Value>(chunk: &[u8], endian: Endian) -> T {
+    let word = match endian {
+        Endian::Little => LittleEndian::read_uint(chunk, T::BYTES),
+        Endian::Big => BigEndian::read_uint(chunk, T::BYTES),
+        Endian::Mixed => swap_halves(LittleEndian::read_uint(chunk, T::BYTES), T::BYTES),
+    };
+    T::from_raw(word)
+}
+
+/// Values stored in `bytes` with `endian`, incomplete values at the end are ignored.
+pub fn values<T: Value>(bytes: &[u8], endian: Endian) -> Vec<T> {
+    bytes.chunks_exact(T::BYTES).map(|chunk| read(chunk, endian)).collect()
+}
+
+/// Bytes of `values` stored with `endian`.
+pub fn bytes<T: Value>(values: &[T], endian: Endian) -> Vec<u8> {
+    let mut result = vec![0u8; values.len() * T::BYTES];
+    for (chunk, value) in result.chunks_mut(T::BYTES).zip(values.iter()) {
+        let word = value.to_raw();
+        match endian {
+            Endian::Little => LittleEndian::write_uint(chunk, word, T::BYTES),
+            Endian::Big => BigEndian::write_uint(chunk, word, T::BYTES),
+            Endian::Mixed => LittleEndian::write_uint(chunk, swap_halves(word, T::BYTES), T::BYTES),
+        }
+    }
+    result
+}
+
+/// Entropy (in bits) of the exponents of the values in `bytes` read with `endian`.
+fn exponent_entropy<T: Value>(bytes: &[u8], endian: Endian) -> f64 {
+    let (bits, mantissa) = if T::BYTES == 4 { (8, 23) } else { (11, 52) };
+    let mut counts = vec![0usize; 1 << bits];
+    let mut n = 0;
+    for chunk in bytes.chunks_exact(T::BYTES).take(SAMPLE) {
+        let word = read::<T>(chunk, endian).to_raw();
+        counts[((word >> mantissa) & ((1 << bits) - 1)) as usize] += 1;
+        n += 1;
+    }
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / n as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Byte order of `bytes` with the most concentrated exponents, little
+/// endian if the orders can not be told apart.
+pub fn detect<T: Value>(bytes: &[u8]) -> Endian {
+    let mut best = (Endian::Little, exponent_entropy::<T>(bytes, Endian::Little));
+    for &endian in ORDERS[1..].iter() {
+        let entropy = exponent_entropy::<T>(bytes, endian);
+        if entropy < best.1 - 1e-9 {
+            best = (endian, entropy);
+        }
+    }
+    best.0
+}
+
+#[allow(unused_imports, dead_code)]
+mod tests {
+    use super::*;
+
+    fn field(n: usize) -> Vec<f64> {
+        (0..n).map(|i| 280.0 + 15.0 * (i as f64 * 0.01).sin() + (i % 7) as f64 * 0.013).collect()
+    }
+
+    #[test]
+    fn byte_orders() {
+        let value = f32::from_bits(0x1122_3344);
+        assert_eq!(bytes(&[value], Endian::Little), vec![0x44, 0x33, 0x22, 0x11]);
+        assert_eq!(bytes(&[value], Endian::Big), vec![0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(bytes(&[value], Endian::Mixed), vec![0x22, 0x11, 0x44, 0x33]);
+        let value = f64::from_bits(0x1122_3344_5566_7788);
+        assert_eq!(bytes(&[value], Endian::Mixed), vec![0x44, 0x33, 0x22, 0x11, 0x88, 0x77, 0x66, 0x55]);
+        for &endian in ORDERS.iter() {
+            let data = field(100);
+            assert_eq!(values::<f64>(&bytes(&data, endian), endian), data);
+            let data: Vec<f32> = data.iter().map(|&v| v as f32).collect();
+            assert_eq!(values::<f32>(&bytes(&data, endian), endian), data);
+        }
+    }
+
+    #[test]
+    fn detect_byte_order() {
+        let data = field(5000);
+        let single: Vec<f32> = data.iter().map(|&v| v as f32).collect();
+        for &endian in ORDERS.iter() {
+            assert_eq!(detect::<f64>(&bytes(&data, endian)), endian);
+            assert_eq!(detect::<f32>(&bytes(&single, endian)), endian);
+        }
+        assert_eq!(detect::<f32>(&[0u8; 64]), Endian::Little);
+        assert_eq!(detect::<f64>(&[]), Endian::Little);
+    }
+}
@@ -0,0 +1,80 @@
+/// Errors of pzip
+///
+/// Every public function returns `Result`, such that bad input never
+/// panics, but is reported to the caller.
+use std::error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing failed
+    Io(io::Error),
+    /// Shape which does not describe a grid, e.g. with negative extents
+    BadShape(String),
+    /// Number of values or bytes does not match the shape
+    SizeMismatch(String),
+    /// Input is not a valid pzip file or stream
+    Corrupt(String),
+    /// File written by a different version of the container format
+    UnsupportedVersion(u8),
+    /// Arguments, predictors or pipelines which can not be used
+    InvalidInput(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::BadShape(msg) | Error::SizeMismatch(msg) | Error::Corrupt(msg) | Error::InvalidInput(msg) => {
+                write!(f, "{}", msg)
+            }
+            Error::UnsupportedVersion(v) => write!(f, "Unsupported pzip version {}", v),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// For use inside of `Read` and `Write` implementations
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            Error::InvalidInput(_) | Error::BadShape(_) => io::Error::new(io::ErrorKind::InvalidInput, e.to_string()),
+            _ => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+        }
+    }
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let e: Error = io::Error::new(io::ErrorKind::NotFound, "missing").into();
+        assert!(matches!(e, Error::Io(_)));
+        assert_eq!(e.to_string(), "missing");
+        assert_eq!(Error::UnsupportedVersion(7).to_string(), "Unsupported pzip version 7");
+        let e: io::Error = Error::Corrupt("Not a pzip file".to_string()).into();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let e: io::Error = Error::BadShape("Negative".to_string()).into();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+}
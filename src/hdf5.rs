@@ -0,0 +1,257 @@
+/// HDF5 filter plugin
+///
+/// Built with `cargo rustc --lib --release --features hdf5 --crate-type cdylib`,
+/// which writes `target/release/libpzip.so`. HDF5 loads the library
+/// from `HDF5_PLUGIN_PATH` and calls `H5PLget_plugin_info` to get the
+/// filter, which compresses every chunk of a dataset into a pzip container.
+/// The container describes itself, such that reading needs no parameters.
+///
+/// The parameters (`cd_values`) of the filter are
+///
+/// | Index | Content                                                      |
+/// |-------|--------------------------------------------------------------|
+/// | 0     | type of the values, 0: f32, 1: f64                           |
+/// | 1-3   | shape of a chunk, z, y, x                                    |
+/// | 4     | predictor, 0: last value (default), 1: lorenz                |
+/// | 5     | flags, 1: ordered, 2: gray codes, 4: mono bytes, 8: big endian |
+///
+/// e.g. `dataset(..., chunks=(4, 90, 160), compression=FILTER_ID,
+/// compression_opts=(0, 4, 90, 160, 1, 7))` in h5py.
+use libc::{c_char, c_int, c_uint, c_void, size_t};
+use std::panic;
+use std::ptr;
+use std::slice;
+
+use super::codec::{self, Value};
+use super::config::{FileType, Predictor};
+use super::container::Header;
+use super::endian::{self, Endian};
+use super::error::{Error, Result};
+use super::position::Position;
+use super::transform::{Byte, Inter, Intra};
+use super::{compress, decompress_from, Options, Pipeline, Shape};
+
+/// Identifier of the filter, taken from the range 256-511 which The HDF
+/// Group reserves for testing, since pzip is not registered.
+pub const FILTER_ID: c_int = 305;
+
+const H5Z_CLASS_T_VERS: c_int = 1;
+const H5Z_FLAG_REVERSE: c_uint = 0x0100;
+const H5PL_TYPE_FILTER: c_int = 0;
+
+type Hid = i64;
+type Filter = extern "C" fn(c_uint, size_t, *const c_uint, size_t, *mut size_t, *mut *mut c_void) -> size_t;
+
+/// `H5Z_class2_t` of the HDF5 C API
+#[repr(C)]
+pub struct FilterClass {
+    version: c_int,
+    id: c_int,
+    encoder_present: c_uint,
+    decoder_present: c_uint,
+    name: *const c_char,
+    can_apply: Option<extern "C" fn(Hid, Hid, Hid) -> c_int>,
+    set_local: Option<extern "C" fn(Hid, Hid, Hid) -> c_int>,
+    filter: Option<Filter>,
+}
+
+// Only read by HDF5, the name points to a static string.
+unsafe impl Sync for FilterClass {}
+
+static CLASS: FilterClass = FilterClass {
+    version: H5Z_CLASS_T_VERS,
+    id: FILTER_ID,
+    encoder_present: 1,
+    decoder_present: 1,
+    name: b"pzip\0" as *const u8 as *const c_char,
+    can_apply: None,
+    set_local: None,
+    filter: Some(filter),
+};
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn H5PLget_plugin_type() -> c_int {
+    H5PL_TYPE_FILTER
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "C" fn H5PLget_plugin_info() -> *const c_void {
+    &CLASS as *const FilterClass as *const c_void
+}
+
+/// Parameters of the filter as given by `cd_values`
+#[derive(Debug, PartialEq)]
+pub struct Parameters {
+    pub filetype: FileType,
+    pub shape: Position,
+    pub predictor: Predictor,
+    pub pipeline: Pipeline,
+    pub endian: Endian,
+}
+
+impl Parameters {
+    pub fn parse(cd_values: &[u32]) -> Result<Parameters> {
+        if cd_values.len() < 4 || cd_values.len() > 6 {
+            return Err(Error::InvalidInput(format!("Expected 4 to 6 filter parameters, got {}", cd_values.len())));
+        }
+        let filetype = match cd_values[0] {
+            0 => FileType::F32,
+            1 => FileType::F64,
+            v => return Err(Error::InvalidInput(format!("Unknown type {}", v))),
+        };
+        let extent = |v: u32| if v == 0 || v > i32::MAX as u32 { Err(Error::BadShape(format!("Invalid chunk extent {}", v))) } else { Ok(v as i32) };
+        let shape = Position { z: extent(cd_values[1])?, y: extent(cd_values[2])?, x: extent(cd_values[3])? };
+        let predictor = match cd_values.get(4) {
+            None | Some(0) => Predictor::LastValue,
+            Some(1) => Predictor::Lorenz,
+            Some(v) => return Err(Error::InvalidInput(format!("Unknown predictor {}", v))),
+        };
+        let flags = cd_values.get(5).cloned().unwrap_or(0);
+        let pipeline = Pipeline {
+            inter: if flags & 1 != 0 { Inter::Ordered } else { Inter::Untouched },
+            intra: if flags & 2 != 0 { Intra::Gray } else { Intra::Untouched },
+            byte: if flags & 4 != 0 { Byte::MonoGray } else { Byte::Untouched },
+            ..Pipeline::default()
+        };
+        let endian = if flags & 8 != 0 { Endian::Big } else { Endian::Little };
+        Ok(Parameters { filetype, shape, predictor, pipeline, endian })
+    }
+}
+
+fn encode<T: Value>(parameters: &Parameters, chunk: &[u8]) -> Result<Vec<u8>> {
+    let p = &parameters.shape;
+    if codec::size(p)? * T::BYTES != chunk.len() {
+        return Err(Error::SizeMismatch(format!("{} bytes do not match the chunk {:?}", chunk.len(), p)));
+    }
+    let data: Vec<T> = endian::values(chunk, parameters.endian);
+    let shape = Shape { x: p.x as usize, y: p.y as usize, z: p.z as usize };
+    let options = Options {
+        predictor: parameters.predictor.clone(),
+        pipeline: parameters.pipeline,
+        endian: parameters.endian,
+        ..Options::default()
+    };
+    compress(&data, shape, &options)
+}
+
+/// Compresses the bytes of a dataset chunk.
+pub fn encode_chunk(cd_values: &[u32], chunk: &[u8]) -> Result<Vec<u8>> {
+    let parameters = Parameters::parse(cd_values)?;
+    match parameters.filetype {
+        FileType::F32 => encode::<f32>(&parameters, chunk),
+        FileType::F64 => encode::<f64>(&parameters, chunk),
+    }
+}
+
+fn decode<T: Value>(compressed: &[u8]) -> Result<Vec<u8>> {
+    let (data, header) = decompress_from::<T, _>(&mut &compressed[..])?;
+    Ok(endian::bytes(&data, header.endian))
+}
+
+/// Decompresses a chunk written by `encode_chunk`.
+pub fn decode_chunk(compressed: &[u8]) -> Result<Vec<u8>> {
+    match Header::read(&mut &compressed[..])?.filetype {
+        FileType::F32 => decode::<f32>(compressed),
+        FileType::F64 => decode::<f64>(compressed),
+    }
+}
+
+/// `H5Z_func_t`, replaces the buffer with the (de)compressed chunk and
+/// returns its size, or 0 on failure.
+extern "C" fn filter(
+    flags: c_uint,
+    cd_nelmts: size_t,
+    cd_values: *const c_uint,
+    nbytes: size_t,
+    buf_size: *mut size_t,
+    buf: *mut *mut c_void,
+) -> size_t {
+    let result = panic::catch_unwind(|| {
+        // SAFETY: HDF5 passes `nbytes` valid bytes in `*buf` and `cd_nelmts` parameters.
+        let input = unsafe { slice::from_raw_parts(*buf as *const u8, nbytes) };
+        if flags & H5Z_FLAG_REVERSE != 0 {
+            decode_chunk(input)
+        } else {
+            let cd_values = if cd_nelmts == 0 { &[][..] } else { unsafe { slice::from_raw_parts(cd_values, cd_nelmts) } };
+            encode_chunk(cd_values, input)
+        }
+    });
+    let output = match result {
+        Ok(Ok(output)) => output,
+        _ => return 0,
+    };
+    // SAFETY: the buffer is allocated with malloc by HDF5 and owned by the filter.
+    unsafe {
+        let new = libc::malloc(output.len().max(1));
+        if new.is_null() {
+            return 0;
+        }
+        ptr::copy_nonoverlapping(output.as_ptr(), new as *mut u8, output.len());
+        libc::free(*buf);
+        *buf = new;
+        *buf_size = output.len();
+    }
+    output.len()
+}
+
+#[allow(unused_imports, dead_code)]
+mod tests {
+    use super::*;
+
+    /// Runs the filter like HDF5 on a copy of `bytes`.
+    fn run(flags: c_uint, cd_values: &[c_uint], bytes: &[u8]) -> Option<Vec<u8>> {
+        unsafe {
+            let mut buf = libc::malloc(bytes.len());
+            ptr::copy_nonoverlapping(bytes.as_ptr(), buf as *mut u8, bytes.len());
+            let mut size = bytes.len();
+            let n = filter(flags, cd_values.len(), cd_values.as_ptr(), bytes.len(), &mut size, &mut buf);
+            let result = if n == 0 { None } else { Some(slice::from_raw_parts(buf as *const u8, n).to_vec()) };
+            libc::free(buf);
+            result
+        }
+    }
+
+    #[test]
+    fn plugin_info() {
+        assert_eq!(H5PLget_plugin_type(), H5PL_TYPE_FILTER);
+        let class = unsafe { &*(H5PLget_plugin_info() as *const FilterClass) };
+        assert_eq!((class.version, class.id), (1, FILTER_ID));
+        assert!(class.filter.is_some());
+    }
+
+    #[test]
+    fn parameters() {
+        let p = Parameters::parse(&[1, 4, 90, 160, 1, 15]).unwrap();
+        assert_eq!((p.filetype, p.shape), (FileType::F64, Position { x: 160, y: 90, z: 4 }));
+        assert_eq!(p.predictor, Predictor::Lorenz);
+        assert_eq!((p.pipeline.inter, p.pipeline.intra, p.pipeline.byte), (Inter::Ordered, Intra::Gray, Byte::MonoGray));
+        assert_eq!(p.endian, Endian::Big);
+        assert_eq!(Parameters::parse(&[0, 1, 2, 3]).unwrap().predictor, Predictor::LastValue);
+        assert!(Parameters::parse(&[0, 1, 2]).is_err());
+        assert!(Parameters::parse(&[0, 0, 2, 3]).is_err());
+        assert!(Parameters::parse(&[2, 1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn filter_roundtrip() {
+        let values: Vec<f32> = (0..2 * 3 * 4).map(|i| 280.0 + (i as f32 * 0.4).sin()).collect();
+        let chunk = endian::bytes(&values, Endian::Little);
+        let compressed = run(0, &[0, 2, 3, 4, 1, 7], &chunk).unwrap();
+        assert_eq!(&compressed[..4], super::super::container::MAGIC);
+        assert_eq!(run(H5Z_FLAG_REVERSE, &[0, 2, 3, 4, 1, 7], &compressed), Some(chunk.clone()));
+
+        let values: Vec<f64> = values.iter().map(|&v| f64::from(v)).collect();
+        let chunk = endian::bytes(&values, Endian::Big);
+        let compressed = run(0, &[1, 1, 6, 4, 0, 8], &chunk).unwrap();
+        assert_eq!(run(H5Z_FLAG_REVERSE, &[], &compressed), Some(chunk));
+    }
+
+    #[test]
+    fn failures_return_zero() {
+        assert_eq!(run(0, &[0, 2, 3, 4], &[0u8; 12]), None);
+        assert_eq!(run(0, &[], &[0u8; 12]), None);
+        assert_eq!(run(H5Z_FLAG_REVERSE, &[], b"garbage"), None);
+    }
+}
@@ -0,0 +1,248 @@
+/// Description of pzip files
+///
+/// Everything recorded in the header, the chunk index and the checksums of
+/// a file, read without decoding the payload.
+use std::fmt;
+use std::fs;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use super::codec::layout;
+use super::config::FileType;
+use super::container::{self, Chunk, Header};
+use super::error::Result;
+use super::order::{Axis, Order};
+use super::position::Position;
+
+pub struct Info {
+    pub header: Header,
+    pub index: Vec<Chunk>,
+    /// CRC32 of every chunk, empty for files without checksums
+    pub crcs: Vec<u32>,
+    /// Size of the whole file in bytes
+    pub size: u64,
+}
+
+impl Info {
+    pub fn read(input: &str) -> Result<Info> {
+        let mut file = BufReader::new(fs::File::open(input)?);
+        let mut header = Header::read(&mut file)?;
+        let index = container::read_index(&mut file)?;
+        let start = file.stream_position()? + container::end(&index);
+        let crcs = match file.seek(SeekFrom::Start(start)) {
+            Ok(_) => checksums(&mut file, &mut header, index.len()),
+            Err(_) => Vec::new(),
+        };
+        let size = fs::metadata(input)?.len();
+        Ok(Info { header, index, crcs, size })
+    }
+
+    /// Description of a container in memory, e.g. a variable of an `archive`
+    pub fn from_bytes(container: &[u8]) -> Result<Info> {
+        let mut r = container;
+        let mut header = Header::read(&mut r)?;
+        let index = container::read_index(&mut r)?;
+        let mut trailer = r.get(container::end(&index) as usize..).unwrap_or_default();
+        let crcs = checksums(&mut trailer, &mut header, index.len());
+        Ok(Info { header, index, crcs, size: container.len() as u64 })
+    }
+
+    /// Size of the uncompressed data in bytes, `None` if it does not fit
+    /// into 64 bits.
+    pub fn raw_size(&self) -> Option<u64> {
+        let s = &self.header.shape;
+        (s.x.max(0) as u64)
+            .checked_mul(s.y.max(0) as u64)?
+            .checked_mul(s.z.max(0) as u64)?
+            .checked_add(self.header.trailing)?
+            .checked_mul(bytes(self.header.filetype))
+    }
+}
+
+/// Reads the checksums after the payload, nothing for older or damaged files.
+fn checksums<R: Read>(r: &mut R, header: &mut Header, n: usize) -> Vec<u32> {
+    if header.version < 6 {
+        return Vec::new();
+    }
+    match container::read_checksums(r, n) {
+        Ok((crcs, checksum)) => {
+            header.checksum = checksum;
+            crcs
+        }
+        Err(_) => Vec::new(),
+    }
+}
+
+fn bytes(filetype: FileType) -> u64 {
+    match filetype {
+        FileType::F32 => 4,
+        FileType::F64 => 8,
+    }
+}
+
+fn shape(p: &Position) -> String {
+    format!("{}x{}x{}", p.z, p.y, p.x)
+}
+
+fn order(order: &Order) -> String {
+    match order {
+        Order::Linear { axes, reversed } => {
+            let names: Vec<String> = axes
+                .iter()
+                .zip(reversed.iter())
+                .map(|(axis, &r)| {
+                    let name = match axis {
+                        Axis::X => "x",
+                        Axis::Y => "y",
+                        Axis::Z => "z",
+                    };
+                    if r { format!("-{}", name) } else { name.to_string() }
+                })
+                .collect();
+            format!("linear {}", names.join(","))
+        }
+        Order::Serpentine => "serpentine".to_string(),
+        Order::Morton => "morton".to_string(),
+        Order::Hilbert => "hilbert".to_string(),
+    }
+}
+
+fn ratio(raw: Option<u64>, compressed: u64) -> String {
+    match raw {
+        Some(raw) if compressed > 0 => format!("{:.3}", raw as f64 / compressed as f64),
+        _ => "-".to_string(),
+    }
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let h = &self.header;
+        let p = &h.pipeline;
+        writeln!(f, "type:        {:?}", h.filetype)?;
+        writeln!(f, "endian:      {}", format!("{:?}", h.endian).to_lowercase())?;
+        writeln!(f, "shape:       {} (z x y x x)", shape(&h.shape))?;
+        writeln!(f, "predictor:   {} cell(s)", h.cells.len())?;
+        for (cell, coeff) in h.cells.iter().zip(h.coeff.iter()) {
+            writeln!(f, "             z={} y={} x={} coeff={}", cell.z, cell.y, cell.x, coeff)?;
+        }
+        writeln!(f, "inter:       {:?}", p.inter)?;
+        writeln!(f, "intra:       {:?}", p.intra)?;
+        writeln!(f, "byte:        {:?}", p.byte)?;
+        writeln!(f, "compact:     {:?}", p.compact)?;
+        writeln!(f, "ring:        {}", p.ring)?;
+        writeln!(f, "order:       {}", order(&p.order))?;
+        match p.tile {
+            Some(tile) => writeln!(f, "tiles:       {}", shape(&tile))?,
+            None => writeln!(f, "tiles:       none")?,
+        }
+        if h.trailing > 0 {
+            writeln!(f, "trailing:    {} value(s)", h.trailing)?;
+        }
+        if !h.attributes.is_empty() {
+            writeln!(f, "attributes:  {}", h.attributes.len())?;
+            for (key, value) in &h.attributes {
+                writeln!(f, "             {} = {}", key, value)?;
+            }
+        }
+        writeln!(f, "version:     {}", h.version)?;
+        match h.checksum {
+            Some(checksum) => writeln!(f, "checksum:    {:016x} (xxh64)", checksum)?,
+            None => writeln!(f, "checksum:    none")?,
+        }
+        match self.raw_size() {
+            Some(raw) => writeln!(f, "raw:         {} bytes", raw)?,
+            None => writeln!(f, "raw:         ? bytes")?,
+        }
+        writeln!(f, "compressed:  {} bytes", self.size)?;
+        writeln!(f, "ratio:       {}", ratio(self.raw_size(), self.size))?;
+        writeln!(f, "chunks:      {}", self.index.len())?;
+        writeln!(f, "{:>8} {:>12} {:>12} {:>12} {:>8} {:>8}", "chunk", "offset", "length", "shape", "ratio", "crc32")?;
+        let tiles = layout(&h.shape, p);
+        for (i, chunk) in self.index.iter().enumerate() {
+            let (extent, raw) = match tiles.get(i) {
+                Some(t) => (shape(&t.shape), (t.size() as u64).checked_mul(bytes(h.filetype))),
+                None if i == tiles.len() && h.trailing > 0 => ("trailing".to_string(), h.trailing.checked_mul(bytes(h.filetype))),
+                None => ("?".to_string(), None),
+            };
+            writeln!(
+                f,
+                "{:>8} {:>12} {:>12} {:>12} {:>8} {:>8}",
+                i,
+                chunk.offset,
+                chunk.length,
+                extent,
+                ratio(raw, chunk.length),
+                self.crcs.get(i).map(|c| format!("{:08x}", c)).unwrap_or_else(|| "-".to_string())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::*;
+    use super::super::endian::Endian;
+    use super::super::predictors::predictors;
+    use super::super::Pipeline;
+
+    #[test]
+    fn describe_tiled_file() {
+        let path = &std::env::temp_dir().join(format!("pzip_info_{}.pzip", std::process::id())).to_string_lossy().into_owned();
+        let pipeline = Pipeline {
+            order: Order::Linear { axes: [Axis::Z, Axis::X, Axis::Y], reversed: [false, true, false] },
+            tile: Some(Position { x: 4, y: 4, z: 1 }),
+            ..Pipeline::default()
+        };
+        let shape = Position { x: 6, y: 4, z: 2 };
+        let header = Header::new(&shape, &predictors::get_last_value_f32(), &pipeline);
+        let chunks = vec![vec![0u8; 64], vec![0u8; 16], vec![0u8; 64], vec![0u8; 32]];
+        container::write(path, &header, &chunks).unwrap();
+
+        let info = Info::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let text = info.to_string();
+        assert_eq!(info.raw_size(), Some(48 * 4));
+        assert!(text.contains("shape:       2x4x6"));
+        assert!(text.contains("order:       linear z,-x,y"));
+        assert!(text.contains("tiles:       1x4x4"));
+        assert!(text.contains("chunks:      4"));
+        assert!(text.contains("       1           64           16        1x4x2    2.000 "));
+        assert!(text.contains(&format!("{:08x}\n", container::crc(&chunks[3]))));
+        assert!(text.contains("version:     6\n"));
+        assert!(text.contains("checksum:    none\n"));
+    }
+
+    #[test]
+    fn describe_trailing_values() {
+        let path = &std::env::temp_dir().join(format!("pzip_info_trailing_{}.pzip", std::process::id())).to_string_lossy().into_owned();
+        let shape = Position { x: 4, y: 2, z: 1 };
+        let mut header = Header::new(&shape, &predictors::get_last_value_f64(), &Pipeline::default());
+        header.trailing = 3;
+        header.endian = Endian::Big;
+        container::write(path, &header, &[vec![0u8; 64], vec![0u8; 12]]).unwrap();
+
+        let info = Info::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let text = info.to_string();
+        assert_eq!(info.raw_size(), Some(11 * 8));
+        assert!(text.contains("endian:      big"));
+        assert!(text.contains("trailing:    3 value(s)"));
+        assert!(text.contains("       1           64           12     trailing    2.000"));
+    }
+
+    #[test]
+    fn describe_attributes() {
+        let shape = Position { x: 2, y: 1, z: 1 };
+        let mut header = Header::new(&shape, &predictors::get_last_value_f32(), &Pipeline::default());
+        header.checksum = Some(0x0123_4567_89ab_cdef);
+        header.attributes.insert("units".to_string(), "K".to_string());
+        header.attributes.insert("long_name".to_string(), "temperature".to_string());
+        let mut bytes = Vec::new();
+        container::write_to(&mut bytes, &header, &[vec![0u8; 8]]).unwrap();
+
+        let text = Info::from_bytes(&bytes).unwrap().to_string();
+        assert!(text.contains("attributes:  2\n             long_name = temperature\n             units = K\n"));
+        assert!(text.contains("checksum:    0123456789abcdef (xxh64)\n"));
+        assert!(!Info::from_bytes(&bytes[..bytes.len() - 8]).unwrap().to_string().contains("trailing"));
+    }
+}
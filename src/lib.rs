@@ -1,26 +1,56 @@
-#![feature(generators, generator_trait)]
-#![feature(uniform_paths)]
+#![cfg_attr(feature = "nightly", feature(generators, generator_trait))]
 
+pub mod archive;
+pub mod batch;
+pub mod bench;
+pub mod codec;
 pub mod config;
+pub mod container;
+pub mod endian;
+pub mod error;
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+pub mod info;
+pub mod mmap;
+pub mod netcdf;
+pub mod npy;
 /// pzip - predicted zip
 ///
 /// # pzip
 /// A compression library for floating point data
 // pub mod mapping;
+pub mod order;
 pub mod position;
 pub mod testing;
+pub mod tiling;
 pub mod transform;
+#[cfg(feature = "nightly")]
 pub mod traversal;
 pub mod ptraversal;
 pub mod predictors;
+pub mod stream;
+pub mod verify;
+pub mod zarr;
+#[cfg(feature = "nightly")]
 pub mod gen;
 
+use codec::Value;
+use config::{Config, STDIO};
+use container::Header;
+use endian::Endian;
+use mmap::MmapSource;
+use npy::NpySource;
+pub use error::{Error, Result};
+use order::Order;
 use position::Position;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
 use testing::{FileToBeCompressed, Source};
 use transform::{Byte, Compact, Inter, Intra};
 use transform::{ByteMapping, CompactMapping, InterMapping, IntraMapping};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Shape {
     pub x: usize,
     pub y: usize,
@@ -32,25 +62,80 @@ pub struct Weight {
     pub pos: Position,
 }
 
+/// Transformations applied between prediction and output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pipeline {
+    pub inter: Inter,
+    pub intra: Intra,
+    pub byte: Byte,
+    pub compact: Compact,
+    pub ring: bool,
+    pub order: Order,
+    /// Shape of independently encoded tiles, `None` encodes the data as a whole
+    pub tile: Option<Position>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline {
+            inter: Inter::Untouched,
+            intra: Intra::Untouched,
+            byte: Byte::Untouched,
+            compact: Compact::Untouched,
+            ring: false,
+            order: Order::default(),
+            tile: None,
+        }
+    }
+}
+
+/// Settings of `compress`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub predictor: config::Predictor,
+    pub pipeline: Pipeline,
+    /// Byte order of the original data, restored by the command line tool
+    pub endian: Endian,
+    /// Metadata stored with the data, see `container::Header::attributes`
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            predictor: config::Predictor::LastValue,
+            pipeline: Pipeline::default(),
+            endian: Endian::Little,
+            attributes: BTreeMap::new(),
+        }
+    }
+}
+
 use predictors::{Ignorant, PredictorTrait};
-pub struct Setup<T> {
-    source: testing::Source<T>,
+pub struct Setup<T, S = Source<T>> {
+    source: S,
     shape: Position,
     predictor: Ignorant<T>,
+    /// Byte order of the input, detected from the data if `None`
+    endian: Option<Endian>,
+    attributes: BTreeMap<String, String>,
 }
 
 impl Setup<f64> {
-    pub fn new(input: &String, shape: Position, predictor: Ignorant<f64>) -> Self {
-        let source: Source<f64> = Source::new(input);
-        Setup {
+    pub fn new(input: &str, shape: Position, predictor: Ignorant<f64>) -> Result<Self> {
+        let source: Source<f64> = Source::new(input)?;
+        Ok(Setup {
             source,
             shape,
             predictor,
-        }
+            endian: Some(Endian::Little),
+            attributes: BTreeMap::new(),
+        })
     }
 
-    pub fn write(&mut self, h: Inter, k: Intra, b: Byte, output: &String) {
-        self.source.load().expect("Wrong loading");
+    pub fn write(&mut self, h: Inter, k: Intra, b: Byte, output: &str) -> Result<()> {
+        self.source.load()?;
+        codec::check_size(self.source.data.len(), &self.shape)?;
         let results = self.predictor.consume(&self.source.data, &self.shape, false);
         let diff: Vec<u64> = results
             .iter()
@@ -59,7 +144,6 @@ impl Setup<f64> {
             .map(|(a, b)| k.to_new_u64(a) ^ k.to_new_u64(b))
             .collect();
         let mut tmp: Vec<u8> = Vec::new();
-        dbg!(&diff);
         for n in diff {
             let _ = tmp.write_u64::<LittleEndian>(n);
         }
@@ -68,23 +152,27 @@ impl Setup<f64> {
         use std::io::{BufWriter, Write};
 
         let tmp: Vec<u8> = tmp.iter().map(|a| b.to_u8(*a)).collect();
-        let mut output = BufWriter::new(File::create(output).unwrap());
-        output.write_all(tmp.as_slice()).unwrap();
+        let mut output = BufWriter::new(File::create(output)?);
+        output.write_all(tmp.as_slice())?;
+        Ok(())
     }
 }
 
 impl Setup<f32> {
-    pub fn new(input: &String, shape: Position, predictor: Ignorant<f32>) -> Self {
-        let source: Source<f32> = Source::new(input);
-        Setup {
+    pub fn new(input: &str, shape: Position, predictor: Ignorant<f32>) -> Result<Self> {
+        let source: Source<f32> = Source::new(input)?;
+        Ok(Setup {
             source,
             shape,
             predictor,
-        }
+            endian: Some(Endian::Little),
+            attributes: BTreeMap::new(),
+        })
     }
 
-    pub fn write(&mut self, h: Inter, k: Intra, b: Byte, c: Compact, ring: bool, output: &String) {
-        self.source.load().expect("Wrong loading");
+    pub fn write(&mut self, h: Inter, k: Intra, b: Byte, c: Compact, ring: bool, output: &str) -> Result<()> {
+        self.source.load()?;
+        codec::check_size(self.source.data.len(), &self.shape)?;
         let results = self.predictor.consume(&self.source.data, &self.shape, ring);
         let diff: Vec<u32> = results
             .iter()
@@ -101,9 +189,226 @@ impl Setup<f32> {
         use std::fs::File;
         use std::io::{BufWriter, Write};
 
-        let mut output = BufWriter::new(File::create(output).unwrap());
+        let mut output = BufWriter::new(File::create(output)?);
 
         let tmp: Vec<u8> = tmp.iter().map(|a| b.to_u8(*a)).collect();
-        output.write_all(tmp.as_slice()).unwrap();
+        output.write_all(tmp.as_slice())?;
+        Ok(())
+    }
+}
+
+impl<T: Value> Setup<T, MmapSource<T>> {
+    /// Setup reading the input in place through a memory map.
+    pub fn mmap(input: &str, shape: Position, predictor: Ignorant<T>) -> Result<Self> {
+        Ok(Setup::from_source(MmapSource::new(input)?, shape, predictor))
+    }
+}
+
+impl<T: Value> Setup<T, NpySource<T>> {
+    /// Setup for a NumPy file, type, byte order and shape are taken from its
+    /// header. Its dimensions are stored as attribute `npy::DIMENSIONS`.
+    pub fn npy(input: &str, predictor: Ignorant<T>) -> Result<Self> {
+        let source: NpySource<T> = NpySource::new(input)?;
+        let shape = source.header.position()?;
+        let attributes = npy::attributes(&source.header.shape);
+        Ok(Setup {
+            source,
+            shape,
+            predictor,
+            endian: None,
+            attributes,
+        })
+    }
+}
+
+impl<T: Value, S: FileToBeCompressed<T> + AsRef<[T]>> Setup<T, S> {
+    /// Setup for any source, e.g. `Source::from_bytes` or `Source::stdin`.
+    pub fn from_source(source: S, shape: Position, predictor: Ignorant<T>) -> Self {
+        Setup {
+            source,
+            shape,
+            predictor,
+            endian: Some(Endian::Little),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Byte order of the input used by `compress` and `compress_records`,
+    /// `None` detects it from the data. Little endian by default.
+    pub fn set_endian(&mut self, endian: Option<Endian>) {
+        self.endian = endian;
+    }
+
+    /// Attribute stored with the data by `compress` and `compress_records`.
+    pub fn set_attribute(&mut self, key: &str, value: &str) {
+        self.attributes.insert(key.to_string(), value.to_string());
+    }
+
+    /// Writes the data into a pzip container, which records everything
+    /// needed for `decompress_file`.
+    pub fn compress(&mut self, pipeline: &Pipeline, output: &str) -> Result<()> {
+        let (header, chunks) = self.encode(pipeline)?;
+        container::write(output, &header, &chunks)
+    }
+
+    /// Like `compress`, but writes the container to `w`.
+    pub fn compress_to<W: Write>(&mut self, pipeline: &Pipeline, w: &mut W) -> Result<()> {
+        let (header, chunks) = self.encode(pipeline)?;
+        container::write_to(w, &header, &chunks)
+    }
+
+    /// Like `compress`, but the data may hold several records of the shape
+    /// (stacked along z) followed by a trailing partial record.
+    pub fn compress_records(&mut self, pipeline: &Pipeline, output: &str) -> Result<()> {
+        let (header, chunks) = self.encode_records(pipeline)?;
+        container::write(output, &header, &chunks)
+    }
+
+    /// Like `compress_records`, but writes the container to `w`.
+    pub fn compress_records_to<W: Write>(&mut self, pipeline: &Pipeline, w: &mut W) -> Result<()> {
+        let (header, chunks) = self.encode_records(pipeline)?;
+        container::write_to(w, &header, &chunks)
+    }
+
+    /// Compresses the file `input` into `output` as `pzip -c` does, with the
+    /// pipeline, records and attributes of `config`. The name of the input is
+    /// stored as attribute `filename`, `STDIO` reads stdin or writes stdout.
+    /// The byte order is the one of `set_endian`.
+    pub fn compress_file(&mut self, config: &Config, input: &str, output: &str) -> Result<()> {
+        for (key, value) in &config.attributes {
+            self.set_attribute(key, value);
+        }
+        if input != STDIO {
+            let name = Path::new(input).file_name().unwrap_or_default().to_string_lossy().into_owned();
+            self.attributes.entry("filename".to_string()).or_insert(name);
+        }
+        let pipeline = config.pipeline();
+        match output {
+            STDIO => {
+                let mut stdout = io::stdout();
+                if config.partial {
+                    self.compress_records_to(&pipeline, &mut stdout)?;
+                } else {
+                    self.compress_to(&pipeline, &mut stdout)?;
+                }
+                Ok(stdout.flush()?)
+            }
+            output if config.partial => self.compress_records(&pipeline, output),
+            output => self.compress(&pipeline, output),
+        }
+    }
+
+    fn encode(&mut self, pipeline: &Pipeline) -> Result<(Header, Vec<Vec<u8>>)> {
+        let endian = self.source.load_as(self.endian)?;
+        let chunks = codec::encode_chunks(self.source.as_ref(), &self.shape, &mut self.predictor, pipeline)?;
+        let mut header = Header::new(&self.shape, &self.predictor, pipeline);
+        header.endian = endian;
+        header.attributes = self.attributes.clone();
+        header.checksum = Some(codec::checksum(self.source.as_ref()));
+        Ok((header, chunks))
+    }
+
+    fn encode_records(&mut self, pipeline: &Pipeline) -> Result<(Header, Vec<Vec<u8>>)> {
+        let endian = self.source.load_as(self.endian)?;
+        let (grid, trailing, chunks) =
+            codec::encode_records(self.source.as_ref(), &self.shape, &mut self.predictor, pipeline)?;
+        let mut header = Header::new(&grid, &self.predictor, pipeline);
+        header.trailing = trailing as u64;
+        header.endian = endian;
+        header.attributes = self.attributes.clone();
+        header.checksum = Some(codec::checksum(self.source.as_ref()));
+        Ok((header, chunks))
+    }
+}
+
+/// Compresses `data` (row-major with `shape`) into a pzip container in memory.
+pub fn compress<T: Value>(data: &[T], shape: Shape, options: &Options) -> Result<Vec<u8>> {
+    let shape = codec::fold(&[shape.z, shape.y, shape.x])?;
+    let mut predictor = options.predictor.get::<T>();
+    let chunks = codec::encode_chunks(data, &shape, &mut predictor, &options.pipeline)?;
+    let mut header = Header::new(&shape, &predictor, &options.pipeline);
+    header.endian = options.endian;
+    header.attributes = options.attributes.clone();
+    header.checksum = Some(codec::checksum(data));
+    let mut result = Vec::new();
+    container::write_to(&mut result, &header, &chunks)?;
+    Ok(result)
+}
+
+/// Decompresses a pzip container in memory and returns the data and its shape.
+///
+/// Values of a trailing partial record follow the values of the shape. The
+/// attributes are returned by `decompress_from` as part of the header.
+pub fn decompress<T: Value>(compressed: &[u8]) -> Result<(Vec<T>, Shape)> {
+    let (data, header) = decompress_from(&mut &compressed[..])?;
+    let p = header.shape;
+    Ok((data, Shape { x: p.x as usize, y: p.y as usize, z: p.z as usize }))
+}
+
+/// Reads a pzip file and returns the decoded data and its shape.
+///
+/// Values of a trailing partial record follow the values of the shape.
+pub fn decompress_file<T: Value>(input: &str) -> Result<(Vec<T>, Position)> {
+    let mut file = io::BufReader::new(std::fs::File::open(input)?);
+    let (data, header) = decompress_from(&mut file)?;
+    Ok((data, header.shape))
+}
+
+/// Like `decompress_file`, but reads the container from `r` and returns its header.
+pub fn decompress_from<T: Value, R: Read>(r: &mut R) -> Result<(Vec<T>, Header)> {
+    let (header, chunks) = container::read_from(r)?;
+    let mut predictor = header.predictor()?;
+    let trailing = header.trailing as usize;
+    let data = codec::decode_records(&chunks, &header.shape, trailing, &mut predictor, &header.pipeline)?;
+    if header.checksum.is_some_and(|c| c != codec::checksum(&data)) {
+        return Err(container::invalid("Checksum of the decompressed data does not match, the file is corrupt"));
+    }
+    Ok((data, header))
+}
+
+/// Decodes the box starting at `start` with `shape` out of a pzip container.
+///
+/// Only the chunks of tiles intersecting the box are read and decoded. A
+/// trailing partial record is not part of the shape and never decoded.
+/// The chunks read are checked against their CRC32, the checksum of the
+/// whole data can not be verified for a region.
+pub fn decompress_region<T: Value>(input: &str, start: Position, shape: Position) -> Result<Vec<T>> {
+    let mut file = io::BufReader::new(std::fs::File::open(input)?);
+    let header = Header::read(&mut file)?;
+    let index = container::read_index(&mut file)?;
+    let payload = file.stream_position()?;
+    let mut predictor = header.predictor()?;
+    let full = &header.shape;
+    let inside = |a: i32, n: i32, m: i32| a >= 0 && n >= 0 && a.checked_add(n).is_some_and(|e| e <= m);
+    if !(inside(start.x, shape.x, full.x) && inside(start.y, shape.y, full.y) && inside(start.z, shape.z, full.z)) {
+        return Err(Error::InvalidInput(format!(
+            "Box at {:?} with {:?} exceeds the shape {:?}",
+            start, shape, full
+        )));
+    }
+
+    let tiles = codec::layout(full, &header.pipeline);
+    if tiles.len() + (header.trailing > 0) as usize != index.len() {
+        return Err(container::invalid("Index does not match the tiles"));
+    }
+    let crcs = if header.version >= 6 {
+        file.seek(io::SeekFrom::Start(payload + container::end(&index)))?;
+        Some(container::read_checksums(&mut file, index.len())?.0)
+    } else {
+        None
+    };
+    let region = tiling::Tile { offset: start, shape };
+    let mut data = vec![T::default(); region.size()];
+    for (i, (tile, chunk)) in tiles.iter().zip(index.iter()).enumerate() {
+        if tile.intersection(&region).is_none() {
+            continue;
+        }
+        let bytes = container::read_chunk(&mut file, payload, chunk)?;
+        if let Some(crcs) = &crcs {
+            container::verify_chunk(i, &bytes, crcs[i])?;
+        }
+        let values = codec::decode(&bytes, &tile.shape, &mut predictor, &header.pipeline)?;
+        tiling::copy(&values, tile, &mut data, &region);
     }
+    Ok(data)
 }
@@ -0,0 +1,346 @@
+/// pzip - command line interface
+///
+/// See `config::USAGE` for the available options.
+use pzip::codec::{self, Value};
+use pzip::archive::{self, Archive};
+use pzip::{batch, bench};
+use pzip::config::{self, CodingMode, Config, FileType, Predictor, STDIO};
+use pzip::container::Header;
+use pzip::endian::{self, Endian};
+use pzip::netcdf;
+use pzip::npy::{self, NpySink, NpySource};
+use pzip::info::Info;
+use pzip::predictors::{predictors, Ignorant};
+use pzip::testing::{CompressedFile, FileToBeCompressed, Source};
+use pzip::stream;
+use pzip::verify;
+use pzip::{Error, Result, Setup};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::{env, fs, process};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if config::help_requested(&args) {
+        print!("{}", config::USAGE);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("info") {
+        if let Err(e) = info(&args[2..]) {
+            eprintln!("pzip: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let mut config = match config::parse_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("pzip: {}\n\n{}", e, config::USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = npy_input(&mut config).and_then(|_| run(&config)) {
+        eprintln!("pzip: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(config: &Config) -> Result<()> {
+    let output = || output(config);
+    match (&config.coding, config.filetype) {
+        (CodingMode::Encode, FileType::F32) if npy::is_npy(config.input) => {
+            encode(config, Setup::npy(config.input, config.predictor.get_f32())?)
+        }
+        (CodingMode::Encode, FileType::F64) if npy::is_npy(config.input) => {
+            encode(config, Setup::npy(config.input, config.predictor.get_f64())?)
+        }
+        (CodingMode::Encode, FileType::F32) if config.input == STDIO && streamable(config) => encode_stream::<f32>(config),
+        (CodingMode::Encode, FileType::F64) if config.input == STDIO && streamable(config) => encode_stream::<f64>(config),
+        (CodingMode::Encode, FileType::F32) if config.input == STDIO => {
+            encode(config, Setup::from_source(Source::stdin(), config.shape, config.predictor.get_f32()))
+        }
+        (CodingMode::Encode, FileType::F64) if config.input == STDIO => {
+            encode(config, Setup::from_source(Source::stdin(), config.shape, config.predictor.get_f64()))
+        }
+        (CodingMode::Encode, FileType::F32) => {
+            encode(config, Setup::mmap(config.input, config.shape, config.predictor.get_f32())?)
+        }
+        (CodingMode::Encode, FileType::F64) => {
+            encode(config, Setup::mmap(config.input, config.shape, config.predictor.get_f64())?)
+        }
+        (CodingMode::Decode, _) if archive::is_archive(config.input) => extract(config.input, output()),
+        (CodingMode::Decode, _) if config.region.is_some() => region(config, output()),
+        (CodingMode::Decode, _) => {
+            let (header, reader) = peek(config.input)?;
+            match header.filetype {
+                FileType::F32 => decode::<f32>(header, reader, config.input, output()),
+                FileType::F64 => decode::<f64>(header, reader, config.input, output()),
+            }
+        }
+        (CodingMode::Bench, FileType::F32) => {
+            let mut predictors = vec![
+                ("lv".to_string(), predictors::get_last_value_f32()),
+                ("lorenz".to_string(), predictors::get_lorenz_f32()),
+            ];
+            if let Predictor::Custom { .. } = config.predictor {
+                predictors.push(("custom".to_string(), config.predictor.get_f32()));
+            }
+            bench(config, &predictors)
+        }
+        (CodingMode::Bench, FileType::F64) => {
+            let mut predictors = vec![
+                ("lv".to_string(), predictors::get_last_value_f64()),
+                ("lorenz".to_string(), predictors::get_lorenz_f64()),
+            ];
+            if let Predictor::Custom { .. } = config.predictor {
+                predictors.push(("custom".to_string(), config.predictor.get_f64()));
+            }
+            bench(config, &predictors)
+        }
+        (CodingMode::Verify, FileType::F32) => verify(config, &config.predictor.get_f32()),
+        (CodingMode::Verify, FileType::F64) => verify(config, &config.predictor.get_f64()),
+        (CodingMode::Batch, _) => batch(config),
+        (CodingMode::NetCdf, _) => netcdf(config),
+    }
+}
+
+/// Takes type, shape and byte order of NumPy input from its header
+fn npy_input(config: &mut Config) -> Result<()> {
+    let single = matches!(config.coding, CodingMode::Encode | CodingMode::Bench | CodingMode::Verify);
+    if single && npy::is_npy(config.input) {
+        let header = npy::header(config.input)?;
+        config.filetype = header.filetype;
+        config.shape = header.position()?;
+        config.endian = Some(header.endian);
+    }
+    Ok(())
+}
+
+fn encode<T: Value, S: FileToBeCompressed<T> + AsRef<[T]>>(config: &Config, mut setup: Setup<T, S>) -> Result<()> {
+    setup.set_endian(config.endian);
+    setup.compress_file(config, config.input, output(config))
+}
+
+/// Checks if stdin can be compressed while it is read, other pipelines need
+/// all values in memory.
+fn streamable(config: &Config) -> bool {
+    stream::check(&config.pipeline()).is_ok() && config.endian == Some(Endian::Little) && !config.partial
+}
+
+/// Compresses stdin value by value, see `pzip::stream`. OUTPUT is removed
+/// if stdin does not match the shape.
+fn encode_stream<T: Value>(config: &Config) -> Result<()> {
+    let mut predictor = config.predictor.get::<T>();
+    let mut header = Header::new(&config.shape, &predictor, &config.pipeline());
+    header.attributes = config.attributes.clone();
+    header.check()?;
+    match output(config) {
+        STDIO => stream::encode_with(io::stdin(), io::stdout(), &header, &mut predictor),
+        output => {
+            let result = stream::encode_with(io::stdin(), fs::File::create(output)?, &header, &mut predictor);
+            if result.is_err() {
+                let _ = fs::remove_file(output);
+            }
+            result
+        }
+    }
+}
+
+/// Reads the bytes of `inner` and keeps a copy of them.
+struct Recorder<R> {
+    inner: R,
+    bytes: Vec<u8>,
+}
+
+impl<R: Read> Read for Recorder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Header of INPUT and a reader of INPUT from its start, stdin is read only once.
+fn peek(input: &str) -> Result<(Header, Box<dyn Read>)> {
+    let mut recorder = Recorder { inner: reader(input)?, bytes: Vec::new() };
+    let header = Header::read(&mut recorder)?;
+    Ok((header, Box::new(io::Cursor::new(recorder.bytes).chain(recorder.inner))))
+}
+
+/// INPUT, stdin for `-`
+fn reader(input: &str) -> Result<Box<dyn Read>> {
+    if input == STDIO {
+        Ok(Box::new(io::stdin()))
+    } else {
+        Ok(Box::new(io::BufReader::new(fs::File::open(input)?)))
+    }
+}
+
+fn output<'a>(config: &Config<'a>) -> &'a str {
+    config.output.expect("parse_args requires OUTPUT")
+}
+
+fn info(files: &[String]) -> Result<()> {
+    if files.is_empty() {
+        return Err(Error::InvalidInput("Missing FILE for info".to_string()));
+    }
+    for (i, file) in files.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("file:        {}", file);
+        if !archive::is_archive(file) {
+            print!("{}", Info::read(file)?);
+            continue;
+        }
+        let mut variables = Archive::open(file)?;
+        println!("variables:   {}", variables.directory.len());
+        for entry in variables.directory.clone() {
+            println!();
+            println!("variable:    {}", entry.name);
+            print!("{}", Info::from_bytes(&variables.container(&entry.name)?)?);
+        }
+    }
+    Ok(())
+}
+
+/// Values of INPUT, checked against the shape
+fn load<T: Value>(config: &Config) -> Result<Vec<T>>
+where
+    Source<T, Box<dyn Read>>: FileToBeCompressed<T>,
+{
+    let data = if npy::is_npy(config.input) {
+        let mut source: NpySource<T> = NpySource::new(config.input)?;
+        source.load()?;
+        source.data
+    } else {
+        let mut source: Source<T, _> = Source::from_reader(reader(config.input)?);
+        source.load_as(config.endian)?;
+        source.data
+    };
+    codec::check_size(data.len(), &config.shape)?;
+    Ok(data)
+}
+
+fn bench<T: Value>(config: &Config, predictors: &[(String, Ignorant<T>)]) -> Result<()>
+where
+    Source<T, Box<dyn Read>>: FileToBeCompressed<T>,
+{
+    let data = load::<T>(config)?;
+    let runs = bench::sweep(&data, &config.shape, predictors, config.coder)?;
+    if config.csv {
+        print!("{}", bench::csv(&runs));
+    } else {
+        print!("{}", bench::table(&runs));
+    }
+    Ok(())
+}
+
+fn verify<T: Value + std::fmt::Debug>(config: &Config, predictor: &Ignorant<T>) -> Result<()>
+where
+    Source<T, Box<dyn Read>>: FileToBeCompressed<T>,
+{
+    let data = load::<T>(config)?;
+    match verify::verify(&data, &config.shape, predictor, &config.pipeline())? {
+        None => {
+            println!("ok: {} values are identical after decompression", data.len());
+            Ok(())
+        }
+        Some(mismatch) => Err(Error::Corrupt(mismatch.to_string())),
+    }
+}
+
+fn batch(config: &Config) -> Result<()> {
+    let (input, output) = (Path::new(config.input), Path::new(output(config)));
+    summary(config, &batch::compress_dir(input, output, config)?)
+}
+
+fn netcdf(config: &Config) -> Result<()> {
+    let (input, output) = (Path::new(config.input), Path::new(output(config)));
+    summary(config, &netcdf::compress_file(input, output, &config.predictor, &config.pipeline())?)
+}
+
+/// Prints the report of `batch` and `netcdf`, failing if a file failed
+fn summary(config: &Config, entries: &[batch::Entry]) -> Result<()> {
+    if config.csv {
+        print!("{}", batch::csv(entries));
+    } else {
+        print!("{}", batch::report(entries));
+    }
+    let failed = entries.iter().filter(|e| matches!(e.outcome, batch::Outcome::Failed(_))).count();
+    if failed > 0 {
+        let msg = format!("{} file(s) failed", failed);
+        return Err(Error::InvalidInput(msg));
+    }
+    Ok(())
+}
+
+/// Writes the values in the byte order of the original input, as NumPy
+/// file if OUTPUT ends with `.npy` or `.npz`.
+fn decode<T: Value>(header: Header, mut reader: Box<dyn Read>, input: &str, output: &str) -> Result<()> {
+    // files are verified before OUTPUT is written, pipes are decoded while they are read
+    let pipe = input == STDIO || output == STDIO;
+    if pipe && stream::is_stream(&header) && header.endian == Endian::Little && !npy::is_npy(output) {
+        return match output {
+            STDIO => stream::decode::<T, _, _>(reader, io::stdout()).map(|_| ()),
+            output => stream::decode::<T, _, _>(reader, fs::File::create(output)?).map(|_| ()),
+        };
+    }
+    let (data, header) = pzip::decompress_from::<T, _>(&mut reader)?;
+    let dims = if header.trailing == 0 { Some(npy::original_dimensions(&header.attributes, &header.shape)) } else { None };
+    write(output, &data, header.endian, dims)
+}
+
+/// Writes the values of the box selected by `--region`, which is read from
+/// INPUT without decoding the tiles outside of it.
+fn region(config: &Config, output: &str) -> Result<()> {
+    let region = config.region.expect("run checks for a region");
+    if config.input == STDIO {
+        return Err(Error::InvalidInput("--region needs a file as INPUT".to_string()));
+    }
+    let header = Header::read(&mut reader(config.input)?)?;
+    let (offset, dims) = (region.offset, Some(npy::dimensions(&region.shape)));
+    match header.filetype {
+        FileType::F32 => write(output, &pzip::decompress_region::<f32>(config.input, offset, region.shape)?, header.endian, dims),
+        FileType::F64 => write(output, &pzip::decompress_region::<f64>(config.input, offset, region.shape)?, header.endian, dims),
+    }
+}
+
+/// Writes decoded values into OUTPUT, with the dimensions `dims` if it is a NumPy file.
+fn write<T: Value>(output: &str, data: &[T], endian: Endian, dims: Option<Vec<usize>>) -> Result<()> {
+    if output == STDIO {
+        let mut stdout = io::stdout();
+        stdout.write_all(&endian::bytes(data, endian))?;
+        return Ok(stdout.flush()?);
+    }
+    if !npy::is_npy(output) {
+        return Ok(fs::write(output, endian::bytes(data, endian))?);
+    }
+    let mut sink: NpySink<T> = NpySink::new(output)?;
+    sink.shape = dims;
+    // NumPy has no mixed endian arrays
+    sink.endian = if endian == Endian::Mixed { Endian::Little } else { endian };
+    sink.put_all(data)?;
+    sink.flush()
+}
+
+/// Writes every variable of a file with several variables into
+/// OUTDIR/VARIABLE.raw, in the byte order of the original input.
+fn extract(input: &str, outdir: &str) -> Result<()> {
+    let mut variables = Archive::open(input)?;
+    fs::create_dir_all(outdir)?;
+    for entry in variables.directory.clone() {
+        let container = variables.container(&entry.name)?;
+        let bytes = match Info::from_bytes(&container)?.header.filetype {
+            FileType::F32 => raw::<f32>(&container)?,
+            FileType::F64 => raw::<f64>(&container)?,
+        };
+        fs::write(Path::new(outdir).join(format!("{}.raw", entry.name)), bytes)?;
+    }
+    Ok(())
+}
+
+fn raw<T: Value>(container: &[u8]) -> Result<Vec<u8>> {
+    let (data, header) = pzip::decompress_from::<T, _>(&mut &container[..])?;
+    Ok(endian::bytes(&data, header.endian))
+}
@@ -0,0 +1,111 @@
+/// Memory mapped input
+///
+/// `MmapSource` maps a raw file and exposes it as a slice of values without
+/// copying it into memory, such that large fields are only held once by
+/// the page cache. Only files in the byte order of the machine can be used
+/// in place, other byte orders are converted into an owned copy on `load_as`.
+use memmap2::Mmap;
+use std::fs;
+use std::mem;
+use std::slice;
+
+use super::codec::Value;
+use super::endian::{self, Endian};
+use super::error::{Error, Result};
+use super::testing::FileToBeCompressed;
+
+pub struct MmapSource<T> {
+    map: Mmap,
+    /// Values converted from another byte order
+    converted: Option<Vec<T>>,
+    /// Position of the next value returned by `get`
+    next: usize,
+}
+
+impl<T: Value> MmapSource<T> {
+    pub fn new(filename: &str) -> Result<Self> {
+        let file = fs::File::open(filename)?;
+        // SAFETY: the map is read only, the file must not be truncated while mapped.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() % T::BYTES != 0 {
+            let msg = format!("{} bytes can not be read into {:?}", map.len(), T::FILETYPE);
+            return Err(Error::SizeMismatch(msg));
+        }
+        Ok(MmapSource { map, converted: None, next: 0 })
+    }
+
+    /// Whether the values are still used in place
+    pub fn is_mapped(&self) -> bool {
+        self.converted.is_none()
+    }
+}
+
+impl<T: Value> AsRef<[T]> for MmapSource<T> {
+    fn as_ref(&self) -> &[T] {
+        match &self.converted {
+            Some(values) => values,
+            // SAFETY: maps are page aligned, the length is a multiple of the
+            // size of T (checked in `new`) and every bit pattern is a valid float.
+            None => unsafe { slice::from_raw_parts(self.map.as_ptr() as *const T, self.map.len() / mem::size_of::<T>()) },
+        }
+    }
+}
+
+impl<T: Value> FileToBeCompressed<T> for MmapSource<T> {
+    fn ix(&self, position: usize) -> &T {
+        &self.as_ref()[position]
+    }
+
+    fn get(&mut self) -> Result<T> {
+        let value = self.as_ref().get(self.next).cloned();
+        self.next += 1;
+        value.ok_or_else(|| Error::SizeMismatch("No values left".to_string()))
+    }
+
+    fn load(&mut self) -> Result<usize> {
+        self.load_as(Some(Endian::Little))?;
+        Ok(self.as_ref().len())
+    }
+
+    fn load_as(&mut self, endian: Option<Endian>) -> Result<Endian> {
+        let endian = endian.unwrap_or_else(|| endian::detect::<T>(&self.map));
+        self.converted = if endian == endian::NATIVE { None } else { Some(endian::values(&self.map, endian)) };
+        Ok(endian)
+    }
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::*;
+    use super::super::position::Position;
+    use super::super::predictors::{predictors, PredictorTrait};
+
+    #[test]
+    fn values_in_place() {
+        let path = std::env::temp_dir().join(format!("pzip_mmap_f64_{}.raw", std::process::id()));
+        let filename = &*path.to_string_lossy();
+        let values: Vec<f64> = (0..1000).map(|i| 1013.25 + (f64::from(i) * 0.1).sin()).collect();
+        fs::write(filename, endian::bytes(&values, Endian::Little)).unwrap();
+
+        let mut source: MmapSource<f64> = MmapSource::new(filename).unwrap();
+        assert_eq!(source.load_as(None).unwrap(), Endian::Little);
+        assert_eq!(source.is_mapped(), endian::NATIVE == Endian::Little);
+        assert_eq!(source.as_ref(), &values[..]);
+        assert_eq!((source.get().unwrap(), *source.ix(999)), (values[0], values[999]));
+        let shape = Position { x: 10, y: 10, z: 10 };
+        let predicted = predictors::get_last_value_f64().consume(source.as_ref(), &shape, false);
+        assert_eq!((predicted.len(), predicted[1], predicted[999]), (1000, values[0], values[998]));
+
+        fs::write(filename, endian::bytes(&values, Endian::Big)).unwrap();
+        let mut source: MmapSource<f64> = MmapSource::new(filename).unwrap();
+        assert_eq!(source.load_as(None).unwrap(), Endian::Big);
+        assert_eq!(source.as_ref(), &values[..]);
+        source.load_as(Some(endian::NATIVE)).unwrap();
+        assert!(source.is_mapped());
+
+        assert!(MmapSource::<f32>::new(filename).is_ok());
+        fs::write(filename, [0u8; 12]).unwrap();
+        assert!(MmapSource::<f64>::new(filename).is_err());
+        fs::remove_file(filename).unwrap();
+    }
+}
@@ -0,0 +1,503 @@
+/// NetCDF classic files
+///
+/// Reader of the classic (CDF-1) and 64-bit offset (CDF-2) formats, which
+/// store a big endian header describing dimensions, attributes and
+/// variables, followed by the data of the variables. Variables along the
+/// unlimited (record) dimension are interleaved record by record.
+///
+/// Every `float` and `double` variable is compressed into its own pzip file,
+/// or into a single file with several variables (see `archive`), with the
+/// shape of its dimensions, folded like NumPy arrays (see `codec::fold`).
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::archive;
+use super::batch::{Description, Entry, Outcome};
+use super::codec::{self, Value};
+use super::config::{FileType, Predictor};
+use super::endian::{self, Endian};
+use super::error::{Error, Result};
+use super::npy;
+use super::{compress, Options, Pipeline, Shape};
+
+const STREAMING: u32 = 0xFFFF_FFFF;
+const NC_DIMENSION: u32 = 0x0A;
+const NC_VARIABLE: u32 = 0x0B;
+const NC_ATTRIBUTE: u32 = 0x0C;
+
+/// External type of attributes and variables
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NcType {
+    Byte,
+    Char,
+    Short,
+    Int,
+    Float,
+    Double,
+}
+
+impl NcType {
+    fn from_u32(tag: u32) -> Result<NcType> {
+        Ok(match tag {
+            1 => NcType::Byte,
+            2 => NcType::Char,
+            3 => NcType::Short,
+            4 => NcType::Int,
+            5 => NcType::Float,
+            6 => NcType::Double,
+            v => return Err(invalid(&format!("unknown type {}", v))),
+        })
+    }
+
+    /// Size of a single value in bytes
+    pub fn size(self) -> usize {
+        match self {
+            NcType::Byte | NcType::Char => 1,
+            NcType::Short => 2,
+            NcType::Int | NcType::Float => 4,
+            NcType::Double => 8,
+        }
+    }
+
+    fn filetype(self) -> Option<FileType> {
+        match self {
+            NcType::Float => Some(FileType::F32),
+            NcType::Double => Some(FileType::F64),
+            _ => None,
+        }
+    }
+}
+
+/// Value of an attribute, numbers of every type are converted to `f64`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Attribute {
+    Text(String),
+    Numbers(Vec<f64>),
+}
+
+impl Attribute {
+    /// Value as stored in the attributes of a pzip container
+    pub fn text(&self) -> String {
+        match self {
+            Attribute::Text(text) => text.clone(),
+            Attribute::Numbers(numbers) => numbers.iter().map(|n| n.to_string()).collect::<Vec<String>>().join(", "),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dimension {
+    pub name: String,
+    /// Number of records for the unlimited dimension
+    pub length: usize,
+    pub unlimited: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    /// Indices into `NetCdf::dimensions`
+    pub dimensions: Vec<usize>,
+    pub attributes: Vec<(String, Attribute)>,
+    pub nctype: NcType,
+    /// Size of the variable (of a single record for record variables) in bytes
+    pub vsize: u64,
+    pub begin: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetCdf {
+    path: PathBuf,
+    /// 1: classic, 2: 64-bit offset
+    pub version: u8,
+    pub records: usize,
+    pub dimensions: Vec<Dimension>,
+    pub attributes: Vec<(String, Attribute)>,
+    pub variables: Vec<Variable>,
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::Corrupt(format!("Invalid NetCDF file: {}", msg))
+}
+
+/// Skips the padding of `n` bytes to the next multiple of four.
+fn padding<R: Read>(r: &mut R, n: usize) -> Result<()> {
+    let mut pad = [0u8; 3];
+    r.read_exact(&mut pad[..(4 - n % 4) % 4])?;
+    Ok(())
+}
+
+fn read_count<R: Read>(r: &mut R) -> Result<usize> {
+    Ok(r.read_u32::<BigEndian>()? as usize)
+}
+
+fn read_name<R: Read>(r: &mut R) -> Result<String> {
+    let n = read_count(r)?;
+    let mut name = vec![0u8; n];
+    r.read_exact(&mut name)?;
+    padding(r, n)?;
+    String::from_utf8(name).map_err(|_| invalid("name is not UTF-8"))
+}
+
+/// Number of elements of a list with `tag`, zero if the list is absent.
+fn read_list<R: Read>(r: &mut R, tag: u32) -> Result<usize> {
+    let found = r.read_u32::<BigEndian>()?;
+    let n = read_count(r)?;
+    match found {
+        0 if n == 0 => Ok(0),
+        t if t == tag => Ok(n),
+        _ => Err(invalid("unexpected list in header")),
+    }
+}
+
+fn read_attributes<R: Read>(r: &mut R) -> Result<Vec<(String, Attribute)>> {
+    let n = read_list(r, NC_ATTRIBUTE)?;
+    let mut attributes = Vec::new();
+    for _ in 0..n {
+        let name = read_name(r)?;
+        let nctype = NcType::from_u32(r.read_u32::<BigEndian>()?)?;
+        let count = read_count(r)?;
+        let length = count.checked_mul(nctype.size()).ok_or_else(|| invalid("attribute too large"))?;
+        let mut bytes = Vec::new();
+        r.take(length as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != length {
+            return Err(invalid("truncated attribute"));
+        }
+        padding(r, length)?;
+        let value = match nctype {
+            NcType::Char => Attribute::Text(String::from_utf8_lossy(&bytes).trim_end_matches('\0').to_string()),
+            NcType::Byte => Attribute::Numbers(bytes.iter().map(|&b| f64::from(b as i8)).collect()),
+            NcType::Short => Attribute::Numbers(bytes.chunks(2).map(|b| f64::from(BigEndian::read_i16(b))).collect()),
+            NcType::Int => Attribute::Numbers(bytes.chunks(4).map(|b| f64::from(BigEndian::read_i32(b))).collect()),
+            NcType::Float => Attribute::Numbers(bytes.chunks(4).map(|b| f64::from(BigEndian::read_f32(b))).collect()),
+            NcType::Double => Attribute::Numbers(bytes.chunks(8).map(BigEndian::read_f64).collect()),
+        };
+        attributes.push((name, value));
+    }
+    Ok(attributes)
+}
+
+impl NetCdf {
+    /// Reads the header of the NetCDF file at `path`.
+    pub fn open(path: &Path) -> Result<NetCdf> {
+        let mut r = BufReader::new(fs::File::open(path)?);
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic[..3] != b"CDF" {
+            return Err(Error::Corrupt(format!("{} is not a NetCDF classic file", path.display())));
+        }
+        let version = magic[3];
+        if version != 1 && version != 2 {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let records = r.read_u32::<BigEndian>()?;
+
+        let mut dimensions = Vec::new();
+        for _ in 0..read_list(&mut r, NC_DIMENSION)? {
+            let name = read_name(&mut r)?;
+            let length = read_count(&mut r)?;
+            dimensions.push(Dimension { name, length, unlimited: length == 0 });
+        }
+        let attributes = read_attributes(&mut r)?;
+
+        let mut variables = Vec::new();
+        for _ in 0..read_list(&mut r, NC_VARIABLE)? {
+            let name = read_name(&mut r)?;
+            let ids = (0..read_count(&mut r)?).map(|_| read_count(&mut r)).collect::<Result<Vec<usize>>>()?;
+            if ids.iter().any(|&id| id >= dimensions.len()) {
+                return Err(invalid(&format!("unknown dimension of {}", name)));
+            }
+            let attributes = read_attributes(&mut r)?;
+            let nctype = NcType::from_u32(r.read_u32::<BigEndian>()?)?;
+            let vsize = u64::from(r.read_u32::<BigEndian>()?);
+            let begin = if version == 1 { u64::from(r.read_u32::<BigEndian>()?) } else { r.read_u64::<BigEndian>()? };
+            variables.push(Variable { name, dimensions: ids, attributes, nctype, vsize, begin });
+        }
+
+        let mut file = NetCdf { path: path.to_path_buf(), version, records: 0, dimensions, attributes, variables };
+        file.records = if records == STREAMING { file.streamed_records()? } else { records as usize };
+        for dim in file.dimensions.iter_mut().filter(|d| d.unlimited) {
+            dim.length = file.records;
+        }
+        Ok(file)
+    }
+
+    /// Number of records of a file written without updating the header
+    fn streamed_records(&self) -> Result<usize> {
+        let size = fs::metadata(&self.path)?.len();
+        let first = self.variables.iter().filter(|v| self.is_record(v)).map(|v| v.begin).min();
+        match (first, self.record_size()) {
+            (Some(begin), step) if step > 0 && size >= begin => Ok(((size - begin) / step) as usize),
+            _ => Ok(0),
+        }
+    }
+
+    pub fn is_record(&self, var: &Variable) -> bool {
+        var.dimensions.first().is_some_and(|&id| self.dimensions[id].unlimited)
+    }
+
+    /// Distance between two records of a record variable in bytes
+    fn record_size(&self) -> u64 {
+        let records: Vec<&Variable> = self.variables.iter().filter(|v| self.is_record(v)).collect();
+        match records.as_slice() {
+            // a single record variable is not padded
+            [var] => (self.values_per_record(var) * var.nctype.size()) as u64,
+            _ => records.iter().map(|v| v.vsize).sum(),
+        }
+    }
+
+    fn values_per_record(&self, var: &Variable) -> usize {
+        let skip = self.is_record(var) as usize;
+        var.dimensions[skip..].iter().map(|&id| self.dimensions[id].length).product()
+    }
+
+    /// Lengths of the dimensions of `var`, the record dimension first.
+    pub fn shape(&self, var: &Variable) -> Vec<usize> {
+        var.dimensions.iter().map(|&id| self.dimensions[id].length).collect()
+    }
+
+    /// Names of the dimensions of `var`
+    pub fn dimension_names(&self, var: &Variable) -> Vec<&str> {
+        var.dimensions.iter().map(|&id| self.dimensions[id].name.as_str()).collect()
+    }
+
+    /// Values of `var`, which has to be of type `T`.
+    pub fn read<T: Value>(&self, var: &Variable) -> Result<Vec<T>> {
+        if var.nctype.filetype() != Some(T::FILETYPE) {
+            return Err(Error::InvalidInput(format!("{} is of type {:?}", var.name, var.nctype)));
+        }
+        let per_record = self.values_per_record(var) * T::BYTES;
+        let (count, step) = if self.is_record(var) { (self.records, self.record_size()) } else { (1, 0) };
+        let mut file = BufReader::new(fs::File::open(&self.path)?);
+        let mut bytes = vec![0u8; per_record * count];
+        for (r, record) in bytes.chunks_mut(per_record.max(1)).enumerate() {
+            file.seek(SeekFrom::Start(var.begin + r as u64 * step))?;
+            file.read_exact(record).map_err(|_| invalid(&format!("data of {} is truncated", var.name)))?;
+        }
+        Ok(endian::values(&bytes, Endian::Big))
+    }
+}
+
+/// Compresses every `float` and `double` variable of the NetCDF file `input`
+/// into `output/<variable>.pzip`, or into the single file `output` if it
+/// ends with `.pzip`. Other variables are skipped.
+pub fn compress_file(input: &Path, output: &Path, predictor: &Predictor, pipeline: &Pipeline) -> Result<Vec<Entry>> {
+    let file = NetCdf::open(input)?;
+    let mut single = if output.extension().is_some_and(|e| e == "pzip") {
+        Some(archive::Writer::new(BufWriter::new(fs::File::create(output)?))?)
+    } else {
+        fs::create_dir_all(output)?;
+        None
+    };
+    let mut entries = Vec::new();
+    for var in &file.variables {
+        let name = PathBuf::from(&var.name);
+        let filetype = match var.nctype.filetype() {
+            Some(filetype) => filetype,
+            None => {
+                let outcome = Outcome::Skipped(format!("values of type {:?} are not compressed", var.nctype));
+                entries.push(Entry { file: name, description: None, outcome });
+                continue;
+            }
+        };
+        let shape = match codec::fold(&file.shape(var)) {
+            Ok(shape) => shape,
+            Err(e) => {
+                entries.push(Entry { file: name, description: None, outcome: Outcome::Skipped(e.to_string()) });
+                continue;
+            }
+        };
+        let description = Description { filetype, endian: Some(Endian::Big), shape };
+        let mut options = Options { predictor: predictor.clone(), pipeline: *pipeline, endian: Endian::Big, ..Options::default() };
+        options.attributes = var.attributes.iter().map(|(key, value)| (key.clone(), value.text())).collect();
+        options.attributes.extend(npy::attributes(&file.shape(var)));
+        options.attributes.insert("dimension_names".to_string(), file.dimension_names(var).join(", "));
+        if let Some(filename) = input.file_name() {
+            options.attributes.insert("filename".to_string(), filename.to_string_lossy().to_string());
+        }
+        let start = Instant::now();
+        let result = match filetype {
+            FileType::F32 => compress_variable::<f32>(&file, var, &description, &options),
+            FileType::F64 => compress_variable::<f64>(&file, var, &description, &options),
+        };
+        let written = result.and_then(|(raw, container)| {
+            match &mut single {
+                Some(writer) => writer.add(&var.name, &container)?,
+                None => fs::write(output.join(format!("{}.pzip", var.name)), &container)?,
+            }
+            Ok((raw, container.len() as u64))
+        });
+        let outcome = match written {
+            Ok((raw, compressed)) => Outcome::Compressed { raw, compressed, seconds: start.elapsed().as_secs_f64() },
+            Err(e) => Outcome::Failed(e.to_string()),
+        };
+        entries.push(Entry { file: name, description: Some(description), outcome });
+    }
+    if let Some(writer) = single {
+        writer.finish()?;
+    }
+    Ok(entries)
+}
+
+/// Compresses a single variable and returns the number of raw bytes and
+/// its container.
+fn compress_variable<T: Value>(
+    file: &NetCdf,
+    var: &Variable,
+    description: &Description,
+    options: &Options,
+) -> Result<(u64, Vec<u8>)> {
+    let data: Vec<T> = file.read(var)?;
+    let p = &description.shape;
+    let shape = Shape { x: p.x as usize, y: p.y as usize, z: p.z as usize };
+    let container = compress(&data, shape, options)?;
+    Ok(((data.len() * T::BYTES) as u64, container))
+}
+
+#[allow(unused_imports, dead_code)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+
+    fn name(out: &mut Vec<u8>, name: &str) {
+        out.write_u32::<BigEndian>(name.len() as u32).unwrap();
+        out.extend_from_slice(name.as_bytes());
+        out.resize(out.len() + (4 - name.len() % 4) % 4, 0);
+    }
+
+    fn header(out: &mut Vec<u8>, tag: u32, n: u32) {
+        out.write_u32::<BigEndian>(tag).unwrap();
+        out.write_u32::<BigEndian>(n).unwrap();
+    }
+
+    /// CDF-1 file with the record variable `t(time, lat, lon)` (float), the
+    /// fixed variables `h(lat, lon)` (double) and `n(lon)` (short) and two records
+    fn example(records: u32) -> Vec<u8> {
+        let mut out = b"CDF\x01".to_vec();
+        out.write_u32::<BigEndian>(records).unwrap();
+        header(&mut out, NC_DIMENSION, 3);
+        for (dim, length) in &[("time", 0), ("lat", 3), ("lon", 4)] {
+            name(&mut out, dim);
+            out.write_u32::<BigEndian>(*length).unwrap();
+        }
+        header(&mut out, NC_ATTRIBUTE, 1);
+        name(&mut out, "title");
+        out.write_u32::<BigEndian>(2).unwrap();
+        out.write_u32::<BigEndian>(5).unwrap();
+        out.extend_from_slice(b"test\0\0\0\0");
+
+        header(&mut out, NC_VARIABLE, 3);
+        let variables: [(&str, &[u32], u32, u32); 3] = [("h", &[1, 2], 6, 96), ("n", &[2], 3, 8), ("t", &[0, 1, 2], 5, 48)];
+        // size of the header, the data of h, n and t follows
+        let fixed = 240;
+        let begins = [fixed, fixed + 96, fixed + 104];
+        for ((var, dims, nctype, vsize), begin) in variables.iter().zip(begins.iter()) {
+            name(&mut out, var);
+            out.write_u32::<BigEndian>(dims.len() as u32).unwrap();
+            for d in dims.iter() {
+                out.write_u32::<BigEndian>(*d).unwrap();
+            }
+            if *var == "t" {
+                header(&mut out, NC_ATTRIBUTE, 1);
+                name(&mut out, "scale");
+                out.write_u32::<BigEndian>(5).unwrap();
+                out.write_u32::<BigEndian>(1).unwrap();
+                out.write_f32::<BigEndian>(0.5).unwrap();
+            } else {
+                header(&mut out, 0, 0);
+            }
+            out.write_u32::<BigEndian>(*nctype).unwrap();
+            out.write_u32::<BigEndian>(*vsize).unwrap();
+            out.write_u32::<BigEndian>(*begin).unwrap();
+        }
+        assert_eq!(out.len(), fixed as usize);
+        for i in 0..12 {
+            out.write_f64::<BigEndian>(100.0 + i as f64).unwrap();
+        }
+        for i in 0..4 {
+            out.write_i16::<BigEndian>(i).unwrap();
+        }
+        for r in 0..2 {
+            for i in 0..12 {
+                out.write_f32::<BigEndian>(r as f32 * 10.0 + i as f32 * 0.5).unwrap();
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn read_classic_file() {
+        let path = &std::env::temp_dir().join(format!("pzip_netcdf_read_{}.nc", std::process::id()));
+        fs::write(path, example(2)).unwrap();
+        let file = NetCdf::open(path);
+        fs::write(path, example(STREAMING)).unwrap();
+        let streamed = NetCdf::open(path).unwrap();
+        let file = file.unwrap();
+        assert_eq!(file.records, 2);
+        assert_eq!(streamed.records, 2);
+        assert_eq!(file.attributes, vec![("title".to_string(), Attribute::Text("test".to_string()))]);
+        let names: Vec<&str> = file.variables.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["h", "n", "t"]);
+
+        let t = &file.variables[2];
+        assert!(file.is_record(t));
+        assert_eq!(file.shape(t), vec![2, 3, 4]);
+        assert_eq!(file.dimension_names(t), vec!["time", "lat", "lon"]);
+        assert_eq!(t.attributes, vec![("scale".to_string(), Attribute::Numbers(vec![0.5]))]);
+        let values: Vec<f32> = file.read(t).unwrap();
+        assert_eq!(values[13], 10.5);
+        let values: Vec<f64> = file.read(&file.variables[0]).unwrap();
+        assert_eq!(values, (0..12).map(|i| 100.0 + i as f64).collect::<Vec<f64>>());
+        assert!(file.read::<f32>(&file.variables[1]).is_err());
+        fs::remove_file(path).unwrap();
+
+        fs::write(path, b"CDF\x05\0\0\0\0").unwrap();
+        assert!(matches!(NetCdf::open(path), Err(Error::UnsupportedVersion(5))));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn one_file_per_variable() {
+        let path = &std::env::temp_dir().join(format!("pzip_netcdf_compress_{}.nc", std::process::id()));
+        let out = &std::env::temp_dir().join(format!("pzip_netcdf_compress_{}", std::process::id()));
+        let _ = fs::remove_dir_all(out);
+        fs::write(path, example(2)).unwrap();
+        let entries = compress_file(path, out, &Predictor::Lorenz, &Pipeline::default()).unwrap();
+        assert!(matches!(entries[0].outcome, Outcome::Compressed { raw: 96, .. }));
+        assert!(matches!(entries[1].outcome, Outcome::Skipped(_)));
+        assert!(matches!(entries[2].outcome, Outcome::Compressed { raw: 96, .. }));
+        assert_eq!(entries[2].description.unwrap().shape, super::super::Position { x: 4, y: 3, z: 2 });
+
+        let file = NetCdf::open(path).unwrap();
+        let (values, _) = super::super::decompress_file::<f32>(&out.join("t.pzip").to_string_lossy()).unwrap();
+        assert_eq!(values, file.read::<f32>(&file.variables[2]).unwrap());
+        fs::remove_file(path).unwrap();
+        fs::remove_dir_all(out).unwrap();
+    }
+
+    #[test]
+    fn all_variables_in_one_file() {
+        let path = &std::env::temp_dir().join(format!("pzip_netcdf_single_{}.nc", std::process::id()));
+        let out = &std::env::temp_dir().join(format!("pzip_netcdf_single_{}.pzip", std::process::id()));
+        fs::write(path, example(2)).unwrap();
+        let entries = compress_file(path, out, &Predictor::LastValue, &Pipeline::default()).unwrap();
+        assert!(matches!(entries[1].outcome, Outcome::Skipped(_)));
+
+        let file = NetCdf::open(path).unwrap();
+        let mut single = archive::Archive::open(&out.to_string_lossy()).unwrap();
+        assert_eq!(single.names(), vec![file.variables[0].name.as_str(), "t"]);
+        let header = single.header("t").unwrap();
+        assert_eq!(header.endian, Endian::Big);
+        assert_eq!(header.attributes["dimensions"], "2x3x4");
+        assert_eq!(header.attributes["dimension_names"], "time, lat, lon");
+        assert_eq!(header.attributes["scale"], "0.5");
+        assert_eq!(header.attributes["filename"], format!("pzip_netcdf_single_{}.nc", std::process::id()));
+        let (values, _) = single.read::<f32>("t").unwrap();
+        assert_eq!(values, file.read::<f32>(&file.variables[2]).unwrap());
+        fs::remove_file(path).unwrap();
+        fs::remove_file(out).unwrap();
+    }
+}
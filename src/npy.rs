@@ -0,0 +1,419 @@
+/// NumPy `.npy` and `.npz` files
+///
+/// Type, byte order and shape of the values are read from the header of the
+/// file. The last three dimensions are z, y and x, further leading
+/// dimensions are folded into z and kept in the attribute `DIMENSIONS` of
+/// the compressed data. Only C ordered arrays of `f4` and `f8` are
+/// supported. An `.npz` archive has to contain a single array and is written
+/// with the array `arr_0` (uncompressed, like `numpy.savez`).
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::marker::PhantomData;
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use super::codec::{self, Value};
+use super::config::FileType;
+use super::endian::{self, Endian};
+use super::error::{Error, Result};
+use super::position::Position;
+use super::testing::{CompressedFile, FileToBeCompressed};
+
+pub const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// Alignment of the data after the header, as used by NumPy
+const ALIGN: usize = 64;
+
+/// Attribute with the dimensions of the original array, e.g. `2x3x4x5`
+pub const DIMENSIONS: &str = "dimensions";
+
+/// Checks if `path` is named like a NumPy file.
+pub fn is_npy(path: &str) -> bool {
+    path.ends_with(".npy") || path.ends_with(".npz")
+}
+
+fn is_npz(path: &str) -> bool {
+    path.ends_with(".npz")
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::Corrupt(format!("Invalid npy header: {}", msg))
+}
+
+/// Text following `'key':` in the dictionary of the header
+fn entry<'a>(text: &'a str, key: &str) -> Result<&'a str> {
+    ["'", "\""]
+        .iter()
+        .filter_map(|q| text.find(&format!("{}{}{}:", q, key, q)).map(|pos| pos + key.len() + 3))
+        .next()
+        .map(|pos| text[pos..].trim_start())
+        .ok_or_else(|| invalid(&format!("missing '{}'", key)))
+}
+
+/// Header of an array
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub filetype: FileType,
+    pub endian: Endian,
+    pub shape: Vec<usize>,
+}
+
+impl Header {
+    pub fn read<R: Read>(r: &mut R) -> Result<Header> {
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic[..6] != MAGIC {
+            return Err(Error::Corrupt("Not a npy file".to_string()));
+        }
+        let length = match magic[6] {
+            1 => {
+                let mut n = [0u8; 2];
+                r.read_exact(&mut n)?;
+                u16::from_le_bytes(n) as usize
+            }
+            2 | 3 => {
+                let mut n = [0u8; 4];
+                r.read_exact(&mut n)?;
+                u32::from_le_bytes(n) as usize
+            }
+            v => return Err(Error::UnsupportedVersion(v)),
+        };
+        let mut text = vec![0u8; length];
+        r.read_exact(&mut text)?;
+        let text = String::from_utf8(text).map_err(|_| invalid("not UTF-8"))?;
+        Header::parse(&text)
+    }
+
+    /// Parses the dictionary of the header, e.g.
+    /// `{'descr': '<f4', 'fortran_order': False, 'shape': (90, 351, 901), }`.
+    fn parse(text: &str) -> Result<Header> {
+        let descr = entry(text, "descr")?;
+        let descr = descr.get(1..).and_then(|d| d.split(['\'', '"']).next()).unwrap_or("");
+        let (endian, filetype) = match (descr.get(..1), descr.get(1..)) {
+            (Some("<"), Some(t)) => (Endian::Little, t),
+            (Some(">"), Some(t)) => (Endian::Big, t),
+            (Some("="), Some(t)) if cfg!(target_endian = "little") => (Endian::Little, t),
+            (Some("="), Some(t)) => (Endian::Big, t),
+            _ => (Endian::Little, descr),
+        };
+        let filetype = match filetype {
+            "f4" => FileType::F32,
+            "f8" => FileType::F64,
+            _ => return Err(Error::InvalidInput(format!("Unsupported dtype '{}', expected f4 or f8", descr))),
+        };
+        if entry(text, "fortran_order")?.starts_with("True") {
+            return Err(Error::InvalidInput("Fortran ordered arrays are not supported".to_string()));
+        }
+        let shape = entry(text, "shape")?;
+        let end = shape.find(')').ok_or_else(|| invalid("shape is not a tuple"))?;
+        let shape = shape[1..end]
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(|d| d.trim_end_matches('L').parse::<usize>().map_err(|_| invalid("invalid dimension")))
+            .collect::<Result<Vec<usize>>>()?;
+        let header = Header { filetype, endian, shape };
+        header.len()?;
+        Ok(header)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        let descr = match (self.endian, self.filetype) {
+            (Endian::Mixed, _) => return Err(Error::InvalidInput("Mixed endian can not be written to npy".to_string())),
+            (Endian::Little, FileType::F32) => "<f4",
+            (Endian::Little, FileType::F64) => "<f8",
+            (Endian::Big, FileType::F32) => ">f4",
+            (Endian::Big, FileType::F64) => ">f8",
+        };
+        let dims: Vec<String> = self.shape.iter().map(|d| d.to_string()).collect();
+        let shape = match dims.len() {
+            1 => format!("({},)", dims[0]),
+            _ => format!("({})", dims.join(", ")),
+        };
+        let mut text = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+        let (version, prefix) = if text.len() + 11 <= u16::MAX as usize { (1u8, 10) } else { (2u8, 12) };
+        let padding = (ALIGN - (prefix + text.len() + 1) % ALIGN) % ALIGN;
+        text.push_str(&" ".repeat(padding));
+        text.push('\n');
+        w.write_all(MAGIC)?;
+        w.write_all(&[version, 0])?;
+        if version == 1 {
+            w.write_all(&(text.len() as u16).to_le_bytes())?;
+        } else {
+            w.write_all(&(text.len() as u32).to_le_bytes())?;
+        }
+        w.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    /// Number of values of the array
+    pub fn len(&self) -> Result<usize> {
+        count(&self.shape).ok_or_else(|| Error::BadShape(format!("Too many values in {:?}", self.shape)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shape.contains(&0)
+    }
+
+    /// Shape of the array with leading dimensions folded into z.
+    pub fn position(&self) -> Result<Position> {
+        codec::fold(&self.shape)
+    }
+}
+
+fn count(dims: &[usize]) -> Option<usize> {
+    dims.iter().try_fold(1usize, |acc, &d| acc.checked_mul(d))
+}
+
+/// Dimensions of an array of `shape`, without leading dimensions of size 1.
+pub fn dimensions(shape: &Position) -> Vec<usize> {
+    let mut dims = vec![shape.z as usize, shape.y as usize, shape.x as usize];
+    while dims.len() > 1 && dims[0] == 1 {
+        dims.remove(0);
+    }
+    dims
+}
+
+/// Attributes recording the dimensions `dims` of an array.
+pub fn attributes(dims: &[usize]) -> BTreeMap<String, String> {
+    let text: Vec<String> = dims.iter().map(|d| d.to_string()).collect();
+    let mut attributes = BTreeMap::new();
+    attributes.insert(DIMENSIONS.to_string(), text.join("x"));
+    attributes
+}
+
+/// Dimensions of the original array of data compressed with `shape`, taken
+/// from the attributes if they match the shape.
+pub fn original_dimensions(attributes: &BTreeMap<String, String>, shape: &Position) -> Vec<usize> {
+    let stored = attributes
+        .get(DIMENSIONS)
+        .and_then(|text| text.split('x').map(|d| d.parse::<usize>().ok()).collect::<Option<Vec<usize>>>());
+    match stored {
+        Some(dims) if count(&dims).is_some() && codec::fold(&dims).ok().as_ref() == Some(shape) => dims,
+        _ => dimensions(shape),
+    }
+}
+
+/// Header of the array in the NumPy file `filename`
+pub fn header(filename: &str) -> Result<Header> {
+    if is_npz(filename) {
+        Header::read(&mut npz_array(filename)?)
+    } else {
+        Header::read(&mut fs::File::open(filename)?)
+    }
+}
+
+/// Reader of the single array inside of an npz archive
+fn npz_array(filename: &str) -> Result<Cursor<Vec<u8>>> {
+    let zipped = |e: zip::result::ZipError| Error::Corrupt(format!("Invalid npz file: {}", e));
+    let mut archive = ZipArchive::new(fs::File::open(filename)?).map_err(zipped)?;
+    if archive.len() != 1 {
+        let msg = format!("{} contains {} arrays, expected a single one", filename, archive.len());
+        return Err(Error::InvalidInput(msg));
+    }
+    let mut array = archive.by_index(0).map_err(zipped)?;
+    let mut bytes = Vec::new();
+    array.read_to_end(&mut bytes)?;
+    Ok(Cursor::new(bytes))
+}
+
+pub struct NpySource<T> {
+    file: Box<dyn Read>,
+    pub header: Header,
+    pub data: Vec<T>,
+}
+
+impl<T> AsRef<[T]> for NpySource<T> {
+    fn as_ref(&self) -> &[T] {
+        &self.data
+    }
+}
+
+impl<T: Value> NpySource<T> {
+    pub fn new(filename: &str) -> Result<Self> {
+        if is_npz(filename) {
+            NpySource::from_reader(npz_array(filename)?)
+        } else {
+            NpySource::from_reader(fs::File::open(filename)?)
+        }
+    }
+
+    /// Reads the header of a `.npy` stream, e.g. from stdin.
+    pub fn from_reader<R: Read + 'static>(reader: R) -> Result<Self> {
+        let mut file: Box<dyn Read> = Box::new(reader);
+        let header = Header::read(&mut file)?;
+        if header.filetype != T::FILETYPE {
+            return Err(Error::InvalidInput(format!("File contains {:?} data", header.filetype)));
+        }
+        Ok(NpySource { file, header, data: Vec::new() })
+    }
+}
+
+impl<T: Value> FileToBeCompressed<T> for NpySource<T> {
+    fn ix(&self, position: usize) -> &T {
+        &self.data[position]
+    }
+
+    fn get(&mut self) -> Result<T> {
+        let mut buf = [0u8; 8];
+        self.file.read_exact(&mut buf[..T::BYTES])?;
+        Ok(endian::values(&buf[..T::BYTES], self.header.endian)[0])
+    }
+
+    fn load(&mut self) -> Result<usize> {
+        self.load_as(None)?;
+        Ok(self.data.len())
+    }
+
+    /// The byte order of the header is always used.
+    fn load_as(&mut self, _endian: Option<Endian>) -> Result<Endian> {
+        let mut bytes = Vec::new();
+        self.file.read_to_end(&mut bytes)?;
+        let expected = self.header.len()?.checked_mul(T::BYTES);
+        if Some(bytes.len()) != expected {
+            let msg = format!("{} bytes do not match the shape {:?}", bytes.len(), self.header.shape);
+            return Err(Error::SizeMismatch(msg));
+        }
+        self.data = endian::values(&bytes, self.header.endian);
+        Ok(self.header.endian)
+    }
+}
+
+/// Writes all values as a single array on `flush`.
+///
+/// The shape defaults to a one dimensional array of all values.
+pub struct NpySink<T> {
+    filename: String,
+    pub endian: Endian,
+    pub shape: Option<Vec<usize>>,
+    values: Vec<T>,
+    pub data: PhantomData<T>,
+}
+
+impl<T> NpySink<T> {
+    pub fn new(filename: &str) -> Result<Self> {
+        fs::File::create(filename)?;
+        Ok(NpySink {
+            filename: filename.to_string(),
+            endian: Endian::Little,
+            shape: None,
+            values: Vec::new(),
+            data: PhantomData,
+        })
+    }
+}
+
+impl<T: Value> CompressedFile<T> for NpySink<T> {
+    fn flush(&mut self) -> Result<()> {
+        let header = Header {
+            filetype: T::FILETYPE,
+            endian: self.endian,
+            shape: self.shape.clone().unwrap_or_else(|| vec![self.values.len()]),
+        };
+        if header.len()? != self.values.len() {
+            let msg = format!("{} values do not match the shape {:?}", self.values.len(), header.shape);
+            return Err(Error::SizeMismatch(msg));
+        }
+        let mut bytes = Vec::with_capacity(128 + self.values.len() * T::BYTES);
+        header.write(&mut bytes)?;
+        bytes.extend(endian::bytes(&self.values, self.endian));
+
+        let file = fs::File::create(&self.filename)?;
+        if is_npz(&self.filename) {
+            let mut zip = ZipWriter::new(file);
+            let options = FileOptions::default().compression_method(CompressionMethod::Stored).large_file(true);
+            let zipped = |e: zip::result::ZipError| Error::Io(e.into());
+            zip.start_file("arr_0.npy", options).map_err(zipped)?;
+            zip.write_all(&bytes)?;
+            zip.finish().map_err(zipped)?;
+        } else {
+            let mut file = file;
+            file.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+
+    fn put(&mut self, value: T) -> Result<()> {
+        self.values.push(value);
+        Ok(())
+    }
+
+    fn put_all(&mut self, values: &[T]) -> Result<()> {
+        self.values.extend_from_slice(values);
+        Ok(())
+    }
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_of_numpy() {
+        // np.save of np.zeros((2, 3, 4, 5), dtype='>f8')
+        let text = "{'descr': '>f8', 'fortran_order': False, 'shape': (2, 3, 4, 5), }";
+        let header = Header::parse(text).unwrap();
+        assert_eq!(header, Header { filetype: FileType::F64, endian: Endian::Big, shape: vec![2, 3, 4, 5] });
+        assert_eq!(header.position().unwrap(), Position { x: 5, y: 4, z: 6 });
+
+        let header = Header::parse("{'descr': '<f4', 'fortran_order': False, 'shape': (7,), }").unwrap();
+        assert_eq!(header.position().unwrap(), Position { x: 7, y: 1, z: 1 });
+        assert!(Header::parse("{'descr': '<i4', 'fortran_order': False, 'shape': (7,), }").is_err());
+        assert!(Header::parse("{'descr': '<f4', 'fortran_order': True, 'shape': (7, 2), }").is_err());
+        let empty = Header::parse("{'descr': '<f4', 'fortran_order': False, 'shape': (0, 2), }").unwrap();
+        assert!(matches!(empty.position(), Err(Error::BadShape(_))));
+        let huge = "{'descr': '<f4', 'fortran_order': False, 'shape': (4294967296, 4294967296, 2), }";
+        assert!(matches!(Header::parse(huge), Err(Error::BadShape(_))));
+    }
+
+    #[test]
+    fn header_roundtrip() {
+        for shape in &[vec![], vec![3], vec![90, 351, 901]] {
+            let header = Header { filetype: FileType::F32, endian: Endian::Little, shape: shape.clone() };
+            let mut buf = Vec::new();
+            header.write(&mut buf).unwrap();
+            assert_eq!(buf.len() % ALIGN, 0);
+            assert_eq!(buf.last(), Some(&b'\n'));
+            assert_eq!(Header::read(&mut buf.as_slice()).unwrap(), header);
+        }
+        let mut buf = Vec::new();
+        Header { filetype: FileType::F64, endian: Endian::Big, shape: vec![3] }.write(&mut buf).unwrap();
+        assert_eq!(&buf[..10], b"\x93NUMPY\x01\x00\x76\x00");
+        assert!(String::from_utf8_lossy(&buf).contains("{'descr': '>f8', 'fortran_order': False, 'shape': (3,), }"));
+    }
+
+    #[test]
+    fn write_and_read_arrays() {
+        let values: Vec<f32> = (0..24).map(|i| i as f32 * 0.5).collect();
+        for extension in &["npy", "npz"] {
+            let path = &std::env::temp_dir().join(format!("pzip_npy_{}.{}", std::process::id(), extension)).to_string_lossy().into_owned();
+            let mut sink: NpySink<f32> = NpySink::new(path).unwrap();
+            sink.shape = Some(vec![2, 3, 4]);
+            sink.endian = Endian::Big;
+            sink.put_all(&values).unwrap();
+            sink.flush().unwrap();
+
+            let mut source: NpySource<f32> = NpySource::new(path).unwrap();
+            assert_eq!(source.header.shape, vec![2, 3, 4]);
+            assert_eq!(source.load_as(Some(Endian::Little)).unwrap(), Endian::Big);
+            assert_eq!(source.data, values);
+            assert!(NpySource::<f64>::new(path).is_err());
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn shapes_of_decompressed_data() {
+        assert_eq!(dimensions(&Position { x: 4, y: 3, z: 2 }), vec![2, 3, 4]);
+        assert_eq!(dimensions(&Position { x: 4, y: 3, z: 1 }), vec![3, 4]);
+        assert_eq!(dimensions(&Position { x: 4, y: 1, z: 1 }), vec![4]);
+
+        let folded = Position { x: 5, y: 4, z: 6 };
+        assert_eq!(original_dimensions(&attributes(&[2, 3, 4, 5]), &folded), vec![2, 3, 4, 5]);
+        assert_eq!(original_dimensions(&attributes(&[1, 6, 4, 5]), &folded), vec![1, 6, 4, 5]);
+        assert_eq!(original_dimensions(&attributes(&[2, 3, 4, 4]), &folded), vec![6, 4, 5]);
+        assert_eq!(original_dimensions(&BTreeMap::new(), &folded), vec![6, 4, 5]);
+    }
+}
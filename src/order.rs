@@ -0,0 +1,289 @@
+/// Scan orders of the data
+///
+/// The data is stored in row-major order (z, y, x). Before the prediction the
+/// values can be rearranged into a different scan order. The predictor then
+/// works on the rearranged data, i.e. the neighbours of a cell are relative
+/// to the scanned and not the stored array.
+///
+/// - Linear: nested loops over the axes in any permutation, each possibly reversed
+/// - Serpentine: row-major, but every other row (and plane) is traversed backwards
+/// - Morton: Z-order curve over the (y, x) plane of every level
+/// - Hilbert: Hilbert curve over the (y, x) plane of every level
+///
+/// The space filling curves produce one long line per level. Neighbours in
+/// x-direction are the predecessors on the curve and neighbours in
+/// z-direction are the same cell on the previous level.
+use super::error::{Error, Result};
+use super::position::Position;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Order {
+    /// Axes from outermost to innermost loop and whether they are traversed backwards
+    Linear { axes: [Axis; 3], reversed: [bool; 3] },
+    Serpentine,
+    Morton,
+    Hilbert,
+}
+
+impl Default for Order {
+    fn default() -> Self {
+        Order::Linear {
+            axes: [Axis::Z, Axis::Y, Axis::X],
+            reversed: [false, false, false],
+        }
+    }
+}
+
+impl Order {
+    /// Shape of the data after it has been rearranged in this order. The
+    /// line of a level scanned by a curve must not exceed the extent of an axis.
+    pub fn scanned_shape(&self, shape: &Position) -> Result<Position> {
+        match self {
+            Order::Linear { axes, .. } => Ok(Position {
+                z: length(shape, axes[0]),
+                y: length(shape, axes[1]),
+                x: length(shape, axes[2]),
+            }),
+            Order::Serpentine => Ok(*shape),
+            Order::Morton | Order::Hilbert => match shape.x.checked_mul(shape.y) {
+                Some(x) => Ok(Position { z: shape.z, y: 1, x }),
+                None => Err(Error::BadShape(format!("Levels of {:?} are too large for {:?}", shape, self))),
+            },
+        }
+    }
+
+    /// Flat (row-major) indices of the stored data in the order of the scan.
+    pub fn indices(&self, shape: &Position) -> Vec<usize> {
+        match self {
+            Order::Linear { axes, reversed } => linear(shape, axes, reversed),
+            Order::Serpentine => serpentine(shape),
+            Order::Morton => curve(shape, morton),
+            Order::Hilbert => curve(shape, hilbert),
+        }
+    }
+
+    /// Rearranges the stored data into the scan order.
+    pub fn gather<T: Copy>(&self, data: &[T], shape: &Position) -> Vec<T> {
+        self.indices(shape).iter().map(|&i| data[i]).collect()
+    }
+
+    /// Rearranges scanned data back into the stored order.
+    pub fn scatter<T: Copy + Default>(&self, scanned: &[T], shape: &Position) -> Vec<T> {
+        let mut result = vec![T::default(); scanned.len()];
+        for (&i, &value) in self.indices(shape).iter().zip(scanned.iter()) {
+            result[i] = value;
+        }
+        result
+    }
+}
+
+fn length(shape: &Position, axis: Axis) -> i32 {
+    match axis {
+        Axis::X => shape.x,
+        Axis::Y => shape.y,
+        Axis::Z => shape.z,
+    }
+}
+
+fn flat(shape: &Position, z: i32, y: i32, x: i32) -> usize {
+    (z as usize * shape.y as usize + y as usize) * shape.x as usize + x as usize
+}
+
+fn linear(shape: &Position, axes: &[Axis; 3], reversed: &[bool; 3]) -> Vec<usize> {
+    let n = [length(shape, axes[0]), length(shape, axes[1]), length(shape, axes[2])];
+    let mut result = Vec::with_capacity(n.iter().map(|&v| v.max(0) as usize).product());
+    for i in 0..n[0] {
+        for j in 0..n[1] {
+            for k in 0..n[2] {
+                let mut coord = Position { x: 0, y: 0, z: 0 };
+                for (d, &v) in [i, j, k].iter().enumerate() {
+                    let v = if reversed[d] { n[d] - 1 - v } else { v };
+                    match axes[d] {
+                        Axis::X => coord.x = v,
+                        Axis::Y => coord.y = v,
+                        Axis::Z => coord.z = v,
+                    }
+                }
+                result.push(flat(shape, coord.z, coord.y, coord.x));
+            }
+        }
+    }
+    result
+}
+
+fn serpentine(shape: &Position) -> Vec<usize> {
+    let mut result = Vec::new();
+    let mut row = 0;
+    for z in 0..shape.z {
+        for j in 0..shape.y {
+            let y = if z % 2 == 1 { shape.y - 1 - j } else { j };
+            for i in 0..shape.x {
+                let x = if row % 2 == 1 { shape.x - 1 - i } else { i };
+                result.push(flat(shape, z, y, x));
+            }
+            row += 1;
+        }
+    }
+    result
+}
+
+/// Visits every level with a space filling curve over the next power of two
+/// square. The cells of the data are sorted by their distance `f(n, x, y)`
+/// along the curve, such that cells outside of the data are never visited.
+fn curve(shape: &Position, f: fn(u64, u64, u64) -> u64) -> Vec<usize> {
+    let (nx, ny) = (shape.x.max(0) as u64, shape.y.max(0) as u64);
+    let n = nx.max(ny).max(1).next_power_of_two();
+    let mut plane = Vec::with_capacity((nx * ny) as usize);
+    for y in 0..ny {
+        for x in 0..nx {
+            plane.push((f(n, x, y), (y * nx + x) as usize));
+        }
+    }
+    plane.sort_unstable();
+    let level = plane.len();
+    let mut result = Vec::with_capacity(level * shape.z.max(0) as usize);
+    for z in 0..shape.z.max(0) as usize {
+        result.extend(plane.iter().map(|&(_, i)| z * level + i));
+    }
+    result
+}
+
+/// Distance along the Z-order curve, the bits of x and y interleaved.
+fn morton(_n: u64, x: u64, y: u64) -> u64 {
+    let mut d = 0;
+    for bit in 0..32 {
+        d |= ((x >> bit) & 1) << (2 * bit);
+        d |= ((y >> bit) & 1) << (2 * bit + 1);
+    }
+    d
+}
+
+/// Distance along the Hilbert curve over the square with side `n`.
+fn hilbert(n: u64, mut x: u64, mut y: u64) -> u64 {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u64;
+        let ry = (y & s > 0) as u64;
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+#[allow(unused_imports, dead_code)]
+mod tests {
+    use super::*;
+
+    fn is_permutation(indices: &[usize], size: usize) -> bool {
+        let mut seen = vec![false; size];
+        for &i in indices {
+            if i >= size || seen[i] {
+                return false;
+            }
+            seen[i] = true;
+        }
+        indices.len() == size
+    }
+
+    #[test]
+    fn default_is_row_major() {
+        let shape = Position { x: 3, y: 2, z: 2 };
+        let result = Order::default().indices(&shape);
+        assert_eq!(result, (0..12).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn linear_permuted_and_reversed() {
+        let shape = Position { x: 3, y: 2, z: 1 };
+        let order = Order::Linear {
+            axes: [Axis::Z, Axis::X, Axis::Y],
+            reversed: [false, false, false],
+        };
+        assert_eq!(order.indices(&shape), vec![0, 3, 1, 4, 2, 5]);
+        assert_eq!(order.scanned_shape(&shape).unwrap(), Position { x: 2, y: 3, z: 1 });
+
+        let order = Order::Linear {
+            axes: [Axis::Z, Axis::Y, Axis::X],
+            reversed: [false, false, true],
+        };
+        assert_eq!(order.indices(&shape), vec![2, 1, 0, 5, 4, 3]);
+    }
+
+    #[test]
+    fn serpentine_rows() {
+        let shape = Position { x: 3, y: 2, z: 2 };
+        let result = Order::Serpentine.indices(&shape);
+        assert_eq!(result, vec![0, 1, 2, 5, 4, 3, 9, 10, 11, 8, 7, 6]);
+    }
+
+    #[test]
+    fn curves_visit_every_cell() {
+        let shape = Position { x: 5, y: 3, z: 2 };
+        for order in [Order::Morton, Order::Hilbert].iter() {
+            let indices = order.indices(&shape);
+            assert!(is_permutation(&indices, 30));
+            assert_eq!(order.scanned_shape(&shape).unwrap(), Position { x: 15, y: 1, z: 2 });
+        }
+        assert_eq!(Order::Morton.indices(&Position { x: 2, y: 2, z: 1 }), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn hilbert_steps_to_adjacent_cells() {
+        let shape = Position { x: 8, y: 8, z: 1 };
+        let indices = Order::Hilbert.indices(&shape);
+        for pair in indices.windows(2) {
+            let (a, b) = (pair[0] as i32, pair[1] as i32);
+            let distance = (a % 8 - b % 8).abs() + (a / 8 - b / 8).abs();
+            assert_eq!(distance, 1);
+        }
+    }
+
+    #[test]
+    fn curves_keep_their_order() {
+        // scans stored in existing files
+        let shape = Position { x: 5, y: 3, z: 1 };
+        assert_eq!(Order::Hilbert.indices(&shape), vec![0, 5, 6, 1, 2, 3, 8, 7, 12, 13, 11, 10, 14, 9, 4]);
+        assert_eq!(Order::Morton.indices(&shape), vec![0, 1, 5, 6, 2, 3, 7, 8, 10, 11, 12, 13, 4, 9, 14]);
+        let shape = Position { x: 3, y: 7, z: 2 };
+        let level = vec![0, 3, 4, 1, 2, 5, 8, 11, 10, 7, 6, 9, 12, 13, 16, 15, 18, 19, 20, 17, 14];
+        let expected: Vec<usize> = level.iter().chain(level.iter()).enumerate().map(|(i, &c)| c + i / 21 * 21).collect();
+        assert_eq!(Order::Hilbert.indices(&shape), expected);
+        let level = vec![0, 1, 3, 4, 2, 5, 6, 7, 9, 10, 8, 11, 12, 13, 15, 16, 14, 17, 18, 19, 20];
+        assert_eq!(&Order::Morton.indices(&shape)[..21], &level[..]);
+    }
+
+    #[test]
+    fn curves_over_long_lines() {
+        let shape = Position { x: 100_000, y: 1, z: 2 };
+        for order in [Order::Morton, Order::Hilbert].iter() {
+            assert!(is_permutation(&order.indices(&shape), 200_000));
+        }
+        let shape = Position { x: 70_000, y: 70_000, z: 1 };
+        assert!(matches!(Order::Morton.scanned_shape(&shape), Err(Error::BadShape(_))));
+    }
+
+    #[test]
+    fn gather_and_scatter() {
+        let shape = Position { x: 4, y: 3, z: 2 };
+        let data: Vec<f32> = (0..24).map(|v| v as f32).collect();
+        for order in [Order::Serpentine, Order::Morton, Order::Hilbert].iter() {
+            let scanned = order.gather(&data, &shape);
+            assert_eq!(order.scatter(&scanned, &shape), data);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::cmp::{Ordering, PartialEq, PartialOrd};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
@@ -30,19 +30,19 @@ impl PartialEq for Position {
 impl PartialOrd for Position {
     fn partial_cmp(&self, other: &Position) -> Option<Ordering> {
         if self.z > other.z {
-            return Some(Ordering::Greater);
-        } else if self.z < self.z {
-            return Some(Ordering::Less);
+            Some(Ordering::Greater)
+        } else if self.z < other.z {
+            Some(Ordering::Less)
         } else if self.y > other.y {
-            return Some(Ordering::Greater);
+            Some(Ordering::Greater)
         } else if self.y < other.y {
-            return Some(Ordering::Less);
+            Some(Ordering::Less)
         } else if self.x > other.x {
-            return Some(Ordering::Greater);
+            Some(Ordering::Greater)
         } else if self.x < other.x {
-            return Some(Ordering::Less);
+            Some(Ordering::Less)
         } else {
-            return Some(Ordering::Equal);
+            Some(Ordering::Equal)
         }
     }
 }
@@ -1,13 +1,13 @@
 use super::position::Position;
-use super::gen::{GeneratorIteratorAdapter};
 use super::ptraversal::{single_neighbours_grouped_no_ring, single_neighbours_grouped_with_ring};
 
 pub trait PredictorTrait<T> {
-    fn predict(&self, infospace: &Vec<T>) -> T;
+    fn predict(&self, infospace: &[T]) -> T;
     fn update(&mut self, information: T);
-    fn consume(&mut self, data: &Vec<T>, shape: &Position, ring: bool) -> Vec<T>;
+    fn consume(&mut self, data: &[T], shape: &Position, ring: bool) -> Vec<T>;
 }
 
+#[derive(Debug, Clone)]
 pub struct Ignorant<T> {
     pub coeff: Vec<T>,
     pub cells: Vec<Position>,
@@ -18,15 +18,15 @@ use std::iter::{Sum};
 #[allow(unused_assignments)]
 impl<T: AddAssign<<T as Mul>::Output>+Default+Copy+Mul + Sum<<T as Mul>::Output>> PredictorTrait<T> for Ignorant<T> {
     fn update(&mut self, _information: T) {}
-    fn predict(&self, infospace: &Vec<T>) -> T {
+    fn predict(&self, infospace: &[T]) -> T {
         infospace.iter().zip(self.coeff.iter()).map(|(v,c)| *v * *c).sum()
     }
-    fn consume(&mut self, data: &Vec<T>, shape: &Position, ring: bool) -> Vec<T> {
+    fn consume(&mut self, data: &[T], shape: &Position, ring: bool) -> Vec<T> {
         let mut spaces = Vec::new();
         if ring {
-            spaces = GeneratorIteratorAdapter(single_neighbours_grouped_with_ring(shape, &self.cells, data)).collect();
+            spaces = single_neighbours_grouped_with_ring(shape, &self.cells, data).collect();
         } else {
-            spaces = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(shape, &self.cells, data)).collect();
+            spaces = single_neighbours_grouped_no_ring(shape, &self.cells, data).collect();
         }
         let mut result = Vec::new();
         for (i, space) in spaces.iter().enumerate() {
@@ -37,6 +37,7 @@ impl<T: AddAssign<<T as Mul>::Output>+Default+Copy+Mul + Sum<<T as Mul>::Output>
     }
 }
 
+#[allow(clippy::module_inception)]
 pub mod predictors {
     use super::*;
     pub fn get_last_value_f32() -> Ignorant<f32> {
@@ -62,6 +63,11 @@ pub mod predictors {
         ];
         Ignorant::<f32> { coeff, cells }
     }
+    pub fn get_lorenz_f64() -> Ignorant<f64> {
+        let lorenz = get_lorenz_f32();
+        let coeff: Vec<f64> = lorenz.coeff.iter().map(|&c| f64::from(c)).collect();
+        Ignorant::<f64> { coeff, cells: lorenz.cells }
+    }
 }
 
 #[allow(unused_imports)]
@@ -1,7 +1,6 @@
 use super::position::Position as Coordinate;
-use super::gen::GeneratorIteratorAdapter;
 
-pub fn neighbours(shape: &Coordinate, pos: &Vec<Coordinate>, data: &Vec<f32>, ring: bool) -> Vec<Vec<f32>>{
+pub fn neighbours(shape: &Coordinate, pos: &Vec<Coordinate>, data: &[f32], ring: bool) -> Vec<Vec<f32>>{
     if ring {
         get_values_with_default_at_nonexistent_neighbours_all_ring_by_pos(shape, pos, data)
     } else {
@@ -9,17 +8,17 @@ pub fn neighbours(shape: &Coordinate, pos: &Vec<Coordinate>, data: &Vec<f32>, ri
     }
 }
 
-pub fn get_values_with_default_at_nonexistent_neighbours_all_ring_by_pos(shape: &Coordinate, pos: &Vec<Coordinate>, data: &Vec<f32>)
+pub fn get_values_with_default_at_nonexistent_neighbours_all_ring_by_pos(shape: &Coordinate, pos: &Vec<Coordinate>, data: &[f32])
 -> Vec<Vec<f32>>
 {
 
-    let tmp = get_values_with_default_at_nonexistent_neighbours_all_ring(&shape, &pos, &data);
+    let tmp = get_values_with_default_at_nonexistent_neighbours_all_ring(shape, pos, data);
 
     let mut result = Vec::new();
     for i in 0..data.len() {
         let mut r = Vec::new();
         for vec in &tmp {
-            r.push(vec[i])
+            r.push(vec.get(i).copied().unwrap_or_default())
         }
         result.push(r)
     }
@@ -28,19 +27,18 @@ pub fn get_values_with_default_at_nonexistent_neighbours_all_ring_by_pos(shape:
 }
 
 
-pub fn get_values_with_default_at_nonexistent_neighbours_all_ring(shape: &Coordinate, pos: &Vec<Coordinate>, data: &Vec<f32>,
+pub fn get_values_with_default_at_nonexistent_neighbours_all_ring(shape: &Coordinate, pos: &Vec<Coordinate>, data: &[f32],
 ) -> Vec<Vec<f32>> {
     let mut result: Vec<Vec<f32>> = Vec::new();
     for p in pos {
-        result.push(get_values_with_default_at_nonexistent_neighbours_ring(&shape, &p, &data));
+        result.push(get_values_with_default_at_nonexistent_neighbours_ring(shape, p, data));
     }
     result
 }
 
 
-pub fn get_values_with_default_at_nonexistent_neighbours_ring(shape: &Coordinate, pos: &Coordinate, data: &Vec<f32>) -> Vec<f32>{
+pub fn get_values_with_default_at_nonexistent_neighbours_ring(shape: &Coordinate, pos: &Coordinate, data: &[f32]) -> Vec<f32>{
     let offset = calculate_offset(shape, pos) as isize;
-    let ptr = data.as_ptr();
     let Coordinate{x:_, y:dy, z:dz} = calculate_dims(shape);
     let Coordinate{x:nx, y:ny, z:nz} = *shape;
     let mut result: Vec<f32> = Vec::new();
@@ -54,7 +52,7 @@ pub fn get_values_with_default_at_nonexistent_neighbours_ring(shape: &Coordinate
         for _ in 0..ny-pos.y {
             for _ in 0..nx {
                 let off = (ix as isize - offset).max(0);
-                let val = unsafe { *ptr.offset(off) };
+                let val = value_at(data, off, default);
                 repeated_add(&mut result, val, 1);
                 ix += 1;
             }
@@ -76,6 +74,14 @@ fn calculate_dims(shape: &Coordinate) -> Coordinate {
     Coordinate{x:dx, y:dy, z:dz}
 }
 
+/// Value at `ix`, `default` if a shape not matching the data points outside of it
+fn value_at(data: &[f32], ix: isize, default: f32) -> f32 {
+    if ix < 0 {
+        return default;
+    }
+    data.get(ix as usize).copied().unwrap_or(default)
+}
+
 fn repeated_add(data: &mut Vec<f32>, val: f32, times: i32) {
     for _ in 0..times {
         data.push(val)
@@ -84,18 +90,17 @@ fn repeated_add(data: &mut Vec<f32>, val: f32, times: i32) {
 
 
 /// Non-ring implementation of the neighbours
-
-pub fn get_values_with_default_at_nonexistent_neighbours_all_by_pos(shape: &Coordinate, pos: &Vec<Coordinate>, data: &Vec<f32>)
+pub fn get_values_with_default_at_nonexistent_neighbours_all_by_pos(shape: &Coordinate, pos: &Vec<Coordinate>, data: &[f32])
 -> Vec<Vec<f32>>
 {
 
-    let tmp = get_values_with_default_at_nonexistent_neighbours_all(&shape, &pos, &data);
+    let tmp = get_values_with_default_at_nonexistent_neighbours_all(shape, pos, data);
 
     let mut result = Vec::new();
     for i in 0..data.len() {
         let mut r = Vec::new();
         for vec in &tmp {
-            r.push(vec[i])
+            r.push(vec.get(i).copied().unwrap_or_default())
         }
         result.push(r)
     }
@@ -103,18 +108,17 @@ pub fn get_values_with_default_at_nonexistent_neighbours_all_by_pos(shape: &Coor
     result
 }
 
-pub fn get_values_with_default_at_nonexistent_neighbours_all(shape: &Coordinate, pos: &Vec<Coordinate>, data: &Vec<f32>,
+pub fn get_values_with_default_at_nonexistent_neighbours_all(shape: &Coordinate, pos: &Vec<Coordinate>, data: &[f32],
 ) -> Vec<Vec<f32>> {
     let mut result: Vec<Vec<f32>> = Vec::new();
     for p in pos {
-        result.push(get_values_with_default_at_nonexistent_neighbours(&shape, &p, &data));
+        result.push(get_values_with_default_at_nonexistent_neighbours(shape, p, data));
     }
     result
 }
 
-pub fn get_values_with_default_at_nonexistent_neighbours(shape: &Coordinate, pos: &Coordinate, data: &Vec<f32>) -> Vec<f32>{
+pub fn get_values_with_default_at_nonexistent_neighbours(shape: &Coordinate, pos: &Coordinate, data: &[f32]) -> Vec<f32>{
     let offset = calculate_offset(shape, pos) as isize;
-    let ptr = data.as_ptr();
     let Coordinate{x:dx, y:dy, z:dz} = calculate_dims(shape);
     let Coordinate{x:nx, y:ny, z:nz} = *shape;
     let mut result: Vec<f32> = Vec::new();
@@ -128,7 +132,7 @@ pub fn get_values_with_default_at_nonexistent_neighbours(shape: &Coordinate, pos
         for _ in 0..ny-pos.y {
             repeated_add(&mut result, default, pos.x * dx);  ix += pos.x * dx;
             for _ in 0..nx-pos.x {
-                let val = unsafe { *ptr.offset(ix as isize - offset) };
+                let val = value_at(data, ix as isize - offset, default);
                 repeated_add(&mut result, val, 1);
                 ix += 1;
             }
@@ -138,84 +142,142 @@ pub fn get_values_with_default_at_nonexistent_neighbours(shape: &Coordinate, pos
 }
 
 
-use std::ops::{AddAssign, Mul};
-use std::ops::{Generator};
-pub fn single_neighbours_no_ring<'a, T: AddAssign<<T as Mul>::Output> + Copy + Default + Mul>(
-    shape: &'a Coordinate, pos: &'a Coordinate, data: &'a Vec<T>) -> impl Generator<Yield = T, Return = ()> + 'a {
-    move || {
+/// Iterator over the flat index of a single neighbour for every cell.
+///
+/// Cells without the neighbour (at the borders of the data) yield `None`.
+/// With `ring` the x-dimension wraps around to the previous row instead.
+/// The index is always smaller than the one of the cell itself, such that
+/// the neighbour is known before the cell during decoding.
+pub struct NeighbourIndices<'a> {
+    shape: &'a Coordinate,
+    pos: &'a Coordinate,
+    offset: isize,
+    ring: bool,
+    len: usize,
+    ix: usize,
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+impl<'a> NeighbourIndices<'a> {
+    pub fn new(shape: &'a Coordinate, pos: &'a Coordinate, len: usize, ring: bool) -> Self {
         let offset = calculate_offset(shape, pos) as isize;
-        let ptr = data.as_ptr();
-        let Coordinate{x:dx, y:dy, z:dz} = calculate_dims(shape);
-        let Coordinate{x:nx, y:ny, z:nz} = *shape;
-        let mut ix = 0i32;
-
-        for _ in 0..pos.z * dz {yield T::default();ix += 1;}
-        for _ in 0..nz-pos.z {
-            for _ in 0..pos.y * dy { yield T::default(); ix += 1;}
-            for _ in 0..ny-pos.y {
-                for _ in 0..pos.x * dx { yield T::default(); ix+=1;}
-                for _ in 0..nx-pos.x {
-                    yield unsafe { *ptr.offset(ix as isize - offset) };
-                    ix+=1;
-                }
-            }
+        NeighbourIndices { shape, pos, offset, ring, len, ix: 0, x: 0, y: 0, z: 0 }
+    }
+
+    fn exists(&self) -> bool {
+        let outside = self.z < self.pos.z || self.y < self.pos.y;
+        if self.ring {
+            !outside && self.ix as isize >= self.offset
+        } else {
+            !outside && self.x >= self.pos.x
         }
     }
-}
 
-pub fn single_neighbours_grouped_no_ring<'a, T: AddAssign<<T as Mul>::Output> + Copy + Default + Mul>(
-    shape: &'a Coordinate, pos: &'a Vec<Coordinate>, data: &'a Vec<T>) -> impl Generator<Yield = Vec<T>, Return = ()> + 'a {
-    move || {
-        let mut iterators: Vec<_> = pos.iter().map(|p| GeneratorIteratorAdapter(single_neighbours_no_ring(shape, p, data))).collect();
-        for _ in 0..data.len(){
-            let mut r: Vec<T> = Vec::new();
-            for iter in iterators.iter_mut() {
-                r.push(iter.next().unwrap())
+    fn step(&mut self) {
+        self.ix += 1;
+        self.x += 1;
+        if self.x == self.shape.x {
+            self.x = 0;
+            self.y += 1;
+            if self.y == self.shape.y {
+                self.y = 0;
+                self.z += 1;
             }
-            yield r;
         }
     }
 }
 
-pub fn single_neighbours_grouped_with_ring<'a, T: AddAssign<<T as Mul>::Output> + Copy + Default + Mul>(
-    shape: &'a Coordinate, pos: &'a Vec<Coordinate>, data: &'a Vec<T>) -> impl Generator<Yield = Vec<T>, Return = ()> + 'a {
-    move || {
-        let mut iterators: Vec<_> = pos.iter().map(|p| GeneratorIteratorAdapter(single_neighbours_with_ring(shape, p, data))).collect();
-        for _ in 0..data.len(){
-            let mut r: Vec<T> = Vec::new();
-            for iter in iterators.iter_mut() {
-                r.push(iter.next().unwrap())
-            }
-            yield r;
+impl<'a> Iterator for NeighbourIndices<'a> {
+    type Item = Option<usize>;
+
+    fn next(&mut self) -> Option<Option<usize>> {
+        if self.z >= self.shape.z || self.ix >= self.len {
+            return None;
         }
+        let neighbour = self.ix as isize - self.offset;
+        let index = if self.exists() && neighbour >= 0 {
+            Some(neighbour as usize)
+        } else {
+            None
+        };
+        self.step();
+        Some(index)
     }
 }
 
-pub fn single_neighbours_with_ring<'a, T: AddAssign<<T as Mul>::Output> + Copy + Default + Mul>(
-    shape: &'a Coordinate, pos: &'a Coordinate, data: &'a Vec<T>) -> impl Generator<Yield = T, Return = ()> + 'a {
-    move || {
-        let offset = calculate_offset(shape, pos) as isize;
-        let ptr = data.as_ptr();
-        let Coordinate{x:_, y:dy, z:dz} = calculate_dims(shape);
-        let Coordinate{x:nx, y:ny, z:nz} = *shape;
-        let mut ix = 0i32;
-
-        for _ in 0..pos.z * dz {yield T::default();ix += 1;}
-        for _ in 0..nz-pos.z {
-            for _ in 0..pos.y * dy { yield T::default(); ix += 1;}
-            for _ in 0..ny-pos.y {
-                for _ in 0..nx {
-                    let off = (ix as isize - offset).max(0);
-                    yield unsafe { *ptr.offset(off) };
-                    ix += 1;
-                }
-            }
+/// Iterator over the values of a single neighbour for every cell of the data.
+///
+/// Cells without the neighbour yield `T::default()` (see `NeighbourIndices`).
+pub struct SingleNeighbours<'a, T> {
+    data: &'a [T],
+    indices: NeighbourIndices<'a>,
+}
+
+impl<'a, T> SingleNeighbours<'a, T> {
+    fn new(shape: &'a Coordinate, pos: &'a Coordinate, data: &'a [T], ring: bool) -> Self {
+        let indices = NeighbourIndices::new(shape, pos, data.len(), ring);
+        SingleNeighbours { data, indices }
+    }
+}
+
+impl<'a, T: Copy + Default> Iterator for SingleNeighbours<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let index = self.indices.next()?;
+        Some(index.and_then(|i| self.data.get(i).copied()).unwrap_or_default())
+    }
+}
+
+/// Iterator over the values of several neighbours for every cell of the data.
+///
+/// Each item holds the values in the same order as the given positions.
+pub struct GroupedNeighbours<'a, T> {
+    iterators: Vec<SingleNeighbours<'a, T>>,
+    remaining: usize,
+}
+
+impl<'a, T: Copy + Default> Iterator for GroupedNeighbours<'a, T> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        if self.remaining == 0 {
+            return None;
         }
+        self.remaining -= 1;
+        Some(self.iterators.iter_mut().map(|iter| iter.next().unwrap_or_default()).collect())
     }
 }
 
+pub fn single_neighbours_no_ring<'a, T: Copy + Default>(
+    shape: &'a Coordinate, pos: &'a Coordinate, data: &'a [T]) -> SingleNeighbours<'a, T> {
+    SingleNeighbours::new(shape, pos, data, false)
+}
 
-#[allow(unused_imports)]
+pub fn single_neighbours_with_ring<'a, T: Copy + Default>(
+    shape: &'a Coordinate, pos: &'a Coordinate, data: &'a [T]) -> SingleNeighbours<'a, T> {
+    SingleNeighbours::new(shape, pos, data, true)
+}
+
+pub fn single_neighbours_grouped_no_ring<'a, T: Copy + Default>(
+    shape: &'a Coordinate, pos: &'a [Coordinate], data: &'a [T]) -> GroupedNeighbours<'a, T> {
+    let iterators = pos.iter().map(|p| single_neighbours_no_ring(shape, p, data)).collect();
+    GroupedNeighbours { iterators, remaining: data.len() }
+}
+
+pub fn single_neighbours_grouped_with_ring<'a, T: Copy + Default>(
+    shape: &'a Coordinate, pos: &'a [Coordinate], data: &'a [T]) -> GroupedNeighbours<'a, T> {
+    let iterators = pos.iter().map(|p| single_neighbours_with_ring(shape, p, data)).collect();
+    GroupedNeighbours { iterators, remaining: data.len() }
+}
+
+/// Generator based implementation of the neighbours (requires the `nightly` feature)
+#[cfg(feature = "nightly")]
+pub mod generators;
+
+#[allow(unused_imports, clippy::vec_init_then_push)]
 mod tests {
     use super::super::{Position};
     use super::*;
@@ -252,7 +314,7 @@ mod tests {
             let mut weights: Vec<Position> = Vec::new();
             weights.push(Position { x: 2, y: 1, z: 1 });
 
-            let result: Vec<f32> = GeneratorIteratorAdapter(single_neighbours_no_ring(&tr, &weights[0], &data)).collect();
+            let result: Vec<f32> = single_neighbours_no_ring(&tr, &weights[0], &data).collect();
             assert_eq!(result[24], 0f32);
             assert_eq!(result[26], 12f32);
         }
@@ -279,7 +341,7 @@ mod tests {
             let mut weights: Vec<Position> = Vec::new();
             weights.push(Position { x: 2, y: 1, z: 1 });
 
-            let result: Vec<f32> = GeneratorIteratorAdapter(single_neighbours_no_ring(&tr, &weights[0], &data)).collect();
+            let result: Vec<f32> = single_neighbours_no_ring(&tr, &weights[0], &data).collect();
             assert_eq!(result[24], 0f32);
             assert_eq!(result[26], 12f32);
         }
@@ -308,7 +370,7 @@ mod tests {
             let mut weights: Vec<Position> = Vec::new();
             weights.push(Position { x: 2, y: 1, z: 1 });
 
-            let result: Vec<f32> = GeneratorIteratorAdapter(single_neighbours_with_ring(&tr, &weights[0], &data)).collect();
+            let result: Vec<f32> = single_neighbours_with_ring(&tr, &weights[0], &data).collect();
             assert_eq!(result[24], 10f32);
             assert_eq!(result[26], 12f32);
         }
@@ -337,7 +399,7 @@ mod tests {
             weights.push(Position { x: 2, y: 1, z: 1 });
             weights.push(Position { x: 1, y: 0, z: 0 });
 
-            let result: Vec<Vec<f32>> = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(&tr, &weights, &data)).collect();
+            let result: Vec<Vec<f32>> = single_neighbours_grouped_no_ring(&tr, &weights, &data).collect();
             assert_eq!(result[24], vec![0f32, 0f32]);
             assert_eq!(result[25], vec![0f32, 24f32]);
             assert_eq!(result[26], vec![12f32, 25f32]);
@@ -367,7 +429,7 @@ mod tests {
             weights.push(Position { x: 2, y: 1, z: 1 });
             weights.push(Position { x: 1, y: 0, z: 0 });
 
-            let result: Vec<Vec<f32>> = GeneratorIteratorAdapter(single_neighbours_grouped_with_ring(&tr, &weights, &data)).collect();
+            let result: Vec<Vec<f32>> = single_neighbours_grouped_with_ring(&tr, &weights, &data).collect();
             assert_eq!(result[24], vec![10f32, 23f32]);
             assert_eq!(result[25], vec![11f32, 24f32]);
             assert_eq!(result[26], vec![12f32, 25f32]);
@@ -384,7 +446,7 @@ mod tests {
         let mut weights: Vec<Position> = Vec::new();
         weights.push(Position { x: 0, y: 1, z: 1 });
 
-        let results: Vec<f64> = GeneratorIteratorAdapter(single_neighbours_grouped_with_ring(&tr, &weights, &data)).map(|x|x[0]).collect();
+        let results: Vec<f64> = single_neighbours_grouped_with_ring(&tr, &weights, &data).map(|x|x[0]).collect();
 
         assert_eq!(results[20], 0f64);
         assert_eq!(results[17], 5f64);
@@ -402,7 +464,7 @@ mod tests {
         let mut weights: Vec<Position> = Vec::new();
         weights.push(Position { x: 1, y: 0, z: 0 });
 
-        let generator_iterator = GeneratorIteratorAdapter(single_neighbours_no_ring(&tr, &weights[0], &data));
+        let generator_iterator = single_neighbours_no_ring(&tr, &weights[0], &data);
         let results: Vec<f64> = generator_iterator.collect();
         assert_eq!(
             results,
@@ -438,7 +500,7 @@ mod tests {
             let mut weights: Vec<Position> = Vec::new();
             weights.push(Position { x: 1, y: 0, z: 2 });
 
-            let result: Vec<Vec<f64>> = GeneratorIteratorAdapter(single_neighbours_grouped_with_ring(&tr, &weights, &data)).collect();
+            let result: Vec<Vec<f64>> = single_neighbours_grouped_with_ring(&tr, &weights, &data).collect();
             assert_eq!(result[20], vec![1f64]);
             assert_eq!(result[17], vec![0f64]);
             assert_eq!(result[26], vec![7f64]);
@@ -459,7 +521,7 @@ mod tests {
             weights.push(Position { x: 1, y: 2, z: 0 });
 
 
-            let result: Vec<Vec<f64>> = GeneratorIteratorAdapter(single_neighbours_grouped_with_ring(&tr, &weights, &data)).collect();
+            let result: Vec<Vec<f64>> = single_neighbours_grouped_with_ring(&tr, &weights, &data).collect();
             assert_eq!(result[19], vec![0f64]);
             assert_eq!(result[20], vec![0f64]);
             assert_eq!(result[17], vec![10f64]);
@@ -474,10 +536,10 @@ mod tests {
         }
 
         {   let tr = Position{x:3, y:3, z:3};
-            let mut weights: Vec<(Position)> = Vec::new();
+            let mut weights: Vec<Position> = Vec::new();
             weights.push(Position { x: 3, y: 3, z: 0 });
 
-            let result: Vec<Vec<f64>> = GeneratorIteratorAdapter(single_neighbours_grouped_with_ring(&tr, &weights, &data)).collect();
+            let result: Vec<Vec<f64>> = single_neighbours_grouped_with_ring(&tr, &weights, &data).collect();
             assert_eq!(result[19], vec![0f64]);
             assert_eq!(result[20], vec![0f64]);
             assert_eq!(result[17], vec![0f64]);
@@ -496,7 +558,7 @@ mod tests {
             let mut weights: Vec<Position> = Vec::new();
             weights.push(Position { x: 2, y: 1, z: 1 });
 
-            let result: Vec<Vec<f64>> = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(&tr, &weights, &data)).collect();
+            let result: Vec<Vec<f64>> = single_neighbours_grouped_no_ring(&tr, &weights, &data).collect();
             assert_eq!(result[24], vec![0f64]);
             assert_eq!(result[26], vec![12f64]);
         }
@@ -524,7 +586,7 @@ mod tests {
     //         let mut weights: Vec<Position> = Vec::new();
     //         weights.push(Position { x: -1, y: 1, z: 0 });
 
-    //         let result: Vec<Vec<f64>> = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(&tr, &weights, &data)).collect();
+    //         let result: Vec<Vec<f64>> = single_neighbours_grouped_no_ring(&tr, &weights, &data).collect();
     //         assert_eq!(result[1], vec![0f64]);
     //         assert_eq!(result[10], vec![0f64]);
     //         assert_eq!(result[11], vec![0f64]);
@@ -551,7 +613,7 @@ mod tests {
     //         weights.push(Position { x: 0, y: -1, z: 1 });
 
 
-    //         let result: Vec<Vec<f64>> = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(&tr, &weights, &data)).collect();
+    //         let result: Vec<Vec<f64>> = single_neighbours_grouped_no_ring(&tr, &weights, &data).collect();
     //         assert_eq!(result[13], vec![7f64]);
     //         assert_eq!(result[10], vec![4f64]);
     //         assert_eq!(result[11], vec![5f64]);
@@ -579,7 +641,7 @@ mod tests {
     //     let mut weights: Vec<Position> = Vec::new();
     //     weights.push(Position { x: -1, y: 1, z: 0 });
 
-    //         let result: Vec<Vec<f64>> = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(&tr, &weights, &data)).collect();
+    //         let result: Vec<Vec<f64>> = single_neighbours_grouped_no_ring(&tr, &weights, &data).collect();
     //     assert_eq!(result[20], vec![0f64]);
     //     assert_eq!(result[17], vec![0f64]);
     //     assert_eq!(result[11], vec![0f64]);
@@ -611,7 +673,7 @@ mod tests {
     //     let mut weights: Vec<Position> = Vec::new();
     //     weights.push(Position { x: -1, y: 1, z: 1 });
 
-    //     let result: Vec<Vec<f64>> = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(&tr, &weights, &data)).collect();
+    //     let result: Vec<Vec<f64>> = single_neighbours_grouped_no_ring(&tr, &weights, &data).collect();
     //     assert_eq!(result[20], vec![0f64]);
     //     assert_eq!(result[17], vec![0f64]);
     //     assert_eq!(result[11], vec![0f64]);
@@ -642,7 +704,7 @@ mod tests {
     //         let mut weights: Vec<Position> = Vec::new();
     //         weights.push(Position { x: -1, y: -1, z: 1 });
 
-    //         let result: Vec<Vec<f64>> = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(&tr, &weights, &data)).collect();
+    //         let result: Vec<Vec<f64>> = single_neighbours_grouped_no_ring(&tr, &weights, &data).collect();
 
     //         assert_eq!(result[12], vec![7f64]);
     //         assert_eq!(result[13], vec![8f64]);
@@ -690,7 +752,7 @@ mod tests {
     //         weights.push(Position { x: 1, y: 0, z: 1 });
     //         // weights.push(Position { x: -1, y: 2, z: 0 }); // TODO: If I don't get a hit it is wrong
 
-    //         let result: Vec<Vec<f64>> = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(&tr, &weights, &data)).collect();
+    //         let result: Vec<Vec<f64>> = single_neighbours_grouped_no_ring(&tr, &weights, &data).collect();
 
     //         assert_eq!(result[21], vec![12.0, 15.0, 9.0, 4.0, 20.0, 19.0, 13.0, 0.0]);
     //         assert_eq!(result[24], vec![15.0, 0.0, 12.0, 7.0, 23.0, 22.0, 16.0, 0.0]);
@@ -721,9 +783,24 @@ mod tests {
     //         weights.push(Position { x: 2, y: 1, z: 1 });
     //         weights.push(Position { x: -2, y: 1, z: 1 });
 
-    //         let result: Vec<Vec<f64>> = GeneratorIteratorAdapter(single_neighbours_grouped_no_ring(&tr, &weights, &data)).collect();
+    //         let result: Vec<Vec<f64>> = single_neighbours_grouped_no_ring(&tr, &weights, &data).collect();
 
     //         assert_eq!(result[24], vec![0.0,14.0]);
     //     }
     // }
+
+    #[test]
+    fn shape_larger_than_data() {
+        let data: Vec<f32> = (0..10).map(|i| i as f32).collect();
+        let shape = Position{x:4, y:4, z:4};
+        let weights = vec![Position { x: 1, y: 1, z: 1 }, Position { x: 0, y: 0, z: 3 }];
+        for &ring in &[false, true] {
+            let result = neighbours(&shape, &weights, &data, ring);
+            assert_eq!(result.len(), data.len());
+        }
+        let result: Vec<f32> = single_neighbours_with_ring(&shape, &weights[1], &data).collect();
+        assert_eq!(result, vec![0f32; 10]);
+        let result: Vec<f32> = single_neighbours_no_ring(&Position{x:2, y:1, z:1}, &weights[0], &data).collect();
+        assert_eq!(result, vec![0f32; 2]);
+    }
 }
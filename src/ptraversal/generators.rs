@@ -0,0 +1,85 @@
+//! Generator based implementation of the neighbours (requires the `nightly` feature)
+use super::{calculate_dims, calculate_offset, Coordinate};
+use crate::gen::GeneratorIteratorAdapter;
+use std::ops::{AddAssign, Mul};
+use std::ops::{Generator};
+
+/// Value at `ix`, the default if a shape not matching the data points outside of it
+fn value_at<T: Copy + Default>(data: &[T], ix: isize) -> T {
+    if ix < 0 {
+        return T::default();
+    }
+    data.get(ix as usize).copied().unwrap_or_default()
+}
+
+pub fn single_neighbours_no_ring<'a, T: AddAssign<<T as Mul>::Output> + Copy + Default + Mul>(
+    shape: &'a Coordinate, pos: &'a Coordinate, data: &'a Vec<T>) -> impl Generator<Yield = T, Return = ()> + 'a {
+    move || {
+        let offset = calculate_offset(shape, pos) as isize;
+        let Coordinate{x:dx, y:dy, z:dz} = calculate_dims(shape);
+        let Coordinate{x:nx, y:ny, z:nz} = *shape;
+        let mut ix = 0i32;
+
+        for _ in 0..pos.z * dz {yield T::default();ix += 1;}
+        for _ in 0..nz-pos.z {
+            for _ in 0..pos.y * dy { yield T::default(); ix += 1;}
+            for _ in 0..ny-pos.y {
+                for _ in 0..pos.x * dx { yield T::default(); ix+=1;}
+                for _ in 0..nx-pos.x {
+                    yield value_at(data, ix as isize - offset);
+                    ix+=1;
+                }
+            }
+        }
+    }
+}
+
+pub fn single_neighbours_grouped_no_ring<'a, T: AddAssign<<T as Mul>::Output> + Copy + Default + Mul>(
+    shape: &'a Coordinate, pos: &'a Vec<Coordinate>, data: &'a Vec<T>) -> impl Generator<Yield = Vec<T>, Return = ()> + 'a {
+    move || {
+        let mut iterators: Vec<_> = pos.iter().map(|p| GeneratorIteratorAdapter(single_neighbours_no_ring(shape, p, data))).collect();
+        for _ in 0..data.len(){
+            let mut r: Vec<T> = Vec::new();
+            for iter in iterators.iter_mut() {
+                r.push(iter.next().unwrap_or_default())
+            }
+            yield r;
+        }
+    }
+}
+
+pub fn single_neighbours_grouped_with_ring<'a, T: AddAssign<<T as Mul>::Output> + Copy + Default + Mul>(
+    shape: &'a Coordinate, pos: &'a Vec<Coordinate>, data: &'a Vec<T>) -> impl Generator<Yield = Vec<T>, Return = ()> + 'a {
+    move || {
+        let mut iterators: Vec<_> = pos.iter().map(|p| GeneratorIteratorAdapter(single_neighbours_with_ring(shape, p, data))).collect();
+        for _ in 0..data.len(){
+            let mut r: Vec<T> = Vec::new();
+            for iter in iterators.iter_mut() {
+                r.push(iter.next().unwrap_or_default())
+            }
+            yield r;
+        }
+    }
+}
+
+pub fn single_neighbours_with_ring<'a, T: AddAssign<<T as Mul>::Output> + Copy + Default + Mul>(
+    shape: &'a Coordinate, pos: &'a Coordinate, data: &'a Vec<T>) -> impl Generator<Yield = T, Return = ()> + 'a {
+    move || {
+        let offset = calculate_offset(shape, pos) as isize;
+        let Coordinate{x:_, y:dy, z:dz} = calculate_dims(shape);
+        let Coordinate{x:nx, y:ny, z:nz} = *shape;
+        let mut ix = 0i32;
+
+        for _ in 0..pos.z * dz {yield T::default();ix += 1;}
+        for _ in 0..nz-pos.z {
+            for _ in 0..pos.y * dy { yield T::default(); ix += 1;}
+            for _ in 0..ny-pos.y {
+                for _ in 0..nx {
+                    let off = (ix as isize - offset).max(0);
+                    yield value_at(data, off);
+                    ix += 1;
+                }
+            }
+        }
+    }
+}
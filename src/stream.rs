@@ -0,0 +1,299 @@
+/// Streaming encoding and decoding
+///
+/// The data is read value by value from any `Read` and the result written to
+/// any `Write`. Only the last `window` values (the distance to the farthest
+/// neighbour of the predictor, at most z * ny * nx) are kept in memory.
+///
+/// Streams are stored as regular pzip containers with a single chunk. They
+/// are always scanned in row-major order and without compaction, such that
+/// the size of the payload is known before the data is read. The checksums
+/// after the payload are computed while the values pass through.
+use byteorder::{ByteOrder, LittleEndian};
+use crc32fast::Hasher as Crc;
+use std::hash::Hasher;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use twox_hash::XxHash64;
+
+use super::codec::{self, validate_cells, Value};
+use super::container::{self, invalid, Chunk, Header};
+use super::endian::Endian;
+use super::error::{Error, Result};
+use super::order::Order;
+use super::position::Position;
+use super::predictors::{Ignorant, PredictorTrait};
+use super::ptraversal::{calculate_offset, NeighbourIndices};
+use super::transform::{ByteMapping, Compact};
+use super::Pipeline;
+
+/// Number of previous values needed to predict the next one.
+pub fn window(shape: &Position, cells: &[Position]) -> usize {
+    cells.iter().map(|c| calculate_offset(shape, c)).max().unwrap_or(0)
+}
+
+/// Checks that data encoded with `pipeline` can be streamed.
+pub fn check(pipeline: &Pipeline) -> Result<()> {
+    if pipeline.order != Order::default() || pipeline.tile.is_some() || pipeline.compact != Compact::Untouched {
+        return Err(Error::InvalidInput(
+            "Streams need row-major order, no tiling and no compaction".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks if the file with `header` can be decoded by `decode`.
+pub fn is_stream(header: &Header) -> bool {
+    check(&header.pipeline).is_ok() && header.trailing == 0
+}
+
+/// Turns the end of the input into `error`, keeping all other I/O errors.
+fn truncated(e: io::Error, error: impl FnOnce() -> Error) -> Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        error()
+    } else {
+        Error::Io(e)
+    }
+}
+
+/// Previously seen values within the window of the predictor.
+struct History<'a, T> {
+    values: Vec<T>,
+    neighbours: Vec<NeighbourIndices<'a>>,
+    space: Vec<T>,
+    ix: usize,
+}
+
+impl<'a, T: Value> History<'a, T> {
+    fn new(shape: &'a Position, cells: &'a [Position], size: usize, ring: bool) -> Self {
+        History {
+            values: vec![T::default(); window(shape, cells).max(1)],
+            neighbours: cells.iter().map(|c| NeighbourIndices::new(shape, c, size, ring)).collect(),
+            space: Vec::with_capacity(cells.len()),
+            ix: 0,
+        }
+    }
+
+    fn predict(&mut self, predictor: &Ignorant<T>) -> T {
+        let values = &self.values;
+        self.space.clear();
+        for iter in self.neighbours.iter_mut() {
+            let index = iter.next().unwrap_or(None);
+            self.space.push(index.map(|i| values[i % values.len()]).unwrap_or_default());
+        }
+        predictor.predict(&self.space)
+    }
+
+    fn push(&mut self, value: T) {
+        let n = self.values.len();
+        self.values[self.ix % n] = value;
+        self.ix += 1;
+    }
+}
+
+/// Encodes raw little endian values of `shape` from `input` into a pzip
+/// container written to `output`.
+pub fn encode<T: Value, R: Read, W: Write>(
+    input: R,
+    output: W,
+    shape: &Position,
+    predictor: &mut Ignorant<T>,
+    pipeline: &Pipeline,
+) -> Result<()> {
+    encode_with(input, output, &Header::new(shape, predictor, pipeline), predictor)
+}
+
+/// Encodes like `encode`, writing `header` with the shape, the pipeline and
+/// e.g. attributes of the data. The header must describe `predictor`.
+pub fn encode_with<T: Value, R: Read, W: Write>(
+    input: R,
+    output: W,
+    header: &Header,
+    predictor: &mut Ignorant<T>,
+) -> Result<()> {
+    let (shape, pipeline) = (&header.shape, &header.pipeline);
+    check(pipeline)?;
+    if header.cells != predictor.cells || header.trailing > 0 || header.endian != Endian::Little {
+        return Err(Error::InvalidInput(
+            "Streams need a header of the predictor for little endian values without trailing values".to_string(),
+        ));
+    }
+    validate_cells(&predictor.cells)?;
+    let mut input = BufReader::new(input);
+    let mut output = BufWriter::new(output);
+    let size = codec::size(shape)?;
+    header.write(&mut output)?;
+    let chunk = Chunk { offset: 0, length: (size * T::BYTES) as u64 };
+    container::write_index(&mut output, &[chunk])?;
+
+    let (h, k) = (&pipeline.inter, &pipeline.intra);
+    let cells = predictor.cells.clone();
+    let mut history = History::new(shape, &cells, size, pipeline.ring);
+    let (mut crc, mut checksum) = (Crc::new(), XxHash64::with_seed(0));
+    let mut buf = vec![0u8; T::BYTES];
+    for i in 0..size {
+        input.read_exact(&mut buf).map_err(|e| truncated(e, || {
+            Error::SizeMismatch(format!("Input ended after {} of {} values of the shape {:?}", i, size, shape))
+        }))?;
+        checksum.write(&buf);
+        let value = T::from_raw(LittleEndian::read_uint(&buf, T::BYTES));
+        let prediction = history.predict(predictor);
+        predictor.update(value);
+        history.push(value);
+
+        let residual = prediction.to_word(h, k) ^ value.to_word(h, k);
+        LittleEndian::write_uint(&mut buf, residual, T::BYTES);
+        for b in buf.iter_mut() {
+            *b = pipeline.byte.to_u8(*b);
+        }
+        crc.update(&buf);
+        output.write_all(&buf)?;
+    }
+    if input.read(&mut buf[..1])? > 0 {
+        return Err(Error::SizeMismatch(format!("Input holds more than the {} values of the shape {:?}", size, shape)));
+    }
+    container::write_checksums(&mut output, &[crc.finalize()], Some(checksum.finish()))?;
+    Ok(output.flush()?)
+}
+
+/// Decodes a pzip stream from `input` and writes the raw little endian
+/// values to `output`. Returns the shape of the data.
+pub fn decode<T: Value, R: Read, W: Write>(input: R, output: W) -> Result<Position> {
+    let mut input = BufReader::new(input);
+    let mut output = BufWriter::new(output);
+    let header = Header::read(&mut input)?;
+    let index = container::read_index(&mut input)?;
+    let pipeline = header.pipeline;
+    if check(&pipeline).is_err() || header.trailing > 0 {
+        return Err(invalid("File is not a stream, use decompress instead"));
+    }
+    let mut predictor = header.predictor::<T>()?;
+    let shape = header.shape;
+    let size = codec::size(&shape)?;
+    if index != [Chunk { offset: 0, length: (size * T::BYTES) as u64 }] {
+        return Err(invalid("Index does not describe a single chunk"));
+    }
+
+    let (h, k) = (&pipeline.inter, &pipeline.intra);
+    let mut history = History::new(&shape, &header.cells, size, pipeline.ring);
+    let (mut crc, mut checksum) = (Crc::new(), XxHash64::with_seed(0));
+    let mut buf = vec![0u8; T::BYTES];
+    for _ in 0..size {
+        input.read_exact(&mut buf).map_err(|e| truncated(e, || invalid("Stream ends before the last value")))?;
+        crc.update(&buf);
+        for b in buf.iter_mut() {
+            *b = pipeline.byte.from_u8(*b);
+        }
+        let residual = LittleEndian::read_uint(&buf, T::BYTES);
+        let prediction = history.predict(&predictor);
+        let value = T::from_word(residual ^ prediction.to_word(h, k), h, k);
+        predictor.update(value);
+        history.push(value);
+
+        LittleEndian::write_uint(&mut buf, value.to_raw(), T::BYTES);
+        checksum.write(&buf);
+        output.write_all(&buf)?;
+    }
+    output.flush()?;
+    if header.version >= 6 {
+        let (crcs, expected) = container::read_checksums(&mut input, 1)?;
+        if crcs[0] != crc.finalize() {
+            return Err(invalid("Checksum of the stream does not match, the file is corrupt"));
+        }
+        if expected.is_some_and(|c| c != checksum.finish()) {
+            return Err(invalid("Checksum of the decompressed data does not match, the file is corrupt"));
+        }
+    }
+    Ok(shape)
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::*;
+    use super::super::codec;
+    use super::super::predictors::predictors;
+    use super::super::transform::{Byte, Inter, Intra};
+
+    #[test]
+    fn window_of_lorenz() {
+        let shape = Position { x: 901, y: 351, z: 90 };
+        let predictor = predictors::get_lorenz_f32();
+        assert_eq!(window(&shape, &predictor.cells), 901 * 351 + 901 + 1);
+    }
+
+    #[test]
+    fn stream_matches_container() {
+        let shape = Position { x: 6, y: 5, z: 4 };
+        let data: Vec<f32> = (0..120).map(|i| (i as f32 * 0.1).cos()).collect();
+        let mut raw = vec![0u8; 120 * 4];
+        LittleEndian::write_f32_into(&data, &mut raw);
+        let pipeline = Pipeline {
+            inter: Inter::Ordered,
+            intra: Intra::Gray,
+            byte: Byte::MonoGray,
+            ring: true,
+            ..Pipeline::default()
+        };
+
+        let mut stream = Vec::new();
+        encode(raw.as_slice(), &mut stream, &shape, &mut predictors::get_lorenz_f32(), &pipeline).unwrap();
+
+        let mut predictor = predictors::get_lorenz_f32();
+        let mut expected = Vec::new();
+        Header::new(&shape, &predictor, &pipeline).write(&mut expected).unwrap();
+        let chunks = codec::encode_chunks(&data, &shape, &mut predictor, &pipeline).unwrap();
+        container::write_index(&mut expected, &container::index(&chunks)).unwrap();
+        expected.extend_from_slice(&chunks[0]);
+        container::write_checksums(&mut expected, &[container::crc(&chunks[0])], Some(codec::checksum(&data))).unwrap();
+        assert_eq!(stream, expected);
+
+        let mut result = Vec::new();
+        assert_eq!(decode::<f32, _, _>(stream.as_slice(), &mut result).unwrap(), shape);
+        assert_eq!(result, raw);
+
+        let middle = stream.len() - 4 - 9 - 120 * 4 / 2;
+        stream[middle] ^= 0x10;
+        let result = decode::<f32, _, _>(stream.as_slice(), Vec::new());
+        assert!(matches!(result, Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn reject_unstreamable_pipelines() {
+        let shape = Position { x: 2, y: 1, z: 1 };
+        let pipeline = Pipeline { order: Order::Hilbert, ..Pipeline::default() };
+        let raw = vec![0u8; 16];
+        let result = encode(raw.as_slice(), Vec::new(), &shape, &mut predictors::get_last_value_f64(), &pipeline);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn header_with_attributes() {
+        let shape = Position { x: 3, y: 2, z: 1 };
+        let raw: Vec<u8> = (0..6).flat_map(|i| (f64::from(i) * 1.5).to_le_bytes().to_vec()).collect();
+        let mut predictor = predictors::get_last_value_f64();
+        let mut header = Header::new(&shape, &predictor, &Pipeline::default());
+        header.attributes.insert("units".to_string(), "K".to_string());
+
+        let mut stream = Vec::new();
+        encode_with(raw.as_slice(), &mut stream, &header, &mut predictor).unwrap();
+        assert_eq!(Header::read(&mut stream.as_slice()).unwrap().attributes, header.attributes);
+        assert!(is_stream(&header));
+        let mut result = Vec::new();
+        decode::<f64, _, _>(stream.as_slice(), &mut result).unwrap();
+        assert_eq!(result, raw);
+
+        let big = Header { endian: Endian::Big, ..header };
+        assert!(encode_with(raw.as_slice(), Vec::new(), &big, &mut predictor).is_err());
+    }
+
+    #[test]
+    fn truncated_input() {
+        let shape = Position { x: 4, y: 1, z: 1 };
+        let raw = vec![0u8; 12];
+        let pipeline = Pipeline::default();
+        let result = encode(raw.as_slice(), Vec::new(), &shape, &mut predictors::get_last_value_f32(), &pipeline);
+        assert!(matches!(result, Err(Error::SizeMismatch(_))));
+
+        let raw = vec![0u8; 20];
+        let result = encode(raw.as_slice(), Vec::new(), &shape, &mut predictors::get_last_value_f32(), &pipeline);
+        assert!(matches!(result, Err(Error::SizeMismatch(_))));
+    }
+}
@@ -1,162 +1,211 @@
+/// Sources of values to be compressed and sinks of decompressed values
+///
+/// `Source` and `Sink` work on any `Read` or `Write`: files (the default),
+/// bytes in memory and stdin/stdout, such that pzip fits into pipes and
+/// servers.
 use byteorder::{self, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::fs;
 use std::io::prelude::*;
 use std::io::{self, Read};
 use std::marker::PhantomData;
 
-pub trait FileToBeCompressed<T> {
-    fn new(filename: &String) -> Self;
+use super::codec::Value;
+use super::endian::{self, Endian};
+use super::error::{Error, Result};
+
+pub trait FileToBeCompressed<T>: Sized {
     fn ix(&self, position: usize) -> &T;
-    fn get(&mut self) -> T;
-    fn load(&mut self) -> Result<usize, io::Error>;
+    fn get(&mut self) -> Result<T>;
+    fn load(&mut self) -> Result<usize>;
+    /// Loads the values stored with `endian`, or with the detected byte order
+    /// if `None`, and returns the byte order used.
+    fn load_as(&mut self, endian: Option<Endian>) -> Result<Endian>;
 }
 
-pub trait CompressedFile<T> {
-    fn new(filename: &String) -> Self;
-    fn flush(&mut self) -> Result<(), io::Error>;
-    fn put(&mut self, value: T) -> Result<(), io::Error>;
-    fn put_all(&mut self, value: &[T]) -> Result<(), io::Error>;
+pub trait CompressedFile<T>: Sized {
+    fn flush(&mut self) -> Result<()>;
+    fn put(&mut self, value: T) -> Result<()>;
+    fn put_all(&mut self, value: &[T]) -> Result<()>;
 }
 
-pub struct Source<T> {
-    file: fs::File,
+pub struct Source<T, R = fs::File> {
+    file: R,
     pub data: Vec<T>,
 }
-pub struct Sink<T> {
-    file: fs::File,
+impl<T, R> AsRef<[T]> for Source<T, R> {
+    fn as_ref(&self) -> &[T] {
+        &self.data
+    }
+}
+
+impl<T> Source<T> {
+    pub fn new(filename: &str) -> Result<Self> {
+        Ok(Source::from_reader(fs::File::open(filename)?))
+    }
+}
+
+impl<T, R: Read> Source<T, R> {
+    pub fn from_reader(file: R) -> Self {
+        Source { file, data: Vec::new() }
+    }
+}
+
+impl<'a, T> Source<T, &'a [u8]> {
+    /// Values stored in memory
+    pub fn from_bytes(bytes: &'a [u8]) -> Self {
+        Source::from_reader(bytes)
+    }
+}
+
+impl<T> Source<T, io::Stdin> {
+    pub fn stdin() -> Self {
+        Source::from_reader(io::stdin())
+    }
+}
+
+pub struct Sink<T, W = fs::File> {
+    file: W,
     pub data: PhantomData<T>,
 }
 
-impl FileToBeCompressed<u8> for Source<u8> {
-    fn new(filename: &String) -> Self {
-        let file = fs::File::open(filename).unwrap();
-        let data: Vec<u8> = Vec::new();
-        Source { file, data }
+impl<T> Sink<T> {
+    // REFACTOR: Change filename to fs::path::Path type
+    pub fn new(filename: &str) -> Result<Self> {
+        Ok(Sink::from_writer(fs::File::create(filename)?))
     }
+}
+
+impl<T, W: Write> Sink<T, W> {
+    pub fn from_writer(file: W) -> Self {
+        Sink { file, data: PhantomData }
+    }
+
+    /// Returns the underlying writer, e.g. the bytes of an in-memory sink.
+    pub fn into_inner(self) -> W {
+        self.file
+    }
+}
+
+impl<T> Sink<T, Vec<u8>> {
+    /// Sink collecting the bytes in memory
+    pub fn in_memory() -> Self {
+        Sink::from_writer(Vec::new())
+    }
+}
+
+impl<T> Sink<T, io::Stdout> {
+    pub fn stdout() -> Self {
+        Sink::from_writer(io::stdout())
+    }
+}
 
+impl<R: Read> FileToBeCompressed<u8> for Source<u8, R> {
     fn ix(&self, position: usize) -> &u8 {
         &self.data[position]
     }
 
-    fn get(&mut self) -> u8 {
-        self.file.read_u8().unwrap()
+    fn get(&mut self) -> Result<u8> {
+        Ok(self.file.read_u8()?)
     }
 
-    fn load(&mut self) -> Result<usize, io::Error> {
+    fn load(&mut self) -> Result<usize> {
         let mut bytes: Vec<u8> = Vec::new();
         let length = self.file.read_to_end(&mut bytes)?;
         self.data = bytes;
         Ok(length)
     }
+
+    fn load_as(&mut self, _endian: Option<Endian>) -> Result<Endian> {
+        self.load()?;
+        Ok(Endian::Little)
+    }
 }
 
-impl FileToBeCompressed<f32> for Source<f32> {
-    fn new(filename: &String) -> Self {
-        let file = fs::File::open(filename).unwrap();
-        let data: Vec<f32> = Vec::new();
-        Source { file, data }
+/// Reads all values of `file` stored with `endian` (detected if `None`).
+fn load_values<T: Value, R: Read>(file: &mut R, endian: Option<Endian>, name: &str) -> Result<(Vec<T>, Endian)> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let size = file.read_to_end(&mut bytes)?;
+
+    if size % T::BYTES != 0 {
+        return Err(Error::SizeMismatch(format!("{} bytes can not be read into {}", size, name)));
     }
+    let endian = endian.unwrap_or_else(|| endian::detect::<T>(&bytes));
+    Ok((endian::values(&bytes, endian), endian))
+}
 
+impl<R: Read> FileToBeCompressed<f32> for Source<f32, R> {
     fn ix(&self, position: usize) -> &f32 {
         &self.data[position]
     }
 
-    fn get(&mut self) -> f32 {
-        self.file.read_f32::<LittleEndian>().unwrap()
+    fn get(&mut self) -> Result<f32> {
+        Ok(self.file.read_f32::<LittleEndian>()?)
     }
 
-    fn load(&mut self) -> Result<usize, io::Error> {
-        let mut bytes: Vec<u8> = Vec::new();
-        let size = self.file.read_to_end(&mut bytes)?;
-
-        if size % 4 != 0 {
-            panic!("Can not be read into f32");
-        }
-        let mut data = vec![0_f32; size / 4];
-        LittleEndian::read_f32_into(&bytes, &mut data);
-        self.data = data;
+    fn load(&mut self) -> Result<usize> {
+        self.load_as(Some(Endian::Little))?;
         Ok(self.data.len())
     }
-}
 
-impl FileToBeCompressed<f64> for Source<f64> {
-    fn new(filename: &String) -> Self {
-        let file = fs::File::open(filename).unwrap();
-        let data: Vec<f64> = Vec::new();
-        Source { file, data }
+    fn load_as(&mut self, endian: Option<Endian>) -> Result<Endian> {
+        let (data, endian) = load_values(&mut self.file, endian, "f32")?;
+        self.data = data;
+        Ok(endian)
     }
+}
 
+impl<R: Read> FileToBeCompressed<f64> for Source<f64, R> {
     fn ix(&self, position: usize) -> &f64 {
         &self.data[position]
     }
 
-    fn get(&mut self) -> f64 {
-        self.file.read_f64::<LittleEndian>().unwrap()
+    fn get(&mut self) -> Result<f64> {
+        Ok(self.file.read_f64::<LittleEndian>()?)
     }
 
-    fn load(&mut self) -> Result<usize, io::Error> {
-        let mut bytes: Vec<u8> = Vec::new();
-        let size = self.file.read_to_end(&mut bytes)?;
-
-        if size % 8 != 0 {
-            panic!("Can not be read into f64");
-        }
-        let mut data = vec![0_f64; size / 8];
-        LittleEndian::read_f64_into(&bytes, &mut data);
-        self.data = data;
+    fn load(&mut self) -> Result<usize> {
+        self.load_as(Some(Endian::Little))?;
         Ok(self.data.len())
     }
-}
 
-impl CompressedFile<u8> for Sink<u8> {
-    // REFACTOR: Change filename to fs::path::Path type
-    fn new(filename: &String) -> Self {
-        let file = fs::File::create(&filename).unwrap();
-        Sink {
-            file,
-            data: PhantomData,
-        }
+    fn load_as(&mut self, endian: Option<Endian>) -> Result<Endian> {
+        let (data, endian) = load_values(&mut self.file, endian, "f64")?;
+        self.data = data;
+        Ok(endian)
     }
+}
 
-    fn flush(&mut self) -> Result<(), io::Error> {
+impl<W: Write> CompressedFile<u8> for Sink<u8, W> {
+    fn flush(&mut self) -> Result<()> {
         self.file.flush()?;
         Ok(())
     }
 
-    fn put(&mut self, value: u8) -> Result<(), io::Error> {
+    fn put(&mut self, value: u8) -> Result<()> {
         self.file.write_u8(value)?;
         Ok(())
     }
 
-    fn put_all(&mut self, values: &[u8]) -> Result<(), io::Error> {
-        self.file.write(values)?;
+    fn put_all(&mut self, values: &[u8]) -> Result<()> {
+        self.file.write_all(values)?;
         Ok(())
     }
 }
 
-impl CompressedFile<f32> for Sink<f32> {
-    // REFACTOR: Change filename to fs::path::Path type
-    fn new(filename: &String) -> Self {
-        let file = fs::File::create(&filename).unwrap();
-        Sink {
-            file,
-            data: PhantomData,
-        }
-    }
-
-    fn flush(&mut self) -> Result<(), io::Error> {
+impl<W: Write> CompressedFile<f32> for Sink<f32, W> {
+    fn flush(&mut self) -> Result<()> {
         self.file.flush()?;
         Ok(())
     }
 
-    fn put(&mut self, value: f32) -> Result<(), io::Error> {
+    fn put(&mut self, value: f32) -> Result<()> {
         let mut buf = [0_u8; 4];
         LittleEndian::write_f32(&mut buf, value);
-        self.file.write(&buf)?;
+        self.file.write_all(&buf)?;
         Ok(())
     }
 
-    fn put_all(&mut self, values: &[f32]) -> Result<(), io::Error> {
+    fn put_all(&mut self, values: &[f32]) -> Result<()> {
         for &n in values {
             self.put(n)?;
         }
@@ -164,29 +213,20 @@ impl CompressedFile<f32> for Sink<f32> {
     }
 }
 
-impl CompressedFile<f64> for Sink<f64> {
-    // REFACTOR: Change filename to fs::path::Path type
-    fn new(filename: &String) -> Self {
-        let file = fs::File::create(&filename).unwrap();
-        Sink {
-            file,
-            data: PhantomData,
-        }
-    }
-
-    fn flush(&mut self) -> Result<(), io::Error> {
+impl<W: Write> CompressedFile<f64> for Sink<f64, W> {
+    fn flush(&mut self) -> Result<()> {
         self.file.flush()?;
         Ok(())
     }
 
-    fn put(&mut self, value: f64) -> Result<(), io::Error> {
+    fn put(&mut self, value: f64) -> Result<()> {
         let mut buf = [0_u8; 8];
         LittleEndian::write_f64(&mut buf, value);
-        self.file.write(&buf)?;
+        self.file.write_all(&buf)?;
         Ok(())
     }
 
-    fn put_all(&mut self, values: &[f64]) -> Result<(), io::Error> {
+    fn put_all(&mut self, values: &[f64]) -> Result<()> {
         for &n in values {
             self.put(n)?;
         }
@@ -194,34 +234,32 @@ impl CompressedFile<f64> for Sink<f64> {
     }
 }
 
-pub fn read_first_k_f32(filename: &String, size: usize) -> Vec<f32> {
-    let mut result = vec![0f32; size];
-    let mut file: Source<f32> = Source::new(filename);
-    file.load().unwrap();
-    for i in 0..size {
-        result[i] = *file.ix(i);
+pub fn read_first_k_f32(filename: &str, size: usize) -> Result<Vec<f32>> {
+    let mut file: Source<f32> = Source::new(filename)?;
+    file.load()?;
+    match file.data.get(..size) {
+        Some(values) => Ok(values.to_vec()),
+        None => Err(Error::SizeMismatch(format!("{} contains less than {} values", filename, size))),
     }
-    result
 }
 
-pub fn read_first_k_f64(filename: &String, size: usize) -> Vec<f64> {
-    let mut result = vec![0f64; size];
-    let mut file: Source<f64> = Source::new(filename);
-    file.load().unwrap();
-    for i in 0..size {
-        result[i] = *file.ix(i);
+pub fn read_first_k_f64(filename: &str, size: usize) -> Result<Vec<f64>> {
+    let mut file: Source<f64> = Source::new(filename)?;
+    file.load()?;
+    match file.data.get(..size) {
+        Some(values) => Ok(values.to_vec()),
+        None => Err(Error::SizeMismatch(format!("{} contains less than {} values", filename, size))),
     }
-    result
 }
 
-#[allow(unused_imports)]
+#[allow(unused_imports, clippy::excessive_precision, clippy::needless_range_loop)]
 mod tests {
     use super::*;
 
     #[test]
     fn readin_k_f32_values() {
         let filename = "/home/ucyo/rust/pzip/data/subset.bin".to_string();
-        let values = read_first_k_f32(&filename, 3);
+        let values = read_first_k_f32(&filename, 3).expect("Error reading the values");
 
         let expected = [
             160.57284545898_f32,
@@ -238,12 +276,12 @@ mod tests {
         let filename = String::from("/tmp/output.raw");
         let v = 213.232_f32;
 
-        let mut sink: Sink<f32> = Sink::new(&filename);
+        let mut sink: Sink<f32> = Sink::new(&filename).expect("Error opening the file");
         sink.put(v).expect("Writing unsuccessfull");
         sink.flush().expect("Writing unsuccessfull");
 
-        let mut source: Source<f32> = Source::new(&filename);
-        let value = source.get();
+        let mut source: Source<f32> = Source::new(&filename).expect("Error opening the file");
+        let value = source.get().expect("Error reading a value");
 
         std::fs::remove_file(&filename).expect("Error");
         assert_eq!(v, value)
@@ -254,11 +292,11 @@ mod tests {
         let filename = String::from("/tmp/output.raw");
         let values = [213.236_f32, 839.9482_f32, 94.32_f32];
 
-        let mut sink: Sink<f32> = Sink::new(&filename);
+        let mut sink: Sink<f32> = Sink::new(&filename).expect("Error opening the file");
         sink.put_all(&values).expect("Writing unsuccessfull");
         sink.flush().expect("Writing unsuccessfull");
 
-        let mut source: Source<f32> = Source::new(&filename);
+        let mut source: Source<f32> = Source::new(&filename).expect("Error opening the file");
         source.load().expect("Load unsuccessfull");
 
         for i in 0..3 {
@@ -271,8 +309,8 @@ mod tests {
     fn trait_read_first_f32_from_file() {
         let filename = "/home/ucyo/rust/pzip/data/subset.bin".to_string();
 
-        let mut source: Source<f32> = Source::new(&filename);
-        let first = source.get();
+        let mut source: Source<f32> = Source::new(&filename).expect("Error opening the file");
+        let first = source.get().expect("Error reading a value");
         assert_eq!(first, 160.57284545898_f32)
     }
 
@@ -280,7 +318,7 @@ mod tests {
     fn trait_read_f32_from_file() {
         let filename = "/home/ucyo/rust/pzip/data/subset.bin".to_string();
 
-        let mut source: Source<f32> = Source::new(&filename);
+        let mut source: Source<f32> = Source::new(&filename).expect("Error opening the file");
         source.load().expect("Error loading the data");
 
         let expected = [
@@ -298,12 +336,12 @@ mod tests {
         let filename = String::from("/tmp/output.raw");
         let v = 213.232_f64;
 
-        let mut sink: Sink<f64> = Sink::new(&filename);
+        let mut sink: Sink<f64> = Sink::new(&filename).expect("Error opening the file");
         sink.put(v).expect("Writing unsuccessfull");
         sink.flush().expect("Writing unsuccessfull");
 
-        let mut source: Source<f64> = Source::new(&filename);
-        let value = source.get();
+        let mut source: Source<f64> = Source::new(&filename).expect("Error opening the file");
+        let value = source.get().expect("Error reading a value");
 
         std::fs::remove_file(&filename).expect("Error");
         assert_eq!(v, value)
@@ -314,11 +352,11 @@ mod tests {
         let filename = String::from("/tmp/output.raw");
         let values = [324234.423234_f64, 9291.822_f64, 1.23131_f64];
 
-        let mut sink: Sink<f64> = Sink::new(&filename);
+        let mut sink: Sink<f64> = Sink::new(&filename).expect("Error opening the file");
         sink.put_all(&values).expect("Writing unsuccessfull");
         sink.flush().expect("Writing unsuccessfull");
 
-        let mut source: Source<f64> = Source::new(&filename);
+        let mut source: Source<f64> = Source::new(&filename).expect("Error opening the file");
         source.load().expect("Load unsuccessfull");
 
         for i in 0..3 {
@@ -327,12 +365,27 @@ mod tests {
         std::fs::remove_file(&filename).expect("Error");
     }
 
+    #[test]
+    fn load_big_endian_f64() {
+        let filename = std::env::temp_dir().join(format!("pzip_big_endian_{}.raw", std::process::id())).to_string_lossy().into_owned();
+        let values: Vec<f64> = (0..512).map(|i| 273.15 + (i as f64 * 0.05).cos()).collect();
+        std::fs::write(&filename, endian::bytes(&values, Endian::Big)).expect("Error writing the file");
+
+        let mut source: Source<f64> = Source::new(&filename).expect("Error opening the file");
+        assert_eq!(source.load_as(None).expect("Load unsuccessfull"), Endian::Big);
+        assert_eq!(source.data, values);
+        let mut source: Source<f64> = Source::new(&filename).expect("Error opening the file");
+        source.load().expect("Load unsuccessfull");
+        assert_ne!(source.data, values);
+        std::fs::remove_file(&filename).expect("Error");
+    }
+
     #[test]
     fn trait_read_first_f64_from_file() {
         let filename = "/home/ucyo/rust/pzip/data/subset.bin".to_string();
 
-        let mut source: Source<f64> = Source::new(&filename);
-        let first = source.get();
+        let mut source: Source<f64> = Source::new(&filename).expect("Error opening the file");
+        let first = source.get().expect("Error reading a value");
         assert_eq!(first, 2.318024477526355e+15_f64)
     }
 
@@ -340,7 +393,7 @@ mod tests {
     fn trait_read_f64_from_file() {
         let filename = "/home/ucyo/rust/pzip/data/subset.bin".to_string();
 
-        let mut source: Source<f64> = Source::new(&filename);
+        let mut source: Source<f64> = Source::new(&filename).expect("Error opening the file");
         source.load().expect("Error loading the data");
 
         let expected = [
@@ -358,12 +411,12 @@ mod tests {
         let filename = String::from("/tmp/output.raw");
         let v = 213_u8;
 
-        let mut sink: Sink<u8> = Sink::new(&filename);
+        let mut sink: Sink<u8> = Sink::new(&filename).expect("Error opening the file");
         sink.put(v).expect("Writing unsuccessfull");
         sink.flush().expect("Writing unsuccessfull");
 
-        let mut source: Source<u8> = Source::new(&filename);
-        let value = source.get();
+        let mut source: Source<u8> = Source::new(&filename).expect("Error opening the file");
+        let value = source.get().expect("Error reading a value");
 
         std::fs::remove_file(&filename).expect("Error");
         assert_eq!(v, value)
@@ -374,11 +427,11 @@ mod tests {
         let filename = String::from("/tmp/output.raw");
         let values = [123_u8, 193_u8, 201_u8];
 
-        let mut sink: Sink<u8> = Sink::new(&filename);
+        let mut sink: Sink<u8> = Sink::new(&filename).expect("Error opening the file");
         sink.put_all(&values).expect("Writing unsuccessfull");
         sink.flush().expect("Writing unsuccessfull");
 
-        let mut source: Source<u8> = Source::new(&filename);
+        let mut source: Source<u8> = Source::new(&filename).expect("Error opening the file");
         source.load().expect("Load unsuccessfull");
 
         for i in 0..3 {
@@ -391,8 +444,8 @@ mod tests {
     fn trait_read_first_byte_from_file() {
         let filename = "/home/ucyo/rust/pzip/data/subset.bin".to_string();
 
-        let mut source: Source<u8> = Source::new(&filename);
-        let first = source.get();
+        let mut source: Source<u8> = Source::new(&filename).expect("Error opening the file");
+        let first = source.get().expect("Error reading a value");
         assert_eq!(first, 166u8)
     }
 
@@ -400,7 +453,7 @@ mod tests {
     fn trait_read_bytes_from_file() {
         let filename = "/home/ucyo/rust/pzip/data/subset.bin".to_string();
 
-        let mut source: Source<u8> = Source::new(&filename);
+        let mut source: Source<u8> = Source::new(&filename).expect("Error opening the file");
         source.load().expect("Error loading the data");
 
         let expected = [166_u8, 146_u8, 32_u8];
@@ -409,4 +462,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sources_and_sinks_in_memory() {
+        let values = [1.5_f64, -2.25, 1e300];
+        let mut sink: Sink<f64, Vec<u8>> = Sink::in_memory();
+        sink.put_all(&values).expect("Error writing the values");
+        let bytes = sink.into_inner();
+        assert_eq!(bytes.len(), 24);
+
+        let mut source: Source<f64, &[u8]> = Source::from_bytes(&bytes);
+        assert_eq!(source.load().expect("Error loading the data"), 3);
+        assert_eq!(source.data, values);
+
+        let mut source: Source<f32, _> = Source::from_reader(&bytes[..23]);
+        assert!(source.load().is_err());
+    }
 }
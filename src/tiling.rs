@@ -0,0 +1,174 @@
+/// Tiling of the data into independent chunks
+///
+/// The grid is split into tiles of a fixed shape (the tiles at the upper
+/// borders may be smaller). Every tile is encoded on its own, i.e. the
+/// predictor only sees neighbours inside of the same tile. The tiles are
+/// numbered in row-major order of the tile grid.
+use super::position::Position;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tile {
+    /// Position of the first value of the tile in the full grid
+    pub offset: Position,
+    /// Shape of the tile
+    pub shape: Position,
+}
+
+impl Tile {
+    pub fn size(&self) -> usize {
+        let extent = |n: i32| n.max(0) as usize;
+        extent(self.shape.x)
+            .checked_mul(extent(self.shape.y))
+            .and_then(|n| n.checked_mul(extent(self.shape.z)))
+            .expect("Tile larger than the address space")
+    }
+
+    /// Common part of both tiles, if there is any.
+    pub fn intersection(&self, other: &Tile) -> Option<Tile> {
+        let range = |a: i32, n: i32, b: i32, m: i32| {
+            let (a, n, b, m) = (a.max(0) as usize, n.max(0) as usize, b.max(0) as usize, m.max(0) as usize);
+            let (start, end) = (a.max(b), (a + n).min(b + m));
+            if end <= start {
+                return None;
+            }
+            Some((start as i32, (end - start) as i32))
+        };
+        let (x, nx) = range(self.offset.x, self.shape.x, other.offset.x, other.shape.x)?;
+        let (y, ny) = range(self.offset.y, self.shape.y, other.offset.y, other.shape.y)?;
+        let (z, nz) = range(self.offset.z, self.shape.z, other.offset.z, other.shape.z)?;
+        Some(Tile {
+            offset: Position { x, y, z },
+            shape: Position { x: nx, y: ny, z: nz },
+        })
+    }
+}
+
+/// Splits `shape` into tiles of (at most) `tile`.
+pub fn tiles(shape: &Position, tile: &Position) -> Vec<Tile> {
+    let step = |n: i32, t: i32| if t > 0 { t } else { n.max(1) };
+    let (tx, ty, tz) = (step(shape.x, tile.x), step(shape.y, tile.y), step(shape.z, tile.z));
+    let mut result = Vec::new();
+    for z in (0..shape.z).step_by(tz as usize) {
+        for y in (0..shape.y).step_by(ty as usize) {
+            for x in (0..shape.x).step_by(tx as usize) {
+                result.push(Tile {
+                    offset: Position { x, y, z },
+                    shape: Position {
+                        x: tx.min(shape.x - x),
+                        y: ty.min(shape.y - y),
+                        z: tz.min(shape.z - z),
+                    },
+                });
+            }
+        }
+    }
+    result
+}
+
+/// Number of tiles `tiles` splits `shape` into, `None` on overflow.
+pub fn count(shape: &Position, tile: &Position) -> Option<usize> {
+    let n = |n: i32, t: i32| {
+        let n = n.max(0) as usize;
+        if t > 0 { n.div_ceil(t as usize) } else { n.min(1) }
+    };
+    n(shape.x, tile.x).checked_mul(n(shape.y, tile.y))?.checked_mul(n(shape.z, tile.z))
+}
+
+/// Copies the values of `tile` out of `data` (row-major with `shape`).
+pub fn extract<T: Copy>(data: &[T], shape: &Position, tile: &Tile) -> Vec<T> {
+    let mut result = Vec::with_capacity(tile.size());
+    for z in 0..tile.shape.z {
+        for y in 0..tile.shape.y {
+            let start = flat(shape, tile.offset.z + z, tile.offset.y + y, tile.offset.x);
+            result.extend_from_slice(&data[start..start + tile.shape.x as usize]);
+        }
+    }
+    result
+}
+
+/// Copies the values of `tile` back into `data` (row-major with `shape`).
+pub fn insert<T: Copy>(data: &mut [T], shape: &Position, tile: &Tile, values: &[T]) {
+    let nx = tile.shape.x as usize;
+    let mut rows = values.chunks(nx.max(1));
+    for z in 0..tile.shape.z {
+        for y in 0..tile.shape.y {
+            let start = flat(shape, tile.offset.z + z, tile.offset.y + y, tile.offset.x);
+            let row = rows.next().expect("Not enough values for the tile");
+            data[start..start + nx].copy_from_slice(row);
+        }
+    }
+}
+
+/// Copies the overlap of two tiles from `values` (covering `from`) into
+/// `data` (covering `to`).
+pub fn copy<T: Copy>(values: &[T], from: &Tile, data: &mut [T], to: &Tile) {
+    let overlap = match from.intersection(to) {
+        Some(overlap) => overlap,
+        None => return,
+    };
+    let nx = overlap.shape.x as usize;
+    for z in overlap.offset.z..overlap.offset.z + overlap.shape.z {
+        for y in overlap.offset.y..overlap.offset.y + overlap.shape.y {
+            let x = overlap.offset.x;
+            let source = flat(&from.shape, z - from.offset.z, y - from.offset.y, x - from.offset.x);
+            let target = flat(&to.shape, z - to.offset.z, y - to.offset.y, x - to.offset.x);
+            data[target..target + nx].copy_from_slice(&values[source..source + nx]);
+        }
+    }
+}
+
+fn flat(shape: &Position, z: i32, y: i32, x: i32) -> usize {
+    (z as usize * shape.y as usize + y as usize) * shape.x as usize + x as usize
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_the_grid() {
+        let shape = Position { x: 5, y: 4, z: 3 };
+        let result = tiles(&shape, &Position { x: 2, y: 4, z: 2 });
+        assert_eq!(result.len(), 3 * 2);
+        assert_eq!(result.iter().map(|t| t.size()).sum::<usize>(), 60);
+        assert_eq!(result[2].offset, Position { x: 4, y: 0, z: 0 });
+        assert_eq!(result[2].shape, Position { x: 1, y: 4, z: 2 });
+        assert_eq!(result[5].shape, Position { x: 1, y: 4, z: 1 });
+        assert_eq!(count(&shape, &Position { x: 2, y: 4, z: 2 }), Some(result.len()));
+        assert_eq!(count(&shape, &Position { x: 0, y: 0, z: 0 }), Some(1));
+
+        let large = Tile { offset: Position { x: 0, y: 0, z: 0 }, shape: Position { x: 1 << 20, y: 1 << 20, z: 4 } };
+        assert_eq!(large.size(), 1 << 42);
+    }
+
+    #[test]
+    fn extract_and_insert() {
+        let shape = Position { x: 5, y: 4, z: 3 };
+        let data: Vec<i32> = (0..60).collect();
+        let mut result = vec![0; 60];
+        for tile in tiles(&shape, &Position { x: 2, y: 3, z: 2 }) {
+            let values = extract(&data, &shape, &tile);
+            assert_eq!(values.len(), tile.size());
+            insert(&mut result, &shape, &tile, &values);
+        }
+        assert_eq!(result, data);
+
+        let tile = Tile { offset: Position { x: 1, y: 2, z: 1 }, shape: Position { x: 2, y: 1, z: 1 } };
+        assert_eq!(extract(&data, &shape, &tile), vec![31, 32]);
+    }
+
+    #[test]
+    fn intersection_and_copy() {
+        let a = Tile { offset: Position { x: 4, y: 0, z: 0 }, shape: Position { x: 4, y: 4, z: 2 } };
+        let b = Tile { offset: Position { x: 2, y: 3, z: 1 }, shape: Position { x: 3, y: 2, z: 1 } };
+        let c = Tile { offset: Position { x: 0, y: 0, z: 0 }, shape: Position { x: 4, y: 4, z: 2 } };
+        let overlap = Tile { offset: Position { x: 4, y: 3, z: 1 }, shape: Position { x: 1, y: 1, z: 1 } };
+        assert_eq!(a.intersection(&b), Some(overlap));
+        assert_eq!(a.intersection(&c), None);
+
+        let values: Vec<i32> = (0..32).collect();
+        let mut data = vec![-1; 6];
+        copy(&values, &a, &mut data, &b);
+        assert_eq!(data, vec![-1, -1, 28, -1, -1, -1]);
+    }
+}
@@ -6,6 +6,7 @@
 /// - MonoGray (Byte)
 pub mod arrays;
 
+#[allow(clippy::wrong_self_convention)]
 pub trait InterMapping {
     fn to_u32(&self, from: f32) -> u32;
     fn from_u32(&self, from: u32) -> f32;
@@ -13,6 +14,7 @@ pub trait InterMapping {
     fn from_u64(&self, from: u64) -> f64;
 }
 
+#[allow(clippy::wrong_self_convention)]
 pub trait IntraMapping {
     fn to_new_u32(&self, num: u32) -> u32;
     fn from_new_u32(&self, num: u32) -> u32;
@@ -24,26 +26,31 @@ pub trait CompactMapping {
     fn compact_u32(&self, data: Vec<u32>) -> Vec<u32>;
 }
 
+#[allow(clippy::wrong_self_convention)]
 pub trait ByteMapping {
     fn to_u8(&self, num: u8) -> u8;
     fn from_u8(&self, num: u8) -> u8;
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Inter {
     Untouched,
     Ordered,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Intra {
     Untouched,
     Gray,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Byte {
     Untouched,
     MonoGray,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Compact {
     Untouched,
     NoLZC,
@@ -63,10 +70,10 @@ impl IntraMapping for Intra {
                 let mut number = num;
                 let mut mask = number >> 1;
                 while mask != 0 {
-                    number = number ^ mask;
-                    mask = mask >> 1;
+                    number ^= mask;
+                    mask >>= 1;
                 }
-                num
+                number
             }
         }
     }
@@ -83,10 +90,10 @@ impl IntraMapping for Intra {
                 let mut number = num;
                 let mut mask = number >> 1;
                 while mask != 0 {
-                    number = number ^ mask;
-                    mask = mask >> 1;
+                    number ^= mask;
+                    mask >>= 1;
                 }
-                num
+                number
             }
         }
     }
@@ -95,65 +102,53 @@ impl IntraMapping for Intra {
 impl InterMapping for Inter {
     fn to_u32(&self, from: f32) -> u32 {
         match self {
-            Inter::Untouched => unsafe { std::mem::transmute::<f32, u32>(from) },
+            Inter::Untouched => from.to_bits(),
             Inter::Ordered => {
-                let mut result = unsafe { std::mem::transmute::<f32, u32>(from) };
-                result = if from < 0f32 {
-                    !result
+                // Decided by the sign bit instead of a comparison, such that
+                // negative zeros and NaN payloads are mapped bijectively.
+                let bits = from.to_bits();
+                if bits >> 31 == 1 {
+                    !bits
                 } else {
-                    result | (1 << 31)
-                };
-
-                // If a value falls within this if-clause, it would
-                // be mapped to a NaN value. To prevent this and to accept
-                // arbitary u32 values, the result needs to be negated. Preliminary
-                // results show that this does not have any side-effects (espacially
-                // for transformations done from proper f32 source)
-                if result > 0x7F80_0000 && from.is_nan() {
-                    return !result;
+                    bits | (1 << 31)
                 }
-                result
             }
         }
     }
     fn from_u32(&self, from: u32) -> f32 {
         match self {
-            Inter::Untouched => unsafe { std::mem::transmute::<u32, f32>(from) },
+            Inter::Untouched => f32::from_bits(from),
             Inter::Ordered => {
                 if from < (1 << 31) {
-                    return unsafe { std::mem::transmute::<u32, f32>(!from) };
+                    f32::from_bits(!from)
                 } else {
-                    return unsafe { std::mem::transmute::<u32, f32>(from - (1 << 31)) };
+                    f32::from_bits(from - (1 << 31))
                 }
             }
         }
     }
     fn to_u64(&self, from: f64) -> u64 {
         match self {
-            Inter::Untouched => unsafe { std::mem::transmute::<f64, u64>(from) },
+            Inter::Untouched => from.to_bits(),
             Inter::Ordered => {
-                let mut result = unsafe { std::mem::transmute::<f64, u64>(from) };
-                result = if from < 0f64 {
-                    !result
-                } else {
-                    result | (1 << 63)
-                };
                 // see comment @to_u32()
-                if result > 0x7FC0_0000_0000_0000 && from.is_nan() {
-                    return !result;
+                let bits = from.to_bits();
+                if bits >> 63 == 1 {
+                    !bits
+                } else {
+                    bits | (1 << 63)
                 }
-                return result;
             }
         }
     }
     fn from_u64(&self, from: u64) -> f64 {
         match self {
-            Inter::Untouched => unsafe { std::mem::transmute::<u64, f64>(from) },
+            Inter::Untouched => f64::from_bits(from),
             Inter::Ordered => {
                 if from < (1 << 63) {
-                    return unsafe { std::mem::transmute::<u64, f64>(!from) };
+                    f64::from_bits(!from)
                 } else {
-                    return unsafe { std::mem::transmute::<u64, f64>(from - (1 << 63)) };
+                    f64::from_bits(from - (1 << 63))
                 }
             }
         }
@@ -180,56 +175,31 @@ impl CompactMapping for Compact {
         match self {
             Compact::Untouched => data,
             Compact::NoLZC => {
-                let bits = 32;
+                // Bits are collected at the lower end of `acc` and written
+                // as soon as a full word is available.
                 let mut result: Vec<u32> = Vec::new();
-
-                let mut remaining = bits;
-                let mut tmp = 0u32;
+                let mut acc = 0u64;
+                let mut filled = 0;
                 for val in data {
-                    let size = bits - val.leading_zeros();
-                    if size <= remaining {
-                        tmp = add(val, &remaining, &tmp);
-                        remaining -= size;
-                    } else {
-                        let (a, b, zeros) = split(val, &remaining);
-
-                        tmp = add(a, &remaining, &tmp);
-                        result.push(tmp);
-                        tmp = if b != 0 {
-                            b << b.leading_zeros() - zeros
-                        } else {
-                            0u32
-                        };
-                        remaining = bits - (bits - b.leading_zeros()) - zeros;
+                    let size = 32 - val.leading_zeros();
+                    acc = (acc << size) | u64::from(val);
+                    filled += size;
+                    if filled >= 32 {
+                        filled -= 32;
+                        result.push((acc >> filled) as u32);
+                        acc &= (1u64 << filled) - 1;
                     }
                 }
-                result.push(tmp);
+                if filled > 0 || result.is_empty() {
+                    result.push((acc << (32 - filled)) as u32);
+                }
                 result
             }
         }
     }
 }
 
-fn add(value: u32, remaining: &u32, into: &u32) -> u32 {
-    let shift = remaining - (32 - value.leading_zeros());
-    let tmp = value << shift;
-    into + tmp
-}
-
-fn split(val: u32, pos: &u32) -> (u32, u32, u32) {
-    let valuelength = 32 - val.leading_zeros();
-    let a = val >> (valuelength - pos);
-    let b = val & 2u32.pow(32 - val.leading_zeros() - pos) - 1;
-    let zeros = if b != 0 {
-        32 - val.leading_zeros() - pos - (32 - b.leading_zeros())
-    } else {
-        32 - val.leading_zeros() - pos - (32 - b.leading_zeros())
-    };
-
-    (a, b, zeros)
-}
-
-#[allow(unused_imports)]
+#[allow(unused_imports, clippy::useless_vec)]
 mod tests {
     use super::*;
 
@@ -253,8 +223,8 @@ mod tests {
             -53f32,
             -0f32,
             0f32,
-            std::f32::INFINITY,
-            std::f32::NAN,
+            f32::INFINITY,
+            f32::NAN,
         ];
         for val in tests {
             if val.is_nan() {
@@ -277,8 +247,8 @@ mod tests {
             8393.42,
             0.0,
             -0.0,
-            std::f64::INFINITY,
-            std::f64::NAN,
+            f64::INFINITY,
+            f64::NAN,
         ];
         for val in tests {
             if val.is_nan() {
@@ -330,6 +300,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn ordered_is_bit_exact() {
+        let tests = vec![0x0000_0000u32, 0x8000_0000, 0x7FC0_0001, 0xFFC0_1234, 0x7F80_0001, 0xFF80_0000];
+        for bits in tests {
+            let val = f32::from_bits(bits);
+            assert_eq!(Inter::Ordered.from_u32(Inter::Ordered.to_u32(val)).to_bits(), bits);
+        }
+        let tests = vec![0x8000_0000_0000_0000u64, 0x7FF8_0000_0000_0001, 0xFFF0_0000_0000_0001];
+        for bits in tests {
+            let val = f64::from_bits(bits);
+            assert_eq!(Inter::Ordered.from_u64(Inter::Ordered.to_u64(val)).to_bits(), bits);
+        }
+        assert!(Inter::Ordered.to_u32(-0.0) < Inter::Ordered.to_u32(0.0));
+    }
+
+    #[test]
+    fn gray_codes_roundtrip() {
+        for &n in [0u32, 1, 2, 15, 0x8000_0000, 0xDEAD_BEEF, u32::MAX].iter() {
+            assert_eq!(Intra::Gray.from_new_u32(Intra::Gray.to_new_u32(n)), n);
+            let n = u64::from(n) << 31 | 0x1234;
+            assert_eq!(Intra::Gray.from_new_u64(Intra::Gray.to_new_u64(n)), n);
+        }
+    }
+
     #[test]
     fn classic_gray_codes() {
         let input: Vec<u32> = vec![15, 5, 6, 3, 1];
@@ -389,6 +383,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn compact_without_leading_zeros() {
+        let data = vec![0b101, 0, 0xFFFF_FFFF, 0b1, 0xF000_0000];
+        let result = Compact::NoLZC.compact_u32(data);
+        assert_eq!(result, vec![0b1011_1111_1111_1111_1111_1111_1111_1111, 0xFF00_0000, 0]);
+        assert_eq!(Compact::NoLZC.compact_u32(vec![0, 0]), vec![0]);
+        assert_eq!(Compact::NoLZC.compact_u32(vec![u32::MAX, 1]), vec![u32::MAX, 1 << 31]);
+    }
+
     #[test]
     fn test_hashmap_size() {
         assert_eq!(arrays::IX_MONO.len(), 256);
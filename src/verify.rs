@@ -0,0 +1,113 @@
+/// Round-trip checks
+///
+/// Data is compressed into an in-memory container, read back and decoded.
+/// The result is compared bit by bit with the input, such that NaN payloads
+/// and signed zeros need to survive as well.
+use std::fmt;
+
+use super::codec::{self, Value};
+use super::container::{self, Header};
+use super::error::Result;
+use super::position::Position;
+use super::predictors::Ignorant;
+use super::Pipeline;
+
+/// First value which differs after the round-trip
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mismatch<T> {
+    pub index: usize,
+    pub expected: T,
+    pub found: T,
+}
+
+impl<T: Value + fmt::Debug> fmt::Display for Mismatch<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = 2 * T::BYTES;
+        write!(
+            f,
+            "mismatch at index {}: expected {:?} (0x{:0w$x}), found {:?} (0x{:0w$x})",
+            self.index,
+            self.expected,
+            self.expected.to_raw(),
+            self.found,
+            self.found.to_raw(),
+            w = digits
+        )
+    }
+}
+
+/// Compresses and decompresses `data` and returns the first mismatch, if any.
+///
+/// Pipelines which can not be decoded (e.g. `Compact::NoLZC`) return an error.
+pub fn verify<T: Value>(
+    data: &[T],
+    shape: &Position,
+    predictor: &Ignorant<T>,
+    pipeline: &Pipeline,
+) -> Result<Option<Mismatch<T>>> {
+    let mut encoder = predictor.clone();
+    let chunks = codec::encode_chunks(data, shape, &mut encoder, pipeline)?;
+    let mut bytes = Vec::new();
+    container::write_to(&mut bytes, &Header::new(shape, &encoder, pipeline), &chunks)?;
+
+    let (header, chunks) = container::read_from(&mut bytes.as_slice())?;
+    let mut decoder = header.predictor()?;
+    let decoded: Vec<T> = codec::decode_chunks(&chunks, &header.shape, &mut decoder, &header.pipeline)?;
+    if decoded.len() != data.len() {
+        return Err(container::invalid("Number of decoded values does not match the input"));
+    }
+    let first = data.iter().zip(decoded.iter()).position(|(a, b)| a.to_raw() != b.to_raw());
+    Ok(first.map(|index| Mismatch { index, expected: data[index], found: decoded[index] }))
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::*;
+    use super::super::bench::pipelines;
+    use super::super::order::Order;
+    use super::super::predictors::predictors;
+    use super::super::transform::Compact;
+
+    #[test]
+    fn special_values_survive() {
+        let shape = Position { x: 4, y: 3, z: 2 };
+        let mut data: Vec<f32> = (0..24).map(|i| (i as f32 * 0.3).cos()).collect();
+        data[1] = -0.0;
+        data[5] = f32::from_bits(0x7FC0_1234);
+        data[6] = f32::from_bits(0xFF80_0001);
+        data[11] = f32::INFINITY;
+        data[17] = f32::from_bits(1);
+        for pipeline in pipelines(4).iter().filter(|p| p.compact == Compact::Untouched) {
+            for predictor in &[predictors::get_last_value_f32(), predictors::get_lorenz_f32()] {
+                assert_eq!(verify(&data, &shape, predictor, pipeline).unwrap(), None, "{:?}", pipeline);
+            }
+        }
+        let pipeline = Pipeline { order: Order::Hilbert, tile: Some(Position { x: 2, y: 2, z: 1 }), ..Pipeline::default() };
+        assert_eq!(verify(&data, &shape, &predictors::get_lorenz_f32(), &pipeline).unwrap(), None);
+    }
+
+    #[test]
+    fn special_values_survive_f64() {
+        let shape = Position { x: 3, y: 2, z: 1 };
+        let data = vec![-0.0f64, f64::from_bits(0x7FF8_0000_0000_0042), f64::from_bits(0xFFF0_0000_0000_0001), 1.5, f64::NEG_INFINITY, 0.0];
+        for pipeline in pipelines(8) {
+            assert_eq!(verify(&data, &shape, &predictors::get_lorenz_f64(), &pipeline).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn report_mismatch() {
+        let mismatch = Mismatch { index: 3, expected: -0.0f32, found: 0.0 };
+        assert_eq!(
+            mismatch.to_string(),
+            "mismatch at index 3: expected -0.0 (0x80000000), found 0.0 (0x00000000)"
+        );
+    }
+
+    #[test]
+    fn compaction_is_not_verifiable() {
+        let shape = Position { x: 2, y: 1, z: 1 };
+        let pipeline = Pipeline { compact: Compact::NoLZC, ..Pipeline::default() };
+        assert!(verify(&[1.0f32, 2.0], &shape, &predictors::get_last_value_f32(), &pipeline).is_err());
+    }
+}
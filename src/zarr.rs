@@ -0,0 +1,477 @@
+/// Zarr arrays compressed with pzip
+///
+/// pzip acts as the compressor of Zarr v2 (`"compressor": {"id": "pzip",
+/// ...}` in `.zarray`) and as the array to bytes codec of Zarr v3
+/// (`{"name": "pzip", "configuration": {...}}` in `zarr.json`). Every chunk
+/// is stored as a pzip container, such that a chunk can be decoded without
+/// the metadata of the array.
+///
+/// Arrays are read from and written to local directory stores. Edge chunks
+/// are padded with the fill value, like Zarr does.
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as Json};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::codec::{self, Value};
+use super::config::{choice, FileType, Predictor};
+use super::container::Header;
+use super::endian::{self, Endian};
+use super::error::{Error, Result};
+use super::transform::{Byte, Inter, Intra};
+use super::{compress, decompress_from, Options, Pipeline, Shape};
+
+pub const NAME: &str = "pzip";
+
+/// Configuration of the codec, as stored in the metadata
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Codec {
+    /// `lv` (default) or `lorenz`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub predictor: Option<String>,
+    /// `raw` (default) or `ordered`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inter: Option<String>,
+    /// `untouched` (default) or `gray`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intra: Option<String>,
+    /// `untouched` (default) or `mono`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte: Option<String>,
+}
+
+impl Codec {
+    pub fn predictor(&self) -> Result<Predictor> {
+        match &self.predictor {
+            Some(v) => choice("predictor", v, vec![("lv", Predictor::LastValue), ("lorenz", Predictor::Lorenz)]),
+            None => Ok(Predictor::LastValue),
+        }
+    }
+
+    pub fn pipeline(&self) -> Result<Pipeline> {
+        let value = |v: &Option<String>, default: &'static str| v.clone().unwrap_or_else(|| default.to_string());
+        Ok(Pipeline {
+            inter: choice("inter", &value(&self.inter, "raw"), vec![("raw", Inter::Untouched), ("ordered", Inter::Ordered)])?,
+            intra: choice("intra", &value(&self.intra, "untouched"), vec![("untouched", Intra::Untouched), ("gray", Intra::Gray)])?,
+            byte: choice("byte", &value(&self.byte, "untouched"), vec![("untouched", Byte::Untouched), ("mono", Byte::MonoGray)])?,
+            ..Pipeline::default()
+        })
+    }
+
+    fn encode_values<T: Value>(&self, bytes: &[u8], chunk: &[usize], endian: Endian) -> Result<Vec<u8>> {
+        let p = codec::fold(chunk)?;
+        if codec::size(&p)? * T::BYTES != bytes.len() {
+            return Err(Error::SizeMismatch(format!("{} bytes do not match the chunk {:?}", bytes.len(), chunk)));
+        }
+        let data: Vec<T> = endian::values(bytes, endian);
+        let shape = Shape { x: p.x as usize, y: p.y as usize, z: p.z as usize };
+        let options = Options { predictor: self.predictor()?, pipeline: self.pipeline()?, endian, ..Options::default() };
+        compress(&data, shape, &options)
+    }
+
+    /// Compresses the bytes of a chunk with the dimensions `chunk`.
+    pub fn encode(&self, bytes: &[u8], chunk: &[usize], filetype: FileType, endian: Endian) -> Result<Vec<u8>> {
+        match filetype {
+            FileType::F32 => self.encode_values::<f32>(bytes, chunk, endian),
+            FileType::F64 => self.encode_values::<f64>(bytes, chunk, endian),
+        }
+    }
+
+    /// Decompresses a chunk into its bytes, in the byte order given to `encode`.
+    pub fn decode(compressed: &[u8]) -> Result<Vec<u8>> {
+        match Header::read(&mut &compressed[..])?.filetype {
+            FileType::F32 => decode_values::<f32>(compressed),
+            FileType::F64 => decode_values::<f64>(compressed),
+        }
+    }
+}
+
+fn decode_values<T: Value>(compressed: &[u8]) -> Result<Vec<u8>> {
+    let (data, header) = decompress_from::<T, _>(&mut &compressed[..])?;
+    Ok(endian::bytes(&data, header.endian))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    V2,
+    V3,
+}
+
+/// Array in a local directory store
+#[derive(Debug, Clone, PartialEq)]
+pub struct Array {
+    pub path: PathBuf,
+    pub format: Format,
+    pub shape: Vec<usize>,
+    pub chunks: Vec<usize>,
+    pub filetype: FileType,
+    /// Byte order of the chunks before compression
+    pub endian: Endian,
+    pub fill_value: f64,
+    pub codec: Codec,
+    /// Separator of the chunk indices in the keys
+    pub separator: String,
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::Corrupt(format!("Invalid Zarr metadata: {}", msg))
+}
+
+fn fill_json(value: f64) -> Json {
+    if value.is_nan() {
+        json!("NaN")
+    } else if value.is_infinite() {
+        json!(if value > 0.0 { "Infinity" } else { "-Infinity" })
+    } else {
+        json!(value)
+    }
+}
+
+fn fill_value(value: &Json) -> Result<f64> {
+    match value {
+        Json::Null => Ok(0.0),
+        Json::Number(n) => n.as_f64().ok_or_else(|| invalid("fill_value")),
+        Json::String(s) if s == "NaN" => Ok(f64::NAN),
+        Json::String(s) if s == "Infinity" => Ok(f64::INFINITY),
+        Json::String(s) if s == "-Infinity" => Ok(f64::NEG_INFINITY),
+        _ => Err(invalid("fill_value")),
+    }
+}
+
+fn dims(value: &Json, key: &str) -> Result<Vec<usize>> {
+    value
+        .as_array()
+        .ok_or_else(|| invalid(key))?
+        .iter()
+        .map(|d| d.as_u64().map(|d| d as usize).ok_or_else(|| invalid(key)))
+        .collect()
+}
+
+fn codec_of(value: &Json) -> Result<Codec> {
+    serde_json::from_value(value.clone()).map_err(|e| invalid(&format!("pzip configuration: {}", e)))
+}
+
+impl Array {
+    /// Creates the metadata of a new array at `path`.
+    pub fn create(
+        path: &Path,
+        format: Format,
+        shape: &[usize],
+        chunks: &[usize],
+        filetype: FileType,
+        codec: Codec,
+    ) -> Result<Array> {
+        if shape.len() != chunks.len() || chunks.contains(&0) {
+            return Err(Error::BadShape(format!("Invalid chunks {:?} for the shape {:?}", chunks, shape)));
+        }
+        let separator = match format {
+            Format::V2 => ".",
+            Format::V3 => "/",
+        };
+        let array = Array {
+            path: path.to_path_buf(),
+            format,
+            shape: shape.to_vec(),
+            chunks: chunks.to_vec(),
+            filetype,
+            endian: Endian::Little,
+            fill_value: 0.0,
+            codec,
+            separator: separator.to_string(),
+        };
+        fs::create_dir_all(path)?;
+        let (name, metadata) = array.metadata()?;
+        fs::write(path.join(name), serde_json::to_string_pretty(&metadata).map_err(io::Error::from)?)?;
+        Ok(array)
+    }
+
+    fn metadata(&self) -> Result<(&'static str, Json)> {
+        let mut codec = serde_json::to_value(&self.codec).map_err(io::Error::from)?;
+        let endian = if self.endian == Endian::Big { "big" } else { "little" };
+        match self.format {
+            Format::V2 => {
+                codec["id"] = json!(NAME);
+                let order = if self.endian == Endian::Big { ">" } else { "<" };
+                let size = if self.filetype == FileType::F32 { 4 } else { 8 };
+                let metadata = json!({
+                    "zarr_format": 2,
+                    "shape": self.shape,
+                    "chunks": self.chunks,
+                    "dtype": format!("{}f{}", order, size),
+                    "compressor": codec,
+                    "fill_value": fill_json(self.fill_value),
+                    "order": "C",
+                    "filters": null,
+                    "dimension_separator": self.separator,
+                });
+                Ok((".zarray", metadata))
+            }
+            Format::V3 => {
+                codec["endian"] = json!(endian);
+                let metadata = json!({
+                    "zarr_format": 3,
+                    "node_type": "array",
+                    "shape": self.shape,
+                    "data_type": if self.filetype == FileType::F32 { "float32" } else { "float64" },
+                    "chunk_grid": {"name": "regular", "configuration": {"chunk_shape": self.chunks}},
+                    "chunk_key_encoding": {"name": "default", "configuration": {"separator": self.separator}},
+                    "fill_value": fill_json(self.fill_value),
+                    "codecs": [{"name": NAME, "configuration": codec}],
+                    "attributes": {},
+                });
+                Ok(("zarr.json", metadata))
+            }
+        }
+    }
+
+    /// Opens the array at `path`, which has to be compressed with pzip.
+    pub fn open(path: &Path) -> Result<Array> {
+        let (format, text) = match fs::read_to_string(path.join("zarr.json")) {
+            Ok(text) => (Format::V3, text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (Format::V2, fs::read_to_string(path.join(".zarray"))?),
+            Err(e) => return Err(e.into()),
+        };
+        let m: Json = serde_json::from_str(&text).map_err(|e| invalid(&e.to_string()))?;
+        let shape = dims(&m["shape"], "shape")?;
+        let fill_value = fill_value(&m["fill_value"])?;
+        let (chunks, filetype, endian, codec, separator) = match format {
+            Format::V2 => {
+                let (endian, filetype) = match m["dtype"].as_str() {
+                    Some("<f4") => (Endian::Little, FileType::F32),
+                    Some("<f8") => (Endian::Little, FileType::F64),
+                    Some(">f4") => (Endian::Big, FileType::F32),
+                    Some(">f8") => (Endian::Big, FileType::F64),
+                    _ => return Err(Error::InvalidInput(format!("Unsupported dtype {}", m["dtype"]))),
+                };
+                if m["order"].as_str() != Some("C") || !(m["filters"].is_null() || m["filters"] == json!([])) {
+                    return Err(Error::InvalidInput("Only C order arrays without filters are supported".to_string()));
+                }
+                let mut compressor = m["compressor"].clone();
+                if compressor["id"].as_str() != Some(NAME) {
+                    return Err(Error::InvalidInput(format!("Array is not compressed with pzip: {}", compressor)));
+                }
+                compressor.as_object_mut().map(|c| c.remove("id"));
+                let separator = m["dimension_separator"].as_str().unwrap_or(".").to_string();
+                (dims(&m["chunks"], "chunks")?, filetype, endian, codec_of(&compressor)?, separator)
+            }
+            Format::V3 => {
+                if m["chunk_grid"]["name"].as_str() != Some("regular") {
+                    return Err(Error::InvalidInput("Only regular chunk grids are supported".to_string()));
+                }
+                let filetype = match m["data_type"].as_str() {
+                    Some("float32") => FileType::F32,
+                    Some("float64") => FileType::F64,
+                    _ => return Err(Error::InvalidInput(format!("Unsupported data type {}", m["data_type"]))),
+                };
+                let codecs = m["codecs"].as_array().cloned().unwrap_or_default();
+                let mut configuration = match codecs.as_slice() {
+                    [codec] if codec["name"].as_str() == Some(NAME) => codec["configuration"].clone(),
+                    _ => return Err(Error::InvalidInput("Expected pzip as the only codec".to_string())),
+                };
+                let endian = match configuration.as_object_mut().and_then(|c| c.remove("endian")) {
+                    Some(e) if e == "big" => Endian::Big,
+                    _ => Endian::Little,
+                };
+                if configuration.is_null() {
+                    configuration = json!({});
+                }
+                let encoding = &m["chunk_key_encoding"];
+                let separator = match encoding["name"].as_str() {
+                    Some("default") => encoding["configuration"]["separator"].as_str().unwrap_or("/").to_string(),
+                    _ => return Err(Error::InvalidInput("Only the default chunk key encoding is supported".to_string())),
+                };
+                let chunks = dims(&m["chunk_grid"]["configuration"]["chunk_shape"], "chunk_shape")?;
+                (chunks, filetype, endian, codec_of(&configuration)?, separator)
+            }
+        };
+        if chunks.len() != shape.len() || chunks.contains(&0) {
+            return Err(invalid("chunks do not match the shape"));
+        }
+        Ok(Array { path: path.to_path_buf(), format, shape, chunks, filetype, endian, fill_value, codec, separator })
+    }
+
+    /// Number of chunks along every dimension
+    pub fn grid(&self) -> Vec<usize> {
+        self.shape.iter().zip(self.chunks.iter()).map(|(s, c)| s.div_ceil(*c)).collect()
+    }
+
+    /// Path of the chunk with the indices `index`
+    pub fn key(&self, index: &[usize]) -> PathBuf {
+        let indices: Vec<String> = index.iter().map(|i| i.to_string()).collect();
+        let key = match self.format {
+            Format::V2 if indices.is_empty() => "0".to_string(),
+            Format::V2 => indices.join(&self.separator),
+            Format::V3 => std::iter::once("c".to_string()).chain(indices).collect::<Vec<String>>().join(&self.separator),
+        };
+        self.path.join(key)
+    }
+
+    /// Writes `data` (in C order) as chunks of the array.
+    pub fn write<T: Value>(&self, data: &[T]) -> Result<()> {
+        self.check::<T>(data.len())?;
+        let fill = T::from_f64(self.fill_value);
+        for index in indices(&self.grid()) {
+            let chunk: Vec<T> = self.positions(&index).map(|i| i.map_or(fill, |i| data[i])).collect();
+            let bytes = endian::bytes(&chunk, self.endian);
+            let compressed = self.codec.encode(&bytes, &self.chunks, self.filetype, self.endian)?;
+            let key = self.key(&index);
+            if let Some(parent) = key.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(key, compressed)?;
+        }
+        Ok(())
+    }
+
+    /// Reads all values in C order, missing chunks hold the fill value.
+    pub fn read<T: Value>(&self) -> Result<Vec<T>> {
+        let n = self.shape.iter().product();
+        self.check::<T>(n)?;
+        let mut data = vec![T::from_f64(self.fill_value); n];
+        for index in indices(&self.grid()) {
+            let compressed = match fs::read(self.key(&index)) {
+                Ok(bytes) => bytes,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let chunk: Vec<T> = endian::values(&Codec::decode(&compressed)?, self.endian);
+            if chunk.len() != self.chunks.iter().product::<usize>() {
+                return Err(Error::Corrupt(format!("Chunk {:?} does not match the chunk shape", index)));
+            }
+            for (value, i) in chunk.into_iter().zip(self.positions(&index)) {
+                if let Some(i) = i {
+                    data[i] = value;
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    fn check<T: Value>(&self, n: usize) -> Result<()> {
+        if T::FILETYPE != self.filetype {
+            return Err(Error::InvalidInput(format!("Array contains {:?} data", self.filetype)));
+        }
+        if n != self.shape.iter().product::<usize>() {
+            return Err(Error::SizeMismatch(format!("{} values do not match the shape {:?}", n, self.shape)));
+        }
+        Ok(())
+    }
+
+    /// Index into the whole array of every value of the chunk `index`,
+    /// `None` for padding beyond the edge of the array.
+    fn positions<'a>(&'a self, index: &'a [usize]) -> impl Iterator<Item = Option<usize>> + 'a {
+        indices(&self.chunks).map(move |local| {
+            let mut flat = 0;
+            for d in 0..self.shape.len() {
+                let i = index[d] * self.chunks[d] + local[d];
+                if i >= self.shape[d] {
+                    return None;
+                }
+                flat = flat * self.shape[d] + i;
+            }
+            Some(flat)
+        })
+    }
+}
+
+/// All multi-indices below `extents` in C order
+fn indices(extents: &[usize]) -> impl Iterator<Item = Vec<usize>> {
+    let extents = extents.to_vec();
+    let total: usize = extents.iter().product();
+    (0..total).map(move |mut n| {
+        let mut index = vec![0; extents.len()];
+        for d in (0..extents.len()).rev() {
+            index[d] = n % extents[d];
+            n /= extents[d];
+        }
+        index
+    })
+}
+
+#[allow(unused_imports, dead_code)]
+mod tests {
+    use super::*;
+
+    fn field(n: usize) -> Vec<f32> {
+        (0..n).map(|i| 250.0 + (i as f32 * 0.07).sin() * 10.0).collect()
+    }
+
+    #[test]
+    fn codec_on_chunk_bytes() {
+        let codec = Codec { predictor: Some("lorenz".to_string()), inter: Some("ordered".to_string()), ..Codec::default() };
+        let values = field(4 * 5 * 6);
+        let bytes = endian::bytes(&values, Endian::Big);
+        let compressed = codec.encode(&bytes, &[4, 5, 6], FileType::F32, Endian::Big).unwrap();
+        assert_eq!(Codec::decode(&compressed).unwrap(), bytes);
+        assert!(codec.encode(&bytes, &[4, 5, 5], FileType::F32, Endian::Big).is_err());
+        let wrong = Codec { intra: Some("nope".to_string()), ..Codec::default() };
+        assert!(wrong.encode(&bytes, &[4, 5, 6], FileType::F32, Endian::Big).is_err());
+    }
+
+    /// Directory of a single test
+    fn store(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pzip_zarr_{}_{}.zarr", name, std::process::id()))
+    }
+
+    #[test]
+    fn chunk_keys() {
+        let path = store("keys");
+        let v2 = Array::create(&path, Format::V2, &[10, 10], &[4, 4], FileType::F32, Codec::default()).unwrap();
+        assert_eq!(v2.grid(), vec![3, 3]);
+        assert_eq!(v2.key(&[2, 1]), path.join("2.1"));
+        let v3 = Array { format: Format::V3, separator: "/".to_string(), ..v2 };
+        assert_eq!(v3.key(&[2, 1]), path.join("c/2/1"));
+        assert_eq!(v3.key(&[]), path.join("c"));
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn directory_stores() {
+        let values = field(5 * 7 * 9);
+        let codec = Codec { predictor: Some("lorenz".to_string()), byte: Some("mono".to_string()), ..Codec::default() };
+        for (format, name) in &[(Format::V2, ".zarray"), (Format::V3, "zarr.json")] {
+            let path = &store("store");
+            let _ = fs::remove_dir_all(path);
+            let array = Array::create(path, *format, &[5, 7, 9], &[2, 4, 4], FileType::F32, codec.clone()).unwrap();
+            array.write(&values).unwrap();
+            assert!(path.join(name).is_file());
+            assert_eq!(fs::read_dir(path).unwrap().count(), if *format == Format::V2 { 3 * 2 * 3 + 1 } else { 2 });
+
+            let opened = Array::open(path).unwrap();
+            assert_eq!(opened, array);
+            assert_eq!(opened.read::<f32>().unwrap(), values);
+            assert!(opened.read::<f64>().is_err());
+
+            // missing chunks hold the fill value
+            fs::remove_file(opened.key(&[0, 0, 0])).unwrap();
+            assert_eq!(opened.read::<f32>().unwrap()[0], 0.0);
+            fs::remove_dir_all(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn metadata_of_other_writers() {
+        let path = &store("metadata");
+        let _ = fs::remove_dir_all(path);
+        fs::create_dir_all(path).unwrap();
+        let zarray = r#"{"zarr_format": 2, "shape": [6], "chunks": [4], "dtype": ">f8", "order": "C",
+            "compressor": {"id": "pzip", "predictor": "lv"}, "fill_value": "NaN", "filters": null}"#;
+        fs::write(path.join(".zarray"), zarray).unwrap();
+        let array = Array::open(path).unwrap();
+        assert_eq!((array.endian, array.filetype, array.separator.as_str()), (Endian::Big, FileType::F64, "."));
+        assert!(array.fill_value.is_nan());
+        array.write(&[1.0f64, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        assert_eq!(array.read::<f64>().unwrap(), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        // only a missing zarr.json falls back to v2
+        fs::create_dir(path.join("zarr.json")).unwrap();
+        assert!(matches!(Array::open(path), Err(Error::Io(_))));
+        fs::remove_dir(path.join("zarr.json")).unwrap();
+
+        fs::write(path.join(".zarray"), zarray.replace("pzip", "blosc")).unwrap();
+        assert!(Array::open(path).is_err());
+        fs::remove_dir_all(path).unwrap();
+    }
+}
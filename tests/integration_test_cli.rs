@@ -0,0 +1,400 @@
+use std::process::Command;
+
+fn pzip(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_pzip")).args(args).output().expect("Could not run pzip")
+}
+
+/// Path of `name` in the temporary directory, unique to this test run
+fn temp(name: &str) -> String {
+    std::env::temp_dir().join(format!("{}_{}", std::process::id(), name)).to_string_lossy().into_owned()
+}
+
+#[test]
+fn encode_and_decode_with_binary() {
+    let input = &temp("cli_input_f64.raw");
+    let compressed = &temp("cli_output_f64.pzip");
+    let output = &temp("cli_output_f64.raw");
+    let data: Vec<u8> = (0..2 * 3 * 4)
+        .flat_map(|i| (f64::from(i) * 0.5).to_le_bytes().to_vec())
+        .collect();
+    std::fs::write(input, &data).expect("Error");
+
+    let options = ["--shape", "2x3x4", "--predictor", "lorenz", "--inter", "ordered", "--byte", "mono", "--intra", "gray"];
+    let mut args = vec!["-c", "--type", "f64", input, compressed];
+    args.extend_from_slice(&options);
+    assert!(pzip(&args).status.success());
+    assert!(pzip(&["-d", "--type", "f64", compressed, output]).status.success());
+    let typed = std::fs::read(output).expect("Error");
+    assert!(pzip(&["-d", compressed, output]).status.success());
+
+    let result = std::fs::read(output).expect("Error");
+    std::fs::remove_file(input).expect("Error");
+    std::fs::remove_file(compressed).expect("Error");
+    std::fs::remove_file(output).expect("Error");
+    assert_eq!(typed, data);
+    assert_eq!(result, data);
+}
+
+#[test]
+fn failures_exit_non_zero() {
+    let result = pzip(&["-c", "-s", "1x1x1", "/tmp/does/not/exist.raw", &temp("cli_missing.pzip")]);
+    assert_eq!(result.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&result.stderr).starts_with("pzip: "));
+
+    let result = pzip(&["-c", "in.raw", "out.pzip", "--shape", "90x351"]);
+    assert_eq!(result.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&result.stderr).contains("ZxYxX"));
+}
+
+#[test]
+fn undecodable_pipelines_are_refused() {
+    let input = temp("cli_nolzc_f32.raw");
+    let compressed = temp("cli_nolzc_f32.pzip");
+    let data: Vec<u8> = (0..2 * 3 * 4).flat_map(|i| (i as f32).to_le_bytes().to_vec()).collect();
+    std::fs::write(&input, &data).expect("Error");
+
+    let result = pzip(&["-c", "--shape", "2x3x4", "--compact", "nolzc", &input, &compressed]);
+    std::fs::remove_file(&input).expect("Error");
+    assert_eq!(result.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&result.stderr).contains("can not be decoded"));
+    assert!(!std::path::Path::new(&compressed).exists());
+}
+
+#[test]
+fn scan_orders_are_restored() {
+    let input = temp("cli_order_f32.raw");
+    let data: Vec<u8> = (0..3 * 5 * 7).flat_map(|i| (i as f32 * 0.25).to_le_bytes().to_vec()).collect();
+    std::fs::write(&input, &data).expect("Error");
+
+    for order in &["x,-z,y", "serpentine", "morton", "hilbert"] {
+        let compressed = temp(&format!("cli_order_{}.pzip", order));
+        let output = temp(&format!("cli_order_{}.raw", order));
+        assert!(pzip(&["-c", "--shape", "3x5x7", "--order", order, &input, &compressed]).status.success());
+        let info = pzip(&["info", &compressed]);
+        assert!(String::from_utf8_lossy(&info.stdout).contains(order.replace("x,-z,y", "linear x,-z,y").as_str()));
+        assert!(pzip(&["-d", &compressed, &output]).status.success());
+        let result = std::fs::read(&output).expect("Error");
+        std::fs::remove_file(&compressed).expect("Error");
+        std::fs::remove_file(&output).expect("Error");
+        assert_eq!(result, data, "{}", order);
+    }
+    std::fs::remove_file(&input).expect("Error");
+}
+
+#[test]
+fn tiles_are_encoded_independently() {
+    let input = temp("cli_tile_f64.raw");
+    let compressed = temp("cli_tile_f64.pzip");
+    let output = temp("cli_tile_out_f64.raw");
+    let data: Vec<u8> = (0..2 * 6 * 10).flat_map(|i| (f64::from(i) * 0.5).to_le_bytes().to_vec()).collect();
+    std::fs::write(&input, &data).expect("Error");
+
+    assert!(pzip(&["-c", "-t", "f64", "-s", "2x6x10", "--tile", "0x4x4", &input, &compressed]).status.success());
+    let info = pzip(&["info", &compressed]);
+    assert!(String::from_utf8_lossy(&info.stdout).contains("0x4x4"), "{}", String::from_utf8_lossy(&info.stdout));
+    assert!(pzip(&["-d", "-t", "f64", &compressed, &output]).status.success());
+
+    let result = std::fs::read(&output).expect("Error");
+    std::fs::remove_file(&input).expect("Error");
+    std::fs::remove_file(&compressed).expect("Error");
+    std::fs::remove_file(&output).expect("Error");
+    assert_eq!(result, data);
+}
+
+#[test]
+fn regions_are_decoded_from_tiles() {
+    let input = temp("cli_region_f32.raw");
+    let compressed = temp("cli_region_f32.pzip");
+    let output = temp("cli_region_out_f32.raw");
+    let values: Vec<f32> = (0..3 * 6 * 10).map(|i| i as f32).collect();
+    let data: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+    std::fs::write(&input, &data).expect("Error");
+
+    assert!(pzip(&["-c", "-s", "3x6x10", "--tile", "1x4x4", &input, &compressed]).status.success());
+    assert!(pzip(&["-d", "--region", "1x2x3:2x3x4", &compressed, &output]).status.success());
+    let result = std::fs::read(&output).expect("Error");
+    let outside = pzip(&["-d", "--region", "2x2x3:2x3x4", &compressed, &output]);
+    std::fs::remove_file(&input).expect("Error");
+    std::fs::remove_file(&compressed).expect("Error");
+    std::fs::remove_file(&output).expect("Error");
+
+    let mut expected = Vec::new();
+    for z in 1..3 {
+        for y in 2..5 {
+            for x in 3..7 {
+                expected.extend_from_slice(&values[(z * 6 + y) * 10 + x].to_le_bytes());
+            }
+        }
+    }
+    assert_eq!(result, expected);
+    assert_eq!(outside.status.code(), Some(1));
+}
+
+#[test]
+fn help_is_printed() {
+    let result = pzip(&["--help"]);
+    assert!(result.status.success());
+    assert!(String::from_utf8_lossy(&result.stdout).starts_with("Usage: pzip"));
+}
+
+#[test]
+fn info_describes_compressed_file() {
+    let input = &temp("cli_info_f32.raw");
+    let compressed = &temp("cli_info_f32.pzip");
+    let data: Vec<u8> = (0..4 * 5 * 6).flat_map(|i| (i as f32).to_le_bytes().to_vec()).collect();
+    std::fs::write(input, &data).expect("Error");
+    assert!(pzip(&["-c", "--preset", "climate-f32", "--shape", "4x5x6", input, compressed]).status.success());
+
+    let result = pzip(&["info", compressed]);
+    std::fs::remove_file(input).expect("Error");
+    std::fs::remove_file(compressed).expect("Error");
+    let text = String::from_utf8_lossy(&result.stdout);
+    assert!(result.status.success());
+    assert!(text.contains("shape:       4x5x6"));
+    assert!(text.contains("predictor:   7 cell(s)"));
+    assert!(text.contains("raw:         480 bytes"));
+
+    let result = pzip(&["info", "/tmp/does/not/exist.pzip"]);
+    assert_eq!(result.status.code(), Some(1));
+}
+
+#[test]
+fn bench_reports_every_combination() {
+    let input = &temp("cli_bench_f32.raw");
+    let data: Vec<u8> = (0..3 * 4 * 5).flat_map(|i| (i as f32).sqrt().to_le_bytes().to_vec()).collect();
+    std::fs::write(input, &data).expect("Error");
+
+    let result = pzip(&["bench", "--shape", "3x4x5", "--coder", "deflate", "--csv", input]);
+    let wrong = pzip(&["bench", "--shape", "3x4x6", input]);
+    std::fs::remove_file(input).expect("Error");
+
+    assert!(result.status.success());
+    let text = String::from_utf8_lossy(&result.stdout);
+    assert_eq!(text.lines().count(), 1 + 2 * 32);
+    assert!(text.lines().all(|l| l.split(',').count() == 10));
+    assert_eq!(wrong.status.code(), Some(1));
+}
+
+#[test]
+fn verify_special_values() {
+    let input = &temp("cli_verify_f32.raw");
+    let mut values: Vec<f32> = (0..2 * 3 * 4).map(|i| (i as f32 * 0.7).sin()).collect();
+    values[3] = -0.0;
+    values[8] = f32::from_bits(0x7FC0_0ABC);
+    values[9] = f32::from_bits(0xFFC0_0001);
+    let data: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+    std::fs::write(input, &data).expect("Error");
+
+    let result = pzip(&["verify", "--preset", "climate-f32", "--shape", "2x3x4", input]);
+    let compacted = pzip(&["verify", "--compact", "nolzc", "--shape", "2x3x4", input]);
+    std::fs::remove_file(input).expect("Error");
+
+    assert!(result.status.success());
+    assert!(String::from_utf8_lossy(&result.stdout).starts_with("ok: 24 values"));
+    assert_eq!(compacted.status.code(), Some(1));
+}
+
+#[test]
+fn batch_compresses_directory() {
+    let dir = &std::path::PathBuf::from(temp("cli_batch"));
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).expect("Error");
+    let data: Vec<u8> = (0..2 * 3 * 4).flat_map(|i| (i as f64).cos().to_le_bytes().to_vec()).collect();
+    std::fs::write(dir.join("icon.ml.qv.f64.little.2x2x3x4_0.raw"), &data).expect("Error");
+    std::fs::write(dir.join("field.raw"), &data).expect("Error");
+    std::fs::write(dir.join("field.raw.toml"), "type = 'f64'\nshape = '4x3x2'\n").expect("Error");
+
+    let out = dir.join("out");
+    let out = out.to_str().unwrap();
+    let result = pzip(&["batch", "--preset", "climate-f64", dir.to_str().unwrap(), out]);
+    let text = String::from_utf8_lossy(&result.stdout).into_owned();
+    let field = temp("cli_batch_field.raw");
+    let decoded = pzip(&["-d", "--type", "f64", &format!("{}/field.raw.pzip", out), &field]);
+    let roundtrip = std::fs::read(&field).expect("Error");
+    std::fs::remove_dir_all(dir).expect("Error");
+    std::fs::remove_file(&field).expect("Error");
+
+    assert!(result.status.success());
+    assert!(text.contains("2 compressed, 0 skipped, 0 failed, 384 -> "));
+    assert!(decoded.status.success());
+    assert_eq!(roundtrip, data);
+}
+
+#[test]
+fn shape_must_match_the_input() {
+    let input = &temp("cli_partial_f32.raw");
+    let compressed = &temp("cli_partial_f32.pzip");
+    let output = &temp("cli_partial_out_f32.raw");
+    let data: Vec<u8> = (0..2 * 3 * 4 * 2 + 5).flat_map(|i| (i as f32).sqrt().to_le_bytes().to_vec()).collect();
+    std::fs::write(input, &data).expect("Error");
+
+    let strict = pzip(&["-c", "--shape", "2x3x4", input, compressed]);
+    let partial = pzip(&["-c", "--partial", "--shape", "2x3x4", input, compressed]);
+    let decoded = pzip(&["-d", compressed, output]);
+    let info = pzip(&["info", compressed]);
+    let roundtrip = std::fs::read(output).expect("Error");
+    for file in &[input, compressed, output] {
+        std::fs::remove_file(file).expect("Error");
+    }
+
+    assert_eq!(strict.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&strict.stderr).contains("53 values do not match the shape"));
+    assert!(partial.status.success());
+    assert!(decoded.status.success());
+    assert_eq!(roundtrip, data);
+    let text = String::from_utf8_lossy(&info.stdout);
+    assert!(text.contains("shape:       4x3x4"));
+    assert!(text.contains("trailing:    5 value(s)"));
+}
+
+#[test]
+fn big_endian_input_is_restored() {
+    let input = &temp("cli_big_f64.raw");
+    let compressed = &temp("cli_big_f64.pzip");
+    let output = &temp("cli_big_out_f64.raw");
+    let data: Vec<u8> = (0..4 * 16 * 16).flat_map(|i| (250.0 + (i as f64 * 0.02).sin()).to_be_bytes().to_vec()).collect();
+    std::fs::write(input, &data).expect("Error");
+
+    let encoded = pzip(&["-c", "-t", "f64", "--endian", "auto", "--shape", "4x16x16", input, compressed]);
+    let decoded = pzip(&["-d", "-t", "f64", compressed, output]);
+    let info = pzip(&["info", compressed]);
+    let roundtrip = std::fs::read(output).expect("Error");
+    for file in &[input, compressed, output] {
+        std::fs::remove_file(file).expect("Error");
+    }
+
+    assert!(encoded.status.success());
+    assert!(decoded.status.success());
+    assert_eq!(roundtrip, data);
+    assert!(String::from_utf8_lossy(&info.stdout).contains("endian:      big"));
+}
+
+#[test]
+fn numpy_files_describe_themselves() {
+    use pzip::npy::{NpySink, NpySource};
+    use pzip::testing::{CompressedFile, FileToBeCompressed};
+
+    let input = &temp("cli_numpy_in.npy");
+    let compressed = &temp("cli_numpy.pzip");
+    let output = &temp("cli_numpy_out.npz");
+    let values: Vec<f64> = (0..3 * 2 * 2 * 5).map(|i| (i as f64 * 0.3).sin()).collect();
+    let mut sink: NpySink<f64> = NpySink::new(input).expect("Error");
+    sink.shape = Some(vec![3, 2, 2, 5]);
+    sink.endian = pzip::endian::Endian::Big;
+    sink.put_all(&values).expect("Error");
+    sink.flush().expect("Error");
+
+    let encoded = pzip(&["-c", "-p", "lorenz", input, compressed]);
+    let decoded = pzip(&["-d", "-t", "f64", compressed, output]);
+    let verified = pzip(&["verify", input]);
+    let mut source: NpySource<f64> = NpySource::new(output).expect("Error");
+    source.load().expect("Error");
+    for file in &[input, compressed, output] {
+        std::fs::remove_file(file).expect("Error");
+    }
+
+    assert!(encoded.status.success(), "{}", String::from_utf8_lossy(&encoded.stderr));
+    assert!(decoded.status.success());
+    assert!(verified.status.success());
+    assert_eq!(source.header.shape, vec![3, 2, 2, 5]);
+    assert_eq!(source.header.endian, pzip::endian::Endian::Big);
+    assert_eq!(source.data, values);
+}
+
+#[test]
+fn pipes_through_stdin_and_stdout() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let pipe = |args: &[&str], input: &[u8]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_pzip"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Could not run pzip");
+        child.stdin.take().expect("Error").write_all(input).expect("Error");
+        let output = child.wait_with_output().expect("Error");
+        assert!(output.status.success());
+        output.stdout
+    };
+    let data: Vec<u8> = (0..3 * 4 * 5).flat_map(|i| (i as f32 * 0.25).to_le_bytes().to_vec()).collect();
+    let compressed = pipe(&["-c", "--preset", "climate-f32", "-s", "3x4x5", "-", "-"], &data);
+    assert_eq!(&compressed[..4], b"PZIP");
+    assert_eq!(pipe(&["-d", "-", "-"], &compressed), data);
+
+    // tiles need all values in memory
+    let tiled = pipe(&["-c", "--tile", "1x2x2", "-s", "3x4x5", "-", "-"], &data);
+    assert_eq!(pipe(&["-d", "-", "-"], &tiled), data);
+
+    // the type is read from the header
+    let doubles: Vec<u8> = (0..3 * 4 * 5).flat_map(|i| (f64::from(i) * 0.25).to_le_bytes().to_vec()).collect();
+    let compressed = pipe(&["-c", "-t", "f64", "-s", "3x4x5", "-", "-"], &doubles);
+    assert_eq!(pipe(&["-d", "-", "-"], &compressed), doubles);
+
+    // streams are compressed while stdin is read and fail if it does not match the shape
+    let failing = |args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_pzip"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Could not run pzip");
+        child.stdin.take().expect("Error").write_all(&data).expect("Error");
+        let output = child.wait_with_output().expect("Error");
+        assert_eq!(output.status.code(), Some(1));
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+    assert!(failing(&["-c", "-s", "3x4x6", "-", "-"]).contains("Input ended after 60 of 72 values"));
+    let compressed = temp("cli_pipe_longer.pzip");
+    assert!(failing(&["-c", "-s", "1x2x3", "-", &compressed]).contains("more than the 6 values"));
+    assert!(!std::path::Path::new(&compressed).exists());
+}
+
+#[test]
+fn several_variables_in_one_file() {
+    use pzip::archive::Writer;
+    use pzip::{Options, Shape};
+
+    let input = &temp("cli_variables.pzip");
+    let outdir = &temp("cli_variables");
+    let qv: Vec<f32> = (0..2 * 3 * 4).map(|i| 0.002 * i as f32).collect();
+    let u: Vec<f64> = (0..10).map(|i| -5.0 + f64::from(i)).collect();
+    let mut writer = Writer::new(Vec::new()).expect("Error");
+    writer.add_values("qv", &qv, Shape { z: 2, y: 3, x: 4 }, &Options::default()).expect("Error");
+    writer.add_values("u", &u, Shape { z: 1, y: 1, x: 10 }, &Options::default()).expect("Error");
+    std::fs::write(input, writer.finish().expect("Error")).expect("Error");
+
+    let result = pzip(&["info", input]);
+    let text = String::from_utf8_lossy(&result.stdout);
+    assert!(result.status.success());
+    assert!(text.contains("variables:   2"));
+    assert!(text.contains("variable:    u\ntype:        F64"));
+
+    assert!(pzip(&["-d", input, outdir]).status.success());
+    let expected: Vec<u8> = u.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+    assert_eq!(std::fs::read(format!("{}/u.raw", outdir)).expect("Error"), expected);
+    assert_eq!(std::fs::read(format!("{}/qv.raw", outdir)).expect("Error").len(), 96);
+    std::fs::remove_file(input).expect("Error");
+    std::fs::remove_dir_all(outdir).expect("Error");
+}
+
+#[test]
+fn attributes_are_stored_with_the_data() {
+    let input = &temp("cli_attributes.raw");
+    let compressed = &temp("cli_attributes.pzip");
+    let data: Vec<u8> = (0..2 * 2 * 2).flat_map(|i| (i as f32).to_le_bytes().to_vec()).collect();
+    std::fs::write(input, &data).expect("Error");
+    let args = ["-c", "-s", "2x2x2", "-a", "units=K", "--attribute", "long_name=air temperature", input, compressed];
+    assert!(pzip(&args).status.success());
+
+    let result = pzip(&["info", compressed]);
+    std::fs::remove_file(input).expect("Error");
+    std::fs::remove_file(compressed).expect("Error");
+    let text = String::from_utf8_lossy(&result.stdout);
+    assert!(text.contains("attributes:  3"));
+    assert!(text.contains(&format!("filename = {}_cli_attributes.raw", std::process::id())));
+    assert!(text.contains("long_name = air temperature"));
+    assert!(text.contains("units = K"));
+}
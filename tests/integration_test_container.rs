@@ -0,0 +1,146 @@
+use pzip::order::Order;
+use pzip::position::Position as Coordinate;
+use pzip::predictors::predictors;
+use pzip::testing::{CompressedFile, Sink};
+use pzip::transform::{Byte, Compact, Inter, Intra};
+use pzip::{Pipeline, Setup};
+
+/// Path of `name` in the temporary directory, unique to this test run
+fn temp(name: &str) -> String {
+    std::env::temp_dir().join(format!("{}_{}", std::process::id(), name)).to_string_lossy().into_owned()
+}
+
+#[test]
+fn tiled_container_roundtrip_f32() {
+    let input = temp("container_input_f32.raw");
+    let output = temp("container_output_f32.pzip");
+    let shape = Coordinate { z: 3, y: 20, x: 30 };
+    let data: Vec<f32> = (0..1800).map(|i| (i as f32 * 0.01).sin() * 100.0).collect();
+
+    let mut sink: Sink<f32> = Sink::new(&input).expect("Error");
+    sink.put_all(&data).expect("Writing unsuccessfull");
+    sink.flush().expect("Writing unsuccessfull");
+
+    let pipeline = Pipeline {
+        inter: Inter::Ordered,
+        intra: Intra::Gray,
+        byte: Byte::MonoGray,
+        compact: Compact::Untouched,
+        ring: false,
+        order: Order::Morton,
+        tile: Some(Coordinate { z: 2, y: 8, x: 16 }),
+    };
+    let mut setup = Setup::<f32>::new(&input, shape, predictors::get_lorenz_f32()).expect("Error");
+    setup.compress(&pipeline, &output).expect("Compression failed");
+
+    let (result, result_shape) = pzip::decompress_file::<f32>(&output).expect("Decompression failed");
+    std::fs::remove_file(&input).expect("Error");
+    std::fs::remove_file(&output).expect("Error");
+
+    assert_eq!(result_shape, shape);
+    assert_eq!(result, data);
+}
+
+#[test]
+fn decode_sub_volume_f64() {
+    let input = temp("container_input_f64.raw");
+    let output = temp("container_output_f64.pzip");
+    let shape = Coordinate { z: 4, y: 10, x: 12 };
+    let data: Vec<f64> = (0..480).map(|i| (i as f64).sqrt()).collect();
+
+    let mut sink: Sink<f64> = Sink::new(&input).expect("Error");
+    sink.put_all(&data).expect("Writing unsuccessfull");
+    sink.flush().expect("Writing unsuccessfull");
+
+    let pipeline = Pipeline {
+        tile: Some(Coordinate { z: 1, y: 4, x: 5 }),
+        ..Pipeline::default()
+    };
+    let mut setup = Setup::<f64>::new(&input, shape, predictors::get_last_value_f64()).expect("Error");
+    setup.compress(&pipeline, &output).expect("Compression failed");
+
+    let start = Coordinate { z: 2, y: 3, x: 4 };
+    let size = Coordinate { z: 1, y: 5, x: 7 };
+    let result = pzip::decompress_region::<f64>(&output, start, size).expect("Decompression failed");
+    let outside = pzip::decompress_region::<f64>(&output, start, Coordinate { z: 3, y: 1, x: 1 });
+    let overflow = pzip::decompress_region::<f64>(&output, Coordinate { z: 0, y: 0, x: i32::MAX }, Coordinate { z: 1, y: 1, x: 1 });
+    std::fs::remove_file(&input).expect("Error");
+    std::fs::remove_file(&output).expect("Error");
+
+    let mut expected = Vec::new();
+    for y in 3..8 {
+        for x in 4..11 {
+            expected.push(data[(2 * 10 + y) * 12 + x]);
+        }
+    }
+    assert_eq!(result, expected);
+    assert!(outside.is_err());
+    assert!(matches!(overflow, Err(pzip::Error::InvalidInput(_))));
+}
+
+#[test]
+fn compress_slices_in_memory() {
+    use pzip::config::Predictor;
+    use pzip::{Options, Shape};
+
+    let shape = Shape { z: 4, y: 10, x: 12 };
+    let data: Vec<f32> = (0..480).map(|i| 273.15 + (i as f32 * 0.05).cos() * 20.0).collect();
+    let options = Options {
+        predictor: Predictor::Lorenz,
+        pipeline: Pipeline { inter: Inter::Ordered, intra: Intra::Gray, ..Pipeline::default() },
+        ..Options::default()
+    };
+    let compressed = pzip::compress(&data, shape, &options).expect("Compression failed");
+    let (result, result_shape) = pzip::decompress::<f32>(&compressed).expect("Decompression failed");
+    assert_eq!(result_shape, shape);
+    assert_eq!(result, data);
+    assert!(pzip::decompress::<f64>(&compressed).is_err());
+
+    let mut options = Options::default();
+    options.attributes.insert("units".to_string(), "K".to_string());
+    let compressed = pzip::compress(&data, shape, &options).expect("Compression failed");
+    let (_, header) = pzip::decompress_from::<f32, _>(&mut &compressed[..]).expect("Decompression failed");
+    assert_eq!(header.attributes, options.attributes);
+
+    let data: Vec<f64> = data.iter().map(|&v| f64::from(v) / 3.0).collect();
+    let compressed = pzip::compress(&data, shape, &Options::default()).expect("Compression failed");
+    assert_eq!(pzip::decompress::<f64>(&compressed).expect("Decompression failed"), (data.clone(), shape));
+
+    assert!(pzip::compress(&data[1..], shape, &Options::default()).is_err());
+    let nolzc = Options { pipeline: Pipeline { compact: Compact::NoLZC, ..Pipeline::default() }, ..Options::default() };
+    let data: Vec<f32> = data.iter().map(|&v| v as f32).collect();
+    assert!(matches!(pzip::compress(&data, shape, &nolzc), Err(pzip::error::Error::InvalidInput(_))));
+    assert!(pzip::decompress::<f64>(&compressed[..compressed.len() - 1]).is_err());
+}
+
+#[test]
+fn detect_corrupted_data() {
+    use pzip::error::Error;
+    use pzip::{Options, Shape};
+
+    let shape = Shape { z: 2, y: 8, x: 8 };
+    let data: Vec<f64> = (0..128).map(|i| 1000.0 + f64::from(i).sqrt()).collect();
+    let compressed = pzip::compress(&data, shape, &Options::default()).expect("Compression failed");
+
+    let mut damaged = compressed.clone();
+    let middle = compressed.len() - 4 - 9 - 100;
+    damaged[middle] ^= 0x04;
+    let result = pzip::decompress::<f64>(&damaged);
+    assert!(matches!(result, Err(Error::Corrupt(ref msg)) if msg.contains("chunk 0")));
+
+    // a wrong checksum of the values is reported after decoding
+    let mut damaged = compressed.clone();
+    let last = damaged.len() - 1;
+    damaged[last] ^= 0x01;
+    let result = pzip::decompress::<f64>(&damaged);
+    assert!(matches!(result, Err(Error::Corrupt(ref msg)) if msg.contains("decompressed data")));
+
+    let output = &temp("container_corrupted.pzip");
+    let mut damaged = compressed.clone();
+    damaged[middle] ^= 0x04;
+    std::fs::write(output, &damaged).expect("Error");
+    let start = Coordinate { x: 0, y: 0, z: 0 };
+    let region = Coordinate { x: 2, y: 2, z: 1 };
+    assert!(matches!(pzip::decompress_region::<f64>(output, start, region), Err(Error::Corrupt(_))));
+    std::fs::remove_file(output).expect("Error");
+}
@@ -5,11 +5,36 @@ use pzip::transform::{Byte, Compact, Inter, Intra};
 use pzip::{Setup};
 use pzip::predictors::predictors;
 
+/// Path of `name` in the temporary directory, unique to this test run
+fn temp(name: &str) -> String {
+    std::env::temp_dir().join(format!("{}_{}", std::process::id(), name)).to_string_lossy().into_owned()
+}
+
+#[test]
+fn residuals_need_the_whole_shape() {
+    let (input, output) = (temp("residuals_short.raw"), temp("residuals_short.out"));
+    let shape = Coordinate { z: 1, y: 2, x: 3 };
+
+    let values: Vec<u8> = (0..5).flat_map(|i| (i as f32).to_le_bytes().to_vec()).collect();
+    std::fs::write(&input, &values).expect("Error");
+    let mut setup = Setup::<f32>::new(&input, shape, predictors::get_last_value_f32()).expect("Error");
+    let result = setup.write(Inter::Untouched, Intra::Untouched, Byte::Untouched, Compact::Untouched, false, &output);
+    assert!(matches!(result, Err(pzip::Error::SizeMismatch(_))));
+
+    let values: Vec<u8> = (0..7).flat_map(|i| f64::from(i).to_le_bytes().to_vec()).collect();
+    std::fs::write(&input, &values).expect("Error");
+    let mut setup = Setup::<f64>::new(&input, shape, predictors::get_last_value_f64()).expect("Error");
+    let result = setup.write(Inter::Untouched, Intra::Untouched, Byte::Untouched, &output);
+    std::fs::remove_file(&input).expect("Error");
+    assert!(matches!(result, Err(pzip::Error::SizeMismatch(_))));
+    assert!(!std::path::Path::new(&output).exists());
+}
+
 #[test]
 #[ignore]
 fn compression_using_last_value_all_once_f64_raw() {
     let input = String::from("/home/ucyo/rust/pzip/data/icon.ml.qv.f64.little.4x90x351x901_0.raw");
-    let output = String::from("/tmp/testing64.pzip");
+    let output = temp("testing64.pzip");
     let shape = Coordinate {
         z: 90,
         y: 351,
@@ -17,11 +42,11 @@ fn compression_using_last_value_all_once_f64_raw() {
     };
     let predictor = predictors::get_last_value_f64();
 
-    let mut prediction = Setup::<f64>::new(&input, shape, predictor);
-    prediction.write(Inter::Untouched, Intra::Untouched, Byte::Untouched, &output);
+    let mut prediction = Setup::<f64>::new(&input, shape, predictor).expect("Error opening the input");
+    prediction.write(Inter::Untouched, Intra::Untouched, Byte::Untouched, &output).expect("Error writing the output");
 
-    let origin = pzip::testing::read_first_k_f64(&input, 760);
-    let outcome = pzip::testing::read_first_k_f64(&output, 760);
+    let origin = pzip::testing::read_first_k_f64(&input, 760).expect("Error reading the values");
+    let outcome = pzip::testing::read_first_k_f64(&output, 760).expect("Error reading the values");
 
     for i in 362..623 {
         println!("{} {} {}", i, origin[i - 1], outcome[i]);
@@ -39,7 +64,7 @@ fn compression_using_last_value_all_once_f64_raw() {
 #[ignore]
 fn compression_using_last_value_all_once_f32_raw() {
     let input = String::from("/home/ucyo/rust/pzip/data/icon.ml.qv.f32.little.4x90x351x901_0.raw");
-    let output = String::from("/tmp/testing32.pzip");
+    let output = temp("testing32.pzip");
     let shape = Coordinate {
         z: 90,
         y: 351,
@@ -47,7 +72,7 @@ fn compression_using_last_value_all_once_f32_raw() {
     };
     let predictor = predictors::get_last_value_f32();
 
-    let mut prediction = Setup::<f32>::new(&input, shape, predictor);
+    let mut prediction = Setup::<f32>::new(&input, shape, predictor).expect("Error opening the input");
     prediction.write(
         Inter::Untouched,
         Intra::Untouched,
@@ -55,10 +80,10 @@ fn compression_using_last_value_all_once_f32_raw() {
         Compact::Untouched,
         false,
         &output,
-    );
+    ).expect("Error writing the output");
 
-    let origin = pzip::testing::read_first_k_f32(&input, 760);
-    let outcome = pzip::testing::read_first_k_f32(&output, 760);
+    let origin = pzip::testing::read_first_k_f32(&input, 760).expect("Error reading the values");
+    let outcome = pzip::testing::read_first_k_f32(&output, 760).expect("Error reading the values");
 
     for i in 362..623 {
         println!("{} {} {}", i, origin[i - 1], outcome[i]);